@@ -0,0 +1,7 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate fat32;
+
+fuzz_target!(|data: &[u8]| {
+    fat32::fuzz::exercise_image(data);
+});
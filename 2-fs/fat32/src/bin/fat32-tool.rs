@@ -0,0 +1,611 @@
+//! Inspects and edits FAT disk images on the host.
+//!
+//! Run `fat32-tool --help` for the list of commands.
+
+extern crate fat32;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use fat32::MasterBootRecord;
+use fat32::device::FileBlockDevice;
+use fat32::gpt::GuidPartitionTable;
+use fat32::traits::{Dir, Entry, File, FileSystem, Metadata, Timestamp};
+use fat32::vfat::{self, BiosParameterBlock, CodePage, Shared, VFat};
+
+const USAGE: &str = "\
+usage: fat32-tool [options] <image> <command> [arguments]
+
+options:
+    -s, --sector-size <bytes>   sector size of the image (default: 512)
+    -p, --partition <n>         use MBR partition n (0 to 3)
+    -g, --gpt <n>               use GPT partition entry n
+    -r, --raw                   use the whole image as the volume
+    -c, --code-page <n>         OEM code page of short names, 437 or 850
+                                (default: 437)
+
+commands:
+    ls [-l] [path]              list a directory
+    tree [path]                 list a directory recursively
+    cat <path>                  write a file to standard output
+    stat <path>                 show the metadata of a file or directory
+    cp <from> <to>              copy a file in or out of the image; paths
+                                in the image are prefixed with '::'
+    mkdir [-p] <path>           create a directory
+    rm [-r] <path>              remove a file or directory
+    mv <from> <to>              rename or move a file or directory
+    df                          show the size and free space of the volume
+    info                        dump the partition table and boot sector
+    chain <path>                print the cluster chain of a file or directory
+    frag [-v]                   report how fragmented files and free space are;
+                                -v lists every fragmented file
+    defrag <path>               move a file or directory into contiguous
+                                clusters
+    lsdel [path]                list the deleted entries of a directory
+    undelete <dir> <n> [name]   restore deleted entry n of a directory as
+                                name (default: its recovered name)
+
+Without -p, -g or -r, the first FAT partition is used, or the whole image if
+it has no partition table.";
+
+/// Prefix marking a path inside of the image in `cp`.
+const IMAGE_PREFIX: &str = "::";
+
+/// Which part of the image holds the volume.
+#[derive(Debug, Copy, Clone)]
+enum Volume {
+    Auto,
+    Mbr(usize),
+    Gpt(usize),
+    Raw,
+}
+
+#[derive(Debug)]
+struct Options {
+    sector_size: u64,
+    volume: Volume,
+    code_page: CodePage,
+    image: PathBuf,
+    command: String,
+    args: Vec<String>,
+}
+
+type Result<T> = ::std::result::Result<T, String>;
+
+fn parse_args(mut args: Vec<String>) -> Result<Options> {
+    let mut sector_size = 512;
+    let mut volume = Volume::Auto;
+    let mut code_page = CodePage::default();
+
+    args.reverse();
+    let number = |args: &mut Vec<String>, option: &str| -> Result<u64> {
+        args.pop()
+            .and_then(|value| value.parse().ok())
+            .ok_or(format!("{} expects a number", option))
+    };
+    let image = loop {
+        let arg = args.pop().ok_or("missing image")?;
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-s" | "--sector-size" => sector_size = number(&mut args, &arg)?,
+            "-p" | "--partition" => volume = Volume::Mbr(number(&mut args, &arg)? as usize),
+            "-g" | "--gpt" => volume = Volume::Gpt(number(&mut args, &arg)? as usize),
+            "-r" | "--raw" => volume = Volume::Raw,
+            "-c" | "--code-page" => {
+                code_page = args.pop()
+                    .and_then(|value| value.parse().ok())
+                    .and_then(CodePage::from_number)
+                    .ok_or("--code-page expects 437 or 850")?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => break PathBuf::from(arg),
+        }
+    };
+    let command = args.pop().ok_or("missing command")?;
+    args.reverse();
+
+    Ok(Options { sector_size, volume, code_page, image, command, args })
+}
+
+/// Splits the flags of a command, such as `-l`, from its other arguments.
+fn split_flags(args: &[String]) -> (Vec<&str>, Vec<&str>) {
+    args.iter().map(|arg| &arg[..]).partition(|arg| arg.starts_with('-') && arg.len() > 1)
+}
+
+/// Returns the flags of a command if all of them are in `allowed`, and its
+/// `count` positional arguments, the last of which default to `defaults`.
+fn command_args<'a>(
+    options: &'a Options,
+    allowed: &[&str],
+    count: usize,
+    defaults: &[&'a str],
+) -> Result<(Vec<&'a str>, Vec<&'a str>)> {
+    let (flags, mut args) = split_flags(&options.args);
+    if let Some(flag) = flags.iter().find(|flag| !allowed.contains(flag)) {
+        return Err(format!("{}: unknown flag '{}'", options.command, flag));
+    }
+    if args.len() < count && args.len() + defaults.len() >= count {
+        let missing = count - args.len();
+        args.extend_from_slice(&defaults[defaults.len() - missing..]);
+    }
+    if args.len() != count {
+        return Err(format!("{}: expected {} argument(s); see --help", options.command, count));
+    }
+    Ok((flags, args))
+}
+
+fn open_device(options: &Options, writable: bool) -> Result<FileBlockDevice> {
+    let device = if writable {
+        FileBlockDevice::open(&options.image, options.sector_size)
+    } else {
+        FileBlockDevice::open_read_only(&options.image, options.sector_size)
+    };
+    device.map_err(|e| format!("{}: {}", options.image.display(), e))
+}
+
+fn mount(options: &Options, writable: bool) -> Result<Shared<VFat>> {
+    let device = open_device(options, writable)?;
+    let vfat = match options.volume {
+        Volume::Auto => VFat::from(device),
+        Volume::Mbr(index) => VFat::from_partition(device, index),
+        Volume::Gpt(index) => VFat::from_gpt_partition(device, index),
+        Volume::Raw => VFat::from_raw(device),
+    };
+    let vfat = vfat.map_err(|e| format!("{}: cannot mount: {:?}", options.image.display(), e))?;
+    vfat.borrow_mut().set_code_page(options.code_page);
+    Ok(vfat)
+}
+
+fn flush(vfat: &Shared<VFat>) -> Result<()> {
+    vfat.borrow_mut().flush().map_err(|e| format!("cannot write image: {}", e))
+}
+
+fn context<T>(result: io::Result<T>, path: &str) -> Result<T> {
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+fn format_timestamp<T: Timestamp>(ts: T) -> String {
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            ts.year(), ts.month(), ts.day(), ts.hour(), ts.minute(), ts.second())
+}
+
+/// Returns the names of the attributes set in `metadata`.
+fn attributes<M: Metadata>(metadata: &M) -> Vec<&'static str> {
+    let flags = [
+        (metadata.read_only(), "read-only"),
+        (metadata.hidden(), "hidden"),
+        (metadata.system(), "system"),
+        (metadata.archive(), "archive"),
+        (metadata.is_volume_label(), "volume label"),
+    ];
+    flags.iter().filter(|&&(set, _)| set).map(|&(_, name)| name).collect()
+}
+
+/// Returns the entries of the directory at `path` sorted by name, without
+/// `.` and `..`.
+fn sorted_entries(vfat: &Shared<VFat>, path: &str) -> Result<Vec<vfat::Entry>> {
+    let dir = context(vfat.open_dir(path), path)?;
+    let mut iter = context(dir.entries(), path)?;
+    let mut entries: Vec<_> = iter.by_ref()
+        .filter(|entry| entry.name() != "." && entry.name() != "..")
+        .collect();
+    context(iter.take_error(), path)?;
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+    Ok(entries)
+}
+
+fn ls(options: &Options) -> Result<()> {
+    let (flags, args) = command_args(options, &["-l"], 1, &["/"])?;
+    let vfat = mount(options, false)?;
+    for entry in sorted_entries(&vfat, args[0])? {
+        if flags.is_empty() {
+            println!("{}", entry.name());
+            continue;
+        }
+
+        let metadata = entry.metadata();
+        println!("{}{}{}{}{} {:>10} {} {}",
+                 if entry.is_dir() { 'd' } else if metadata.is_volume_label() { 'v' } else { '-' },
+                 if metadata.read_only() { 'r' } else { '-' },
+                 if metadata.hidden() { 'h' } else { '-' },
+                 if metadata.system() { 's' } else { '-' },
+                 if metadata.archive() { 'a' } else { '-' },
+                 metadata.len(),
+                 format_timestamp(metadata.modified()),
+                 entry.name());
+    }
+    Ok(())
+}
+
+fn tree(options: &Options) -> Result<()> {
+    fn print_tree(vfat: &Shared<VFat>, path: &Path, depth: usize) -> Result<()> {
+        let path_str = path.to_string_lossy();
+        for entry in sorted_entries(vfat, &path_str)? {
+            let suffix = if entry.is_dir() { "/" } else { "" };
+            println!("{:indent$}{}{}", "", entry.name(), suffix, indent = depth * 4);
+            if entry.is_dir() {
+                print_tree(vfat, &path.join(entry.name()), depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    let (_, args) = command_args(options, &[], 1, &["/"])?;
+    let vfat = mount(options, false)?;
+    println!("{}", args[0]);
+    print_tree(&vfat, Path::new(args[0]), 1)
+}
+
+fn cat(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 1, &[])?;
+    let vfat = mount(options, false)?;
+    let mut file = context(vfat.open_file(args[0]), args[0])?;
+    let stdout = io::stdout();
+    context(io::copy(&mut file, &mut stdout.lock()), args[0])?;
+    Ok(())
+}
+
+fn stat(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 1, &[])?;
+    let vfat = mount(options, false)?;
+    let entry = context(vfat.open(args[0]), args[0])?;
+    let clusters = context(vfat.cluster_chain(args[0]), args[0])?;
+    let metadata = entry.metadata();
+    let attributes = attributes(metadata);
+
+    println!("    Name: {}", entry.name());
+    println!("    Type: {}", if entry.is_dir() { "directory" } else { "file" });
+    println!("    Size: {}", metadata.len());
+    println!("Clusters: {} (first: {})", clusters.len(),
+             clusters.first().map(|c| c.to_string()).unwrap_or("none".to_string()));
+    println!("   Attrs: {}", if attributes.is_empty() { "none".to_string() } else { attributes.join(", ") });
+    println!(" Created: {}", format_timestamp(metadata.created()));
+    println!("Modified: {}", format_timestamp(metadata.modified()));
+    println!("Accessed: {}", &format_timestamp(metadata.accessed())[..10]);
+    Ok(())
+}
+
+/// The direction of a copy by `cp`, with the `::` prefix removed from the
+/// path in the image.
+#[derive(Debug, PartialEq)]
+enum Transfer<'a> {
+    /// From a file in the image to a host file.
+    Out { from: &'a str, to: &'a str },
+    /// From a host file into the image.
+    In { from: &'a str, to: &'a str },
+}
+
+/// Determines which of `from` and `to` is in the image.
+fn transfer<'a>(from: &'a str, to: &'a str) -> Result<Transfer<'a>> {
+    match (from.starts_with(IMAGE_PREFIX), to.starts_with(IMAGE_PREFIX)) {
+        (true, false) => Ok(Transfer::Out { from: &from[IMAGE_PREFIX.len()..], to }),
+        (false, true) => Ok(Transfer::In { from, to: &to[IMAGE_PREFIX.len()..] }),
+        _ => Err(format!("cp: exactly one path must be in the image (prefixed with '{}')", IMAGE_PREFIX)),
+    }
+}
+
+fn cp(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 2, &[])?;
+    match transfer(args[0], args[1])? {
+        Transfer::Out { from, to } => {
+            let vfat = mount(options, false)?;
+            let mut file = context(vfat.open_file(from), from)?;
+            let mut host = context(fs::File::create(to), to)?;
+            context(io::copy(&mut file, &mut host), to)?;
+            Ok(())
+        }
+        Transfer::In { from, to } => {
+            let mut to = PathBuf::from(to);
+            let mut host = context(fs::File::open(from), from)?;
+            let vfat = mount(options, true)?;
+            if vfat.open_dir(&to).is_ok() {
+                let name = Path::new(from).file_name().ok_or(format!("{}: not a file", from))?;
+                to.push(name);
+            }
+            let to = to.to_string_lossy().into_owned();
+            if vfat.open_file(&to).is_ok() {
+                context(vfat.remove(&to, false), &to)?;
+            }
+
+            let mut file = context(vfat.create_file(&to), &to)?;
+            context(io::copy(&mut host, &mut file), &to)?;
+            context(file.sync(), &to)?;
+            flush(&vfat)
+        }
+    }
+}
+
+fn mkdir(options: &Options) -> Result<()> {
+    let (flags, args) = command_args(options, &["-p"], 1, &[])?;
+    let vfat = mount(options, true)?;
+    context(vfat.create_dir(args[0], !flags.is_empty()), args[0])?;
+    flush(&vfat)
+}
+
+fn rm(options: &Options) -> Result<()> {
+    let (flags, args) = command_args(options, &["-r"], 1, &[])?;
+    let vfat = mount(options, true)?;
+    context(vfat.remove(args[0], !flags.is_empty()), args[0])?;
+    flush(&vfat)
+}
+
+fn mv(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 2, &[])?;
+    let vfat = mount(options, true)?;
+    context(vfat.rename(args[0], args[1]), args[0])?;
+    flush(&vfat)
+}
+
+fn df(options: &Options) -> Result<()> {
+    command_args(options, &[], 0, &[])?;
+    let vfat = mount(options, false)?;
+    let mut fs = vfat.borrow_mut();
+    let total = fs.total_space();
+    let free = context(fs.free_space(), "free space")?;
+    let used = total - free;
+    let percent = if total == 0 { 0 } else { used * 100 / total };
+
+    println!("{:>14} {:>14} {:>14} {:>5}", "Size", "Used", "Free", "Use%");
+    println!("{:>14} {:>14} {:>14} {:>4}%", total, used, free, percent);
+    Ok(())
+}
+
+fn info(options: &Options) -> Result<()> {
+    command_args(options, &[], 0, &[])?;
+    let mut device = open_device(options, false)?;
+
+    match MasterBootRecord::from(&mut device) {
+        Ok(mbr) => {
+            println!("MBR partitions:");
+            for i in 0..4 {
+                let partition = mbr.get_partition(i);
+                if partition.partition_type != 0 {
+                    println!("  {}: type {:#04x}, start {}, {} sectors",
+                             i, partition.partition_type, { partition.relative_sector }, partition.sectors());
+                }
+            }
+        }
+        Err(e) => println!("No MBR: {:?}", e),
+    }
+
+    if let Ok(gpt) = GuidPartitionTable::from(&mut device) {
+        println!("GPT (disk {}{}):", gpt.disk_guid, if gpt.used_backup { ", from backup" } else { "" });
+        for (i, partition) in gpt.partitions() {
+            println!("  {}: type {}, start {}, {} sectors, name '{}'",
+                     i, partition.type_guid, partition.first_lba, partition.sectors(), partition.name);
+        }
+    }
+
+    let vfat = mount(options, false)?;
+    let mut fs = vfat.borrow_mut();
+    let start = fs.volume_start();
+    let bpb = BiosParameterBlock::from(&mut device, start)
+        .map_err(|e| format!("cannot read boot sector: {:?}", e))?;
+    println!("Volume at sector {}: {:#?}", start, bpb);
+    let label = context(fs.volume_label(), "volume label")?;
+    println!("Volume label: {}", label.unwrap_or("none".to_string()));
+    println!("Volume ID: {}", fs.volume_id().map(|id| format!("{:04X}-{:04X}", id >> 16, id & 0xFFFF))
+             .unwrap_or("none".to_string()));
+    println!("FAT type: {:?}", fs.fat_type());
+    println!("Bytes per cluster: {}", fs.bytes_per_cluster());
+    println!("FAT copies: {}, {}", fs.number_of_fats(),
+             if fs.is_mirroring() { "mirrored".to_string() } else { format!("active FAT {}", fs.active_fat()) });
+    println!("Root directory cluster: {}", fs.root_dir_cluster.id());
+    println!("Size: {} bytes, free: {} bytes", fs.total_space(), context(fs.free_space(), "free space")?);
+    Ok(())
+}
+
+fn chain(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 1, &[])?;
+    let vfat = mount(options, false)?;
+    let clusters = context(vfat.cluster_chain(args[0]), args[0])?;
+
+    // Print runs of consecutive clusters as ranges.
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for &cluster in &clusters {
+        match runs.last_mut() {
+            Some(run) if run.1 + 1 == cluster => run.1 = cluster,
+            _ => runs.push((cluster, cluster)),
+        }
+    }
+    let runs: Vec<String> = runs.iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+        .collect();
+    println!("{} cluster(s): {}", clusters.len(), runs.join(" "));
+    Ok(())
+}
+
+fn frag(options: &Options) -> Result<()> {
+    let (flags, _) = command_args(options, &["-v"], 0, &[])?;
+    let vfat = mount(options, false)?;
+    let report = context(vfat::fragmentation(&vfat), "fragmentation")?;
+    let fragmented = report.fragmented();
+    if !flags.is_empty() {
+        for entry in &fragmented {
+            println!("{:>6} fragments {:>8} clusters {}", entry.fragments, entry.clusters, entry.path);
+        }
+    }
+    println!("{} of {} entries fragmented ({}%)", fragmented.len(), report.entries.len(), report.fragmented_percent());
+    println!("{} free runs, largest {} clusters", report.free_runs, report.largest_free_run);
+    Ok(())
+}
+
+fn defrag(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 1, &[])?;
+    let vfat = mount(options, true)?;
+    let fragments = context(vfat::defragment(&vfat, args[0]), args[0])?;
+    if fragments > 1 {
+        println!("{}: moved {} fragments into one", args[0], fragments);
+    } else {
+        println!("{}: already contiguous", args[0]);
+    }
+    flush(&vfat)
+}
+
+fn lsdel(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 1, &["/"])?;
+    let vfat = mount(options, false)?;
+    let dir = context(vfat.open_dir(args[0]), args[0])?;
+    for (i, deleted) in context(dir.deleted_entries(), args[0])?.iter().enumerate() {
+        println!("{:>4} {} {:>10} {} {}",
+                 i,
+                 if deleted.metadata.is_dir() { 'd' } else { '-' },
+                 deleted.metadata.len(),
+                 if deleted.recoverable { "recoverable  " } else { "unrecoverable" },
+                 deleted.name);
+    }
+    Ok(())
+}
+
+fn undelete(options: &Options) -> Result<()> {
+    let (_, args) = command_args(options, &[], 3, &[""])?;
+    let index: usize = args[1].parse().map_err(|_| format!("undelete: invalid entry number '{}'", args[1]))?;
+    let vfat = mount(options, true)?;
+    let dir = context(vfat.open_dir(args[0]), args[0])?;
+    let deleted = context(dir.deleted_entries(), args[0])?;
+    let deleted = deleted.get(index).ok_or(format!("undelete: no deleted entry {}", index))?;
+
+    let name = match args[2] {
+        "" if deleted.name_complete => &deleted.name,
+        "" => return Err(format!("undelete: the name of '{}' is incomplete; give a name", deleted.name)),
+        name => name,
+    };
+    let path = Path::new(args[0]).join(name).to_string_lossy().into_owned();
+    context(vfat.undelete(deleted, &path), &path)?;
+    flush(&vfat)
+}
+
+fn run(options: &Options) -> Result<()> {
+    match &options.command[..] {
+        "ls" => ls(options),
+        "tree" => tree(options),
+        "cat" => cat(options),
+        "stat" => stat(options),
+        "cp" => cp(options),
+        "mkdir" => mkdir(options),
+        "rm" => rm(options),
+        "mv" => mv(options),
+        "df" => df(options),
+        "info" => info(options),
+        "chain" => chain(options),
+        "frag" => frag(options),
+        "defrag" => defrag(options),
+        "lsdel" => lsdel(options),
+        "undelete" => undelete(options),
+        command => Err(format!("unknown command '{}'; see --help", command)),
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("fat32-tool: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        let _ = io::stdout().flush();
+        eprintln!("fat32-tool: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parse_command(command: &str, args: &[&str]) -> Options {
+        let mut all = strings(&["disk.img", command]);
+        all.extend(strings(args));
+        parse_args(all).expect("valid arguments")
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(strings(&["-s", "4096", "--gpt", "1", "-c", "850", "disk.img", "ls", "-l", "/a"]))
+            .expect("valid arguments");
+        assert_eq!(options.sector_size, 4096);
+        match options.volume {
+            Volume::Gpt(1) => {}
+            volume => panic!("expected GPT partition 1, found {:?}", volume),
+        }
+        assert_eq!(options.code_page, CodePage::Cp850);
+        assert_eq!(options.image, PathBuf::from("disk.img"));
+        assert_eq!(options.command, "ls");
+        assert_eq!(options.args, strings(&["-l", "/a"]));
+
+        let options = parse_command("df", &[]);
+        assert_eq!((options.sector_size, options.code_page), (512, CodePage::default()));
+        match options.volume {
+            Volume::Auto => {}
+            volume => panic!("expected Auto, found {:?}", volume),
+        }
+
+        for &(args, error) in &[
+            (&["--verbose", "disk.img", "ls"][..], "unknown option '--verbose'"),
+            (&["-p", "x", "disk.img", "ls"][..], "-p expects a number"),
+            (&["-s"][..], "-s expects a number"),
+            (&["-c", "1252", "disk.img", "ls"][..], "--code-page expects 437 or 850"),
+            (&["-r"][..], "missing image"),
+            (&["disk.img"][..], "missing command"),
+        ] {
+            assert_eq!(parse_args(strings(args)).err().as_ref().map(|e| &e[..]), Some(error));
+        }
+    }
+
+    #[test]
+    fn test_split_flags() {
+        let args = strings(&["-l", "/a", "-", "-r"]);
+        assert_eq!(split_flags(&args), (vec!["-l", "-r"], vec!["/a", "-"]));
+    }
+
+    #[test]
+    fn test_command_args() {
+        // `ls` lists the root directory by default.
+        let ls = parse_command("ls", &["-l"]);
+        assert_eq!(command_args(&ls, &["-l"], 1, &["/"]).unwrap(), (vec!["-l"], vec!["/"]));
+        let ls = parse_command("ls", &["/a"]);
+        assert_eq!(command_args(&ls, &["-l"], 1, &["/"]).unwrap(), (vec![], vec!["/a"]));
+
+        // `undelete` uses the recovered name unless a name is given.
+        let undelete = parse_command("undelete", &["/", "2"]);
+        assert_eq!(command_args(&undelete, &[], 3, &[""]).unwrap().1, vec!["/", "2", ""]);
+        let undelete = parse_command("undelete", &["/", "2", "new.txt"]);
+        assert_eq!(command_args(&undelete, &[], 3, &[""]).unwrap().1, vec!["/", "2", "new.txt"]);
+
+        for &(command, args, error) in &[
+            ("ls", &["-a"][..], "ls: unknown flag '-a'"),
+            ("ls", &["/a", "/b"][..], "ls: expected 1 argument(s); see --help"),
+            ("undelete", &["/"][..], "undelete: expected 3 argument(s); see --help"),
+        ] {
+            let (allowed, count, defaults): (&[&str], usize, &[&str]) = match command {
+                "ls" => (&["-l"], 1, &["/"]),
+                _ => (&[], 3, &[""]),
+            };
+            let options = parse_command(command, args);
+            assert_eq!(command_args(&options, allowed, count, defaults).err(), Some(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        assert_eq!(transfer("::/a/b.txt", "b.txt"), Ok(Transfer::Out { from: "/a/b.txt", to: "b.txt" }));
+        assert_eq!(transfer("b.txt", "::/a"), Ok(Transfer::In { from: "b.txt", to: "/a" }));
+        assert_eq!(transfer("::", "out"), Ok(Transfer::Out { from: "", to: "out" }));
+        for &(from, to) in &[("a", "b"), ("::a", "::b")] {
+            assert!(transfer(from, to).unwrap_err().starts_with("cp: exactly one path"));
+        }
+    }
+}
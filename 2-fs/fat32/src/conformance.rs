@@ -0,0 +1,376 @@
+//! Checks that hold every implementation of the file system traits to the
+//! same behaviour, whatever its on-disk format.
+//!
+//! File systems are `&S` for some owner `S`, e.g. `&Shared<VFat>`. Writable
+//! file systems are checked with `check_all()`, given a function making new,
+//! empty ones; read-only ones with `check_read_only()`, given one holding
+//! the tree of `Tree` as `populate()` creates it.
+//!
+//! Names are compared ignoring case. Directory entries named `.` and `..`
+//! are ignored, as the traits leave it open whether directories list them.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use traits::{Dir, Entry, File, FileSystem, Metadata};
+
+/// The contents of the files of the tree the read-only checks expect:
+/// `/Hello.txt`, `/frag.bin`, whose last 300 bytes are zero, and
+/// `/Docs/Über.txt`.
+pub struct Tree {
+    pub hello: Vec<u8>,
+    pub frag: Vec<u8>,
+    pub uber: Vec<u8>,
+}
+
+impl Tree {
+    pub fn new() -> Tree {
+        let mut frag: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        frag.extend_from_slice(&[0; 300]);
+        Tree {
+            hello: (0..1200u32).map(|i| (i * 7) as u8).collect(),
+            frag,
+            uber: "grüße".as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Runs every check, each against a new file system made by `new_fs`.
+pub fn check_all<S, F>(new_fs: F)
+    where F: Fn() -> S, for<'a> &'a S: FileSystem
+{
+    let fs = new_fs();
+    populate(&fs);
+    check_read_only(&fs);
+
+    check_create(&new_fs());
+    check_write(&new_fs());
+    check_rename(&new_fs());
+    check_remove(&new_fs());
+}
+
+/// Creates the tree of `Tree` in the empty file system `fs`.
+pub fn populate<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let tree = Tree::new();
+    write_file(fs, "/Hello.txt", &tree.hello);
+    write_file(fs, "/frag.bin", &tree.frag);
+    fs.create_dir("/Docs", false).expect("create /Docs");
+    write_file(fs, "/Docs/Über.txt", &tree.uber);
+}
+
+/// Runs the checks that do not modify `fs`, which must hold the tree of
+/// `Tree`.
+pub fn check_read_only<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    check_open(fs);
+    check_read(fs);
+    check_seek(fs);
+}
+
+/// Returns the names of the entries of the directory at `path`, sorted.
+fn names<S>(fs: &S, path: &str) -> Vec<String>
+    where for<'a> &'a S: FileSystem
+{
+    let mut names: Vec<String> = fs.open_dir(path)
+        .expect(path)
+        .entries()
+        .expect(path)
+        .map(|entry| entry.name().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+fn read_file<S>(fs: &S, path: &str) -> Vec<u8>
+    where for<'a> &'a S: FileSystem
+{
+    let mut file = fs.open_file(path).expect(path);
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).expect(path);
+    assert_eq!(data.len() as u64, file.size(), "size of {}", path);
+    data
+}
+
+fn write_file<S>(fs: &S, path: &str, data: &[u8])
+    where for<'a> &'a S: FileSystem
+{
+    let mut file = fs.create_file(path).expect(path);
+    file.write_all(data).expect(path);
+    file.sync().expect(path);
+}
+
+fn expect_kind<T>(result: io::Result<T>, kind: io::ErrorKind, what: &str) {
+    match result {
+        Err(ref e) if e.kind() == kind => {}
+        Err(e) => panic!("{}: expected error of kind {:?} but found {:?}", what, kind, e),
+        Ok(_) => panic!("{}: expected error of kind {:?} but succeeded", what, kind),
+    }
+}
+
+fn check_open<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let root = vec!["Docs", "Hello.txt", "frag.bin"];
+    assert_eq!(names(fs, "/"), root);
+    assert_eq!(names(fs, "/Docs"), vec!["Über.txt"]);
+
+    // Names match ignoring case and keep the case they were created with.
+    assert_eq!(fs.open("/HELLO.TXT").unwrap().name(), "Hello.txt");
+    assert_eq!(fs.open("/docs/üBER.TXT").unwrap().name(), "Über.txt");
+
+    // Empty components, `.` and trailing slashes are ignored; `..` is the
+    // parent directory, and the root directory is its own parent.
+    for path in &["/Docs/", "//Docs", "/./Docs/.", "/Docs/../Docs"] {
+        assert!(fs.open(path).unwrap().is_dir(), "{}", path);
+        assert_eq!(names(fs, path), vec!["Über.txt"], "{}", path);
+    }
+    for path in &["/", "/..", "/Docs/..", "/Docs/../.."] {
+        assert_eq!(names(fs, path), root, "{}", path);
+    }
+    assert_eq!(read_file(fs, "/Docs/../Hello.txt"), Tree::new().hello);
+
+    expect_kind(fs.open("Hello.txt"), io::ErrorKind::InvalidInput, "relative path");
+    expect_kind(fs.open(""), io::ErrorKind::InvalidInput, "empty path");
+    expect_kind(fs.open("/missing"), io::ErrorKind::NotFound, "missing entry");
+    expect_kind(fs.open("/Docs/missing"), io::ErrorKind::NotFound, "missing entry in directory");
+    expect_kind(fs.open("/missing/Über.txt"), io::ErrorKind::InvalidInput, "missing directory");
+    expect_kind(fs.open("/missing/.."), io::ErrorKind::InvalidInput, "parent of missing directory");
+    expect_kind(fs.open("/Hello.txt/x"), io::ErrorKind::InvalidInput, "file as directory");
+    expect_kind(fs.open("/Hello.txt/.."), io::ErrorKind::InvalidInput, "parent of file");
+    expect_kind(fs.open_file("/Docs"), io::ErrorKind::Other, "directory as file");
+    expect_kind(fs.open_dir("/Hello.txt"), io::ErrorKind::Other, "file as directory");
+}
+
+fn check_read<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let tree = Tree::new();
+    assert_eq!(read_file(fs, "/Hello.txt"), tree.hello);
+    assert_eq!(read_file(fs, "/frag.bin"), tree.frag);
+    assert_eq!(read_file(fs, "/Docs/Über.txt"), tree.uber);
+
+    let entry = fs.open("/Hello.txt").unwrap();
+    assert!(entry.is_file() && !entry.is_dir());
+    assert!(entry.as_file().is_some() && entry.as_dir().is_none());
+    assert_eq!(entry.metadata().len(), tree.hello.len() as u64);
+    assert!(!entry.metadata().is_volume_label());
+    let entry = fs.open("/Docs").unwrap();
+    assert!(entry.is_dir() && !entry.is_file());
+    assert_eq!(entry.metadata().len(), 0);
+
+    // Reads in small pieces, across cluster and sector boundaries, read the
+    // same data.
+    let mut file = fs.open_file("/frag.bin").unwrap();
+    let mut data = Vec::new();
+    let mut buf = [0; 77];
+    loop {
+        match file.read(&mut buf).unwrap() {
+            0 => break,
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    }
+    assert_eq!(data, tree.frag);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+}
+
+fn check_seek<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let hello = Tree::new().hello;
+    let len = hello.len() as u64;
+    let mut file = fs.open_file("/Hello.txt").unwrap();
+    let mut buf = [0; 16];
+
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), len);
+    assert_eq!(file.seek(SeekFrom::Current(-16)).unwrap(), len - 16);
+    assert_eq!(file.read(&mut buf).unwrap(), 16);
+    assert_eq!(&buf[..], &hello[hello.len() - 16..]);
+    assert_eq!(file.seek(SeekFrom::Start(510)).unwrap(), 510);
+    assert_eq!(file.read(&mut buf).unwrap(), 16);
+    assert_eq!(&buf[..], &hello[510..526]);
+
+    // Positional reads leave the offset alone.
+    assert_eq!(file.read_at(&mut buf, 1000).unwrap(), 16);
+    assert_eq!(&buf[..], &hello[1000..1016]);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 526);
+    assert_eq!(file.read_at(&mut buf, len - 4).unwrap(), 4);
+    assert_eq!(file.read_at(&mut buf, len + 4).unwrap(), 0);
+
+    // Seeking past the end is allowed; seeking before the start is not and
+    // keeps the offset.
+    assert_eq!(file.seek(SeekFrom::End(100)).unwrap(), len + 100);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    file.seek(SeekFrom::Start(0)).unwrap();
+    expect_kind(file.seek(SeekFrom::Current(-1)), io::ErrorKind::InvalidInput, "seek before start");
+    expect_kind(file.seek(SeekFrom::End(-(len as i64) - 1)), io::ErrorKind::InvalidInput, "seek before start");
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 0);
+    assert_eq!(file.size(), len);
+}
+
+fn check_create<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let file = fs.create_file("/Readme").unwrap();
+    assert_eq!(file.size(), 0);
+    let dir = fs.create_dir("/a/b/c", true).unwrap();
+    assert_eq!(dir.entries().unwrap().filter(|e| e.name() != "." && e.name() != "..").count(), 0);
+    fs.create_dir("/a/d/", false).unwrap();
+    fs.create_file("/a/b/../file").unwrap();
+
+    assert_eq!(names(fs, "/"), vec!["Readme", "a"]);
+    assert_eq!(names(fs, "/a"), vec!["b", "d", "file"]);
+    assert_eq!(names(fs, "/a/b"), vec!["c"]);
+    let entry = fs.open("/README").unwrap();
+    assert_eq!(entry.name(), "Readme");
+    assert!(entry.is_file());
+    assert_eq!(entry.metadata().len(), 0);
+    assert!(fs.open("/A/B/C").unwrap().is_dir());
+    assert_eq!(read_file(fs, "/a/file"), b"");
+
+    expect_kind(fs.create_file("/readme"), io::ErrorKind::AlreadyExists, "existing file");
+    expect_kind(fs.create_file("/A"), io::ErrorKind::AlreadyExists, "existing directory");
+    expect_kind(fs.create_dir("/a/B", false), io::ErrorKind::AlreadyExists, "existing directory");
+    expect_kind(fs.create_dir("/a/b", true), io::ErrorKind::AlreadyExists, "existing directory");
+    expect_kind(fs.create_file("new"), io::ErrorKind::InvalidInput, "relative path");
+    expect_kind(fs.create_dir("new", true), io::ErrorKind::InvalidInput, "relative path");
+    expect_kind(fs.create_file("/"), io::ErrorKind::InvalidInput, "root directory");
+    expect_kind(fs.create_file("/a/.."), io::ErrorKind::InvalidInput, "no final component");
+    expect_kind(fs.create_file("/missing/new"), io::ErrorKind::InvalidInput, "missing parent");
+    expect_kind(fs.create_dir("/missing/new", false), io::ErrorKind::InvalidInput, "missing parent");
+    expect_kind(fs.create_file("/Readme/new"), io::ErrorKind::InvalidInput, "file as parent");
+    expect_kind(fs.create_dir("/Readme/new", true), io::ErrorKind::InvalidInput, "file as parent");
+    expect_kind(fs.create_dir("/Readme/new/dir", true), io::ErrorKind::InvalidInput, "file as ancestor");
+    expect_kind(fs.create_file("/a?"), io::ErrorKind::InvalidInput, "invalid name");
+    expect_kind(fs.create_file(&format!("/{}", "x".repeat(256))), io::ErrorKind::InvalidInput, "long name");
+    fs.create_file(&format!("/{}", "x".repeat(255))).unwrap();
+    assert!(fs.open("/missing").is_err());
+}
+
+fn check_write<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    let data: Vec<u8> = (0..3000u32).map(|i| (i * 13 % 256) as u8).collect();
+    write_file(fs, "/data.bin", &data);
+    assert_eq!(read_file(fs, "/data.bin"), data);
+    assert_eq!(fs.open("/data.bin").unwrap().metadata().len(), 3000);
+
+    // Overwriting in place keeps the rest of the file.
+    let mut expected = data.clone();
+    let mut file = fs.open_file("/data.bin").unwrap();
+    file.seek(SeekFrom::Start(500)).unwrap();
+    file.write_all(&[0xAB; 600]).unwrap();
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 1100);
+    assert_eq!(file.size(), 3000);
+    expected[500..1100].copy_from_slice(&[0xAB; 600]);
+
+    // Writing past the end fills the gap with zeroes.
+    assert_eq!(file.seek(SeekFrom::End(1000)).unwrap(), 4000);
+    file.write_all(b"tail").unwrap();
+    assert_eq!(file.size(), 4004);
+    expected.extend_from_slice(&[0; 1000]);
+    expected.extend_from_slice(b"tail");
+
+    // Positional writes leave the offset alone.
+    assert_eq!(file.write_at(b"head", 0).unwrap(), 4);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 4004);
+    expected[..4].copy_from_slice(b"head");
+    let mut buf = [0; 4];
+    assert_eq!(file.read_at(&mut buf, 0).unwrap(), 4);
+    assert_eq!(&buf, b"head");
+    file.sync().unwrap();
+    drop(file);
+
+    assert_eq!(read_file(fs, "/data.bin"), expected);
+    assert_eq!(fs.open("/data.bin").unwrap().metadata().len(), 4004);
+
+    // Files written in interleaved pieces keep their own data.
+    let mut one = fs.create_file("/one").unwrap();
+    let mut two = fs.create_file("/two").unwrap();
+    for i in 0..40u8 {
+        one.write_all(&[i; 100]).unwrap();
+        two.write_all(&[!i; 70]).unwrap();
+    }
+    one.sync().unwrap();
+    two.sync().unwrap();
+    let one_data: Vec<u8> = (0..40u8).flat_map(|i| vec![i; 100]).collect();
+    let two_data: Vec<u8> = (0..40u8).flat_map(|i| vec![!i; 70]).collect();
+    assert_eq!(read_file(fs, "/one"), one_data);
+    assert_eq!(read_file(fs, "/two"), two_data);
+    assert_eq!(one.write(&[]).unwrap(), 0);
+}
+
+fn check_rename<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    populate(fs);
+    let tree = Tree::new();
+
+    fs.rename("/Hello.txt", "/Greeting.txt").unwrap();
+    assert_eq!(names(fs, "/"), vec!["Docs", "Greeting.txt", "frag.bin"]);
+    assert_eq!(read_file(fs, "/greeting.txt"), tree.hello);
+    expect_kind(fs.open("/Hello.txt"), io::ErrorKind::NotFound, "renamed file");
+
+    // Renaming to the same name in another case changes only the case.
+    fs.rename("/frag.bin", "/FRAG.BIN").unwrap();
+    assert_eq!(names(fs, "/"), vec!["Docs", "FRAG.BIN", "Greeting.txt"]);
+    fs.rename("/Greeting.txt", "/GREETING.TXT/").unwrap();
+    assert_eq!(fs.open("/greeting.txt").unwrap().name(), "GREETING.TXT");
+
+    // Files and directories move between directories with their contents.
+    fs.rename("/FRAG.BIN", "/Docs/frag.bin").unwrap();
+    assert_eq!(read_file(fs, "/Docs/frag.bin"), tree.frag);
+    fs.create_dir("/New", false).unwrap();
+    fs.rename("/Docs", "/New/Moved").unwrap();
+    assert_eq!(names(fs, "/"), vec!["GREETING.TXT", "New"]);
+    assert_eq!(names(fs, "/New/Moved"), vec!["frag.bin", "Über.txt"]);
+    assert_eq!(names(fs, "/New/Moved/.."), vec!["Moved"]);
+    assert_eq!(read_file(fs, "/New/Moved/Über.txt"), tree.uber);
+    write_file(fs, "/New/Moved/after", b"after");
+    assert_eq!(read_file(fs, "/new/moved/AFTER"), b"after");
+
+    expect_kind(fs.rename("/missing", "/x"), io::ErrorKind::NotFound, "missing source");
+    expect_kind(fs.rename("/GREETING.TXT", "/New/moved"), io::ErrorKind::AlreadyExists, "existing target");
+    expect_kind(fs.rename("/New", "/greeting.txt"), io::ErrorKind::AlreadyExists, "existing target");
+    expect_kind(fs.rename("/New", "/New/Moved/Inner"), io::ErrorKind::InvalidInput, "into itself");
+    expect_kind(fs.rename("GREETING.TXT", "/x"), io::ErrorKind::InvalidInput, "relative source");
+    expect_kind(fs.rename("/GREETING.TXT", "x"), io::ErrorKind::InvalidInput, "relative target");
+    expect_kind(fs.rename("/", "/x"), io::ErrorKind::InvalidInput, "root directory");
+    expect_kind(fs.rename("/GREETING.TXT", "/missing/x"), io::ErrorKind::InvalidInput, "missing parent");
+    expect_kind(fs.rename("/GREETING.TXT", "/a*b"), io::ErrorKind::InvalidInput, "invalid name");
+    assert_eq!(names(fs, "/"), vec!["GREETING.TXT", "New"]);
+    assert_eq!(names(fs, "/New"), vec!["Moved"]);
+}
+
+fn check_remove<S>(fs: &S)
+    where for<'a> &'a S: FileSystem
+{
+    populate(fs);
+
+    fs.remove("/hello.TXT", false).unwrap();
+    assert_eq!(names(fs, "/"), vec!["Docs", "frag.bin"]);
+    expect_kind(fs.open("/Hello.txt"), io::ErrorKind::NotFound, "removed file");
+
+    // Directories are removed only with their children, even when empty.
+    fs.create_dir("/Empty", false).unwrap();
+    expect_kind(fs.remove("/Empty", false), io::ErrorKind::Other, "directory without children");
+    expect_kind(fs.remove("/Docs", false), io::ErrorKind::Other, "directory without children");
+    fs.remove("/Empty", true).unwrap();
+    fs.remove("/Docs/", true).unwrap();
+    assert_eq!(names(fs, "/"), vec!["frag.bin"]);
+    expect_kind(fs.open("/Docs/Über.txt"), io::ErrorKind::InvalidInput, "child of removed directory");
+
+    expect_kind(fs.remove("/missing", false), io::ErrorKind::NotFound, "missing entry");
+    expect_kind(fs.remove("/missing/x", false), io::ErrorKind::InvalidInput, "missing parent");
+    expect_kind(fs.remove("frag.bin", false), io::ErrorKind::InvalidInput, "relative path");
+    expect_kind(fs.remove("/", true), io::ErrorKind::InvalidInput, "root directory");
+
+    // Removed names can be used again.
+    write_file(fs, "/Hello.txt", b"again");
+    fs.create_dir("/Docs", false).unwrap();
+    assert_eq!(read_file(fs, "/Hello.txt"), b"again");
+    assert_eq!(names(fs, "/"), vec!["Docs", "Hello.txt", "frag.bin"]);
+    assert_eq!(names(fs, "/Docs"), Vec::<String>::new());
+}
@@ -0,0 +1,311 @@
+use std::cmp::min;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use traits::BlockDevice;
+
+/// Returns an error unless `sector_size` is a multiple of 512 of at least
+/// 512, as `BlockDevice` requires.
+fn check_sector_size(sector_size: u64) -> io::Result<()> {
+    if sector_size < 512 || sector_size % 512 != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid sector size"));
+    }
+    Ok(())
+}
+
+fn sector_out_of_range() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "sector out of range")
+}
+
+fn short_buffer() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "buffer shorter than a sector")
+}
+
+/// A block device backed by memory.
+///
+/// Clones share the same storage, so an image remains accessible after the
+/// device is handed to a file system, e.g. to inspect it or mount it again.
+#[derive(Debug, Clone)]
+pub struct MemBlockDevice {
+    data: Arc<Mutex<Vec<u8>>>,
+    sector_size: u64,
+}
+
+impl MemBlockDevice {
+    /// Returns a zero-filled device of `sectors` sectors of `sector_size`
+    /// bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is not a multiple of 512 of at least 512.
+    pub fn new(sectors: u64, sector_size: u64) -> MemBlockDevice {
+        MemBlockDevice::from_vec(vec![0; (sectors * sector_size) as usize], sector_size)
+    }
+
+    /// Returns a device holding the image `data`. A trailing partial sector
+    /// of `data` is not accessible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is not a multiple of 512 of at least 512.
+    pub fn from_vec(data: Vec<u8>, sector_size: u64) -> MemBlockDevice {
+        check_sector_size(sector_size).expect("invalid sector size");
+        MemBlockDevice { data: Arc::new(Mutex::new(data)), sector_size }
+    }
+
+    /// The number of sectors of the device.
+    pub fn sectors(&self) -> u64 {
+        self.data.lock().unwrap().len() as u64 / self.sector_size
+    }
+
+    /// Returns a copy of the contents of the device.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+
+    /// Returns the byte range of sector `n` if it lies within the device.
+    fn range(&self, n: u64, len: usize) -> io::Result<(usize, usize)> {
+        match n.checked_add(1) {
+            Some(end) if end <= self.sectors() => {
+                let start = (n * self.sector_size) as usize;
+                Ok((start, start + len))
+            }
+            _ => Err(sector_out_of_range()),
+        }
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> Option<u64> {
+        Some(self.sectors())
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let len = min(self.sector_size as usize, buf.len());
+        let (start, end) = self.range(n, len)?;
+        buf[..len].copy_from_slice(&self.data.lock().unwrap()[start..end]);
+        Ok(len)
+    }
+
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let last = n.saturating_add((buf.len() as u64 - 1) / self.sector_size);
+        let (start, _) = self.range(n, 0)?;
+        self.range(last, 0)?;
+        buf.copy_from_slice(&self.data.lock().unwrap()[start..start + buf.len()]);
+        Ok(buf.len())
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let len = self.sector_size as usize;
+        if buf.len() < len {
+            return Err(short_buffer());
+        }
+        let (start, end) = self.range(n, len)?;
+        self.data.lock().unwrap()[start..end].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// A view of the `sectors` sectors of a parent device starting at sector
+/// `start`, such as a single partition of a disk. Sector `n` of the view is
+/// sector `start + n` of the parent; accesses beyond the view fail.
+#[derive(Debug)]
+pub struct PartitionDevice<T> {
+    device: T,
+    start: u64,
+    sectors: u64,
+}
+
+impl<T: BlockDevice> PartitionDevice<T> {
+    /// Returns a view of sectors `start` to `start + sectors` (exclusive) of
+    /// `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the view would extend
+    /// beyond the last addressable sector.
+    pub fn new(device: T, start: u64, sectors: u64) -> io::Result<PartitionDevice<T>> {
+        if start.checked_add(sectors).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "partition out of range"));
+        }
+        Ok(PartitionDevice { device, start, sectors })
+    }
+
+    /// The sector of the parent device the view starts at.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The number of sectors of the view.
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    /// Returns the parent device.
+    pub fn into_inner(self) -> T {
+        self.device
+    }
+
+    /// Maps sector `n` of the view to a sector of the parent device.
+    fn parent_sector(&self, n: u64) -> io::Result<u64> {
+        if n >= self.sectors {
+            return Err(sector_out_of_range());
+        }
+        Ok(self.start + n)
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for PartitionDevice<T> {
+    fn sector_size(&self) -> u64 {
+        self.device.sector_size()
+    }
+
+    fn sector_count(&self) -> Option<u64> {
+        Some(self.sectors)
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector = self.parent_sector(n)?;
+        self.device.read_sector(sector, buf)
+    }
+
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let sector = self.parent_sector(n)?;
+        self.parent_sector(n.saturating_add((buf.len() as u64 - 1) / self.sector_size()))?;
+        self.device.read_sectors(sector, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector = self.parent_sector(n)?;
+        self.device.write_sector(sector, buf)
+    }
+}
+
+#[cfg(not(target_os = "ros"))]
+pub use self::file::FileBlockDevice;
+
+#[cfg(not(target_os = "ros"))]
+mod file {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    use traits::BlockDevice;
+    use super::{check_sector_size, sector_out_of_range, short_buffer};
+
+    /// A block device backed by a host file, such as a disk image.
+    ///
+    /// The device consists of the whole sectors of the file as it was when
+    /// opened; the file is never grown.
+    #[derive(Debug)]
+    pub struct FileBlockDevice {
+        file: File,
+        sector_size: u64,
+        sectors: u64,
+    }
+
+    impl FileBlockDevice {
+        /// Opens the image at `path` for reading and writing with sectors of
+        /// `sector_size` bytes, typically 512 or 4096.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind `InvalidInput` if `sector_size` is not a
+        /// multiple of 512 of at least 512. Errors opening the file are
+        /// returned as is.
+        pub fn open<P: AsRef<Path>>(path: P, sector_size: u64) -> io::Result<FileBlockDevice> {
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            FileBlockDevice::new(file, sector_size)
+        }
+
+        /// Opens the image at `path` like `open()`, but read-only; writes
+        /// fail.
+        pub fn open_read_only<P: AsRef<Path>>(path: P, sector_size: u64) -> io::Result<FileBlockDevice> {
+            FileBlockDevice::new(File::open(path)?, sector_size)
+        }
+
+        /// Creates, or truncates, the file at `path` and sizes it to `sectors`
+        /// zero-filled sectors of `sector_size` bytes.
+        pub fn create<P: AsRef<Path>>(path: P, sectors: u64, sector_size: u64) -> io::Result<FileBlockDevice> {
+            check_sector_size(sector_size)?;
+            let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+            file.set_len(sectors * sector_size)?;
+            FileBlockDevice::new(file, sector_size)
+        }
+
+        /// Returns a device backed by the already open `file`.
+        pub fn new(file: File, sector_size: u64) -> io::Result<FileBlockDevice> {
+            check_sector_size(sector_size)?;
+            let sectors = file.metadata()?.len() / sector_size;
+            Ok(FileBlockDevice { file, sector_size, sectors })
+        }
+
+        /// The number of sectors of the device.
+        pub fn sectors(&self) -> u64 {
+            self.sectors
+        }
+
+        /// Flushes written sectors to the underlying storage.
+        pub fn sync(&mut self) -> io::Result<()> {
+            self.file.sync_all()
+        }
+
+        /// Seeks to the start of sector `n`.
+        fn seek_sector(&mut self, n: u64) -> io::Result<()> {
+            if n >= self.sectors {
+                return Err(sector_out_of_range());
+            }
+            self.file.seek(SeekFrom::Start(n * self.sector_size))?;
+            Ok(())
+        }
+    }
+
+    impl BlockDevice for FileBlockDevice {
+        fn sector_size(&self) -> u64 {
+            self.sector_size
+        }
+
+        fn sector_count(&self) -> Option<u64> {
+            Some(self.sectors)
+        }
+
+        fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let len = ::std::cmp::min(self.sector_size as usize, buf.len());
+            self.seek_sector(n)?;
+            self.file.read_exact(&mut buf[..len])?;
+            Ok(len)
+        }
+
+        fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            if n.saturating_add((buf.len() as u64 - 1) / self.sector_size) >= self.sectors {
+                return Err(sector_out_of_range());
+            }
+            self.seek_sector(n)?;
+            self.file.read_exact(buf)?;
+            Ok(buf.len())
+        }
+
+        fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+            let len = self.sector_size as usize;
+            if buf.len() < len {
+                return Err(short_buffer());
+            }
+            self.seek_sector(n)?;
+            self.file.write_all(&buf[..len])?;
+            Ok(len)
+        }
+    }
+}
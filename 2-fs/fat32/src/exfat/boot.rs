@@ -0,0 +1,160 @@
+use std::{fmt, io};
+
+use exfat::Error;
+use traits::BlockDevice;
+
+/// The file system name of exFAT boot sectors.
+const FILE_SYSTEM_NAME: &[u8; 8] = b"EXFAT   ";
+
+/// The main boot sector of an exFAT volume.
+#[repr(C, packed)]
+pub struct BootSector {
+    jump_boot: [u8; 3],
+    file_system_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: u64,
+    volume_length: u64,
+    /// The first sector of the first FAT, relative to the volume.
+    pub fat_offset: u32,
+    /// The number of sectors of each FAT.
+    pub fat_length: u32,
+    /// The first sector of the cluster heap, relative to the volume.
+    pub cluster_heap_offset: u32,
+    /// The number of clusters in the cluster heap.
+    pub cluster_count: u32,
+    pub first_cluster_of_root_directory: u32,
+    volume_serial_number: u32,
+    file_system_revision: u16,
+    volume_flags: u16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    pub number_of_fats: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+    __reserved: [u8; 7],
+    boot_code: [u8; 390],
+    boot_signature: u16,
+}
+
+impl BootSector {
+    /// Reads the exFAT boot sector from sector `sector` of device `device`.
+    ///
+    /// # Errors
+    ///
+    /// If the file system name or boot signature is invalid, returns an
+    /// error of `BadSignature`.
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BootSector, Error> {
+        let mut buf = [0u8; 512];
+        if device.read_sector(sector, &mut buf)? != 512 {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "boot sector should be 512 bytes")));
+        }
+
+        let boot: BootSector = unsafe { ::std::mem::transmute(buf) };
+        if &{ boot.file_system_name } != FILE_SYSTEM_NAME || boot.boot_signature != 0xAA55 {
+            return Err(Error::BadSignature);
+        }
+        Ok(boot)
+    }
+
+    /// Returns an error unless the boot sector describes a plausible exFAT
+    /// volume: the range FAT volumes use for their BPB is zero, sectors are
+    /// 512 to 4096 bytes, clusters at most 32MiB and the FATs and cluster
+    /// heap are in order and within the volume.
+    pub fn validate(&self) -> Result<(), Error> {
+        let bad = |reason| Err(Error::BadBootSector(reason));
+        if { self.must_be_zero }.iter().any(|&b| b != 0) {
+            return bad("FAT parameters present");
+        }
+        if self.bytes_per_sector_shift < 9 || self.bytes_per_sector_shift > 12 {
+            return bad("invalid sector size");
+        }
+        if self.bytes_per_sector_shift as u32 + self.sectors_per_cluster_shift as u32 > 25 {
+            return bad("invalid cluster size");
+        }
+        if self.number_of_fats != 1 && self.number_of_fats != 2 {
+            return bad("invalid number of FATs");
+        }
+
+        let fats_end = self.fat_offset as u64 + self.fat_length as u64 * self.number_of_fats as u64;
+        let heap_end = self.cluster_heap_offset as u64
+            + ((self.cluster_count as u64) << self.sectors_per_cluster_shift);
+        if self.fat_offset < 24 || fats_end > self.cluster_heap_offset as u64 {
+            return bad("FATs overlap the boot region or cluster heap");
+        }
+        if (self.fat_length as u64) * self.bytes_per_sector() / 4 < self.cluster_count as u64 + 2 {
+            return bad("FAT too small");
+        }
+        if self.cluster_count == 0 || heap_end > self.volume_length {
+            return bad("cluster heap out of range");
+        }
+        let root = self.first_cluster_of_root_directory;
+        if root < 2 || root - 2 >= self.cluster_count {
+            return bad("root directory cluster out of range");
+        }
+        Ok(())
+    }
+
+    /// The number of bytes in a sector.
+    pub fn bytes_per_sector(&self) -> u64 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    /// The number of sectors in a cluster.
+    pub fn sectors_per_cluster(&self) -> u64 {
+        1 << self.sectors_per_cluster_shift
+    }
+
+    /// The number of sectors of the volume.
+    pub fn volume_length(&self) -> u64 {
+        self.volume_length
+    }
+
+    pub fn volume_serial_number(&self) -> u32 {
+        self.volume_serial_number
+    }
+
+    /// The index of the FAT and allocation bitmap in use: the second if the
+    /// volume has two and the ActiveFat flag is set, otherwise the first.
+    pub fn active_fat(&self) -> u8 {
+        match self.volume_flags & 0x01 {
+            1 if self.number_of_fats == 2 => 1,
+            _ => 0,
+        }
+    }
+
+    /// Whether the volume was not unmounted cleanly.
+    pub fn is_dirty(&self) -> bool {
+        self.volume_flags & 0x02 != 0
+    }
+}
+
+/// Returns the checksum of the boot region `sectors`, the main boot sector
+/// followed by the ten sectors after it. The volume flags and percent in use
+/// fields of the boot sector are excluded, as they change without the
+/// checksum being updated.
+pub(crate) fn boot_checksum(sectors: &[u8]) -> u32 {
+    sectors.iter().enumerate()
+        .filter(|&(i, _)| i != 106 && i != 107 && i != 112)
+        .fold(0u32, |checksum, (_, &b)| checksum.rotate_right(1).wrapping_add(b as u32))
+}
+
+impl fmt::Debug for BootSector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BootSector")
+            .field("partition_offset", &{ self.partition_offset })
+            .field("volume_length", &{ self.volume_length })
+            .field("fat_offset", &{ self.fat_offset })
+            .field("fat_length", &{ self.fat_length })
+            .field("cluster_heap_offset", &{ self.cluster_heap_offset })
+            .field("cluster_count", &{ self.cluster_count })
+            .field("first_cluster_of_root_directory", &{ self.first_cluster_of_root_directory })
+            .field("volume_serial_number", &{ self.volume_serial_number })
+            .field("file_system_revision", &{ self.file_system_revision })
+            .field("volume_flags", &{ self.volume_flags })
+            .field("bytes_per_sector", &self.bytes_per_sector())
+            .field("sectors_per_cluster", &self.sectors_per_cluster())
+            .field("number_of_fats", &{ self.number_of_fats })
+            .field("percent_in_use", &{ self.percent_in_use })
+            .finish()
+    }
+}
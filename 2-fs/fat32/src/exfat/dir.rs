@@ -0,0 +1,190 @@
+use std::cmp::min;
+use std::ffi::OsStr;
+use std::io;
+use std::vec;
+
+use exfat::{Entry, ExFat, File, Metadata};
+use exfat::exfat::Stream;
+use traits;
+use vfat::{Cluster, Shared};
+
+/// The size of a directory entry in bytes.
+pub(crate) const ENTRY_SIZE: usize = 32;
+
+/// Entry types: the type code with the in-use bit (0x80) set.
+pub(crate) const END_OF_DIRECTORY: u8 = 0x00;
+pub(crate) const ALLOCATION_BITMAP: u8 = 0x81;
+pub(crate) const UPCASE_TABLE: u8 = 0x82;
+pub(crate) const VOLUME_LABEL: u8 = 0x83;
+pub(crate) const FILE: u8 = 0x85;
+pub(crate) const STREAM_EXTENSION: u8 = 0xC0;
+pub(crate) const FILE_NAME: u8 = 0xC1;
+
+/// The largest directory the specification allows, in bytes.
+const MAX_DIR_LEN: u64 = 256 << 20;
+
+/// The number of UTF-16 code units of a name held by each file name entry.
+const NAME_UNITS_PER_ENTRY: usize = 15;
+
+#[derive(Debug)]
+pub struct Dir {
+    pub name: String,
+    pub metadata: Metadata,
+    fs: Shared<ExFat>,
+    /// The directory's data; `None` for the root directory, whose length is
+    /// that of its cluster chain.
+    stream: Option<Stream>,
+}
+
+impl Dir {
+    pub(crate) fn new(fs: Shared<ExFat>, name: String, metadata: Metadata, stream: Stream) -> Dir {
+        Dir { name, metadata, fs, stream: Some(stream) }
+    }
+
+    /// Returns the root directory of the volume `fs`.
+    pub fn root(fs: &Shared<ExFat>) -> Dir {
+        Dir { name: String::new(), metadata: Metadata::root(), fs: fs.clone(), stream: None }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.stream.is_none()
+    }
+
+    /// Finds the entry named `name` in `self`, comparing names after mapping
+    /// them to upper case with the volume's up-case table.
+    ///
+    /// # Errors
+    ///
+    /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
+    /// is returned. If no entry with name `name` exists in `self`, an error of
+    /// `NotFound` is returned.
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
+        use traits::{Dir, Entry};
+
+        let name = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid UTF-8 in name"))?;
+        let entries = self.entries()?;
+        let fs = self.fs.borrow();
+        let upcase = fs.upcase();
+        for entry in entries {
+            if upcase.eq_ignore_case(name, entry.name()) {
+                return Ok(entry);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    /// Returns the raw directory entries of `self`.
+    fn raw_entries(&self) -> io::Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        let mut fs = self.fs.borrow_mut();
+        match self.stream {
+            None => {
+                let root = fs.root_dir_cluster();
+                fs.read_chain(root, &mut raw)?;
+            }
+            Some(stream) if stream.len > MAX_DIR_LEN => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "directory too large"));
+            }
+            Some(stream) => {
+                // Entries past the valid length would read as zero, which
+                // ends the directory, so they are not read at all.
+                let len = min(stream.valid_len, stream.len);
+                fs.read_stream(&Stream { len, ..stream }, &mut raw)?;
+            }
+        }
+        Ok(raw)
+    }
+
+    /// Parses the file directory entry set `set`, a file directory entry and
+    /// its secondary entries. Returns `None` if the set is invalid: its
+    /// checksum does not match or it lacks a stream extension entry or file
+    /// name entries.
+    fn parse_set(&self, set: &[u8]) -> Option<Entry> {
+        if set.len() < 3 * ENTRY_SIZE || set_checksum(set) != u16::from_le_bytes([set[2], set[3]]) {
+            return None;
+        }
+        let stream = &set[ENTRY_SIZE..2 * ENTRY_SIZE];
+        if stream[0] != STREAM_EXTENSION {
+            return None;
+        }
+
+        let name_len = stream[3] as usize;
+        let mut units = Vec::with_capacity(name_len);
+        for entry in set[2 * ENTRY_SIZE..].chunks(ENTRY_SIZE) {
+            if units.len() >= name_len || entry[0] != FILE_NAME {
+                break;
+            }
+            units.extend(entry[2..2 + 2 * NAME_UNITS_PER_ENTRY].chunks(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])));
+        }
+        if name_len == 0 || units.len() < name_len {
+            return None;
+        }
+        units.truncate(name_len);
+        let name = String::from_utf16_lossy(&units);
+
+        let u64_at = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&stream[i..i + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let flags = stream[1];
+        let allocated = flags & 0x01 != 0;
+        let stream = Stream {
+            first_cluster: Cluster::from(if allocated {
+                u32::from_le_bytes([stream[20], stream[21], stream[22], stream[23]])
+            } else {
+                0
+            }),
+            no_fat_chain: flags & 0x02 != 0,
+            valid_len: if allocated { u64_at(8) } else { 0 },
+            len: if allocated { u64_at(24) } else { 0 },
+        };
+
+        let metadata = Metadata::from_entry(&set[..ENTRY_SIZE], stream.len);
+        Some(if metadata.is_dir() {
+            Entry::Dir(Dir::new(self.fs.clone(), name, metadata, stream))
+        } else {
+            Entry::File(File::new(self.fs.clone(), name, metadata, stream))
+        })
+    }
+}
+
+/// Returns the checksum of the entry set `set`, which excludes the checksum
+/// field of its first entry.
+fn set_checksum(set: &[u8]) -> u16 {
+    set.iter().enumerate()
+        .filter(|&(i, _)| i != 2 && i != 3)
+        .fold(0u16, |checksum, (_, &b)| checksum.rotate_right(1).wrapping_add(b as u16))
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    /// Returns the files and directories of `self` in directory order.
+    /// Invalid entry sets are skipped.
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let raw = self.raw_entries()?;
+        let count = raw.len() / ENTRY_SIZE;
+        let mut entries = Vec::new();
+        let mut i = 0;
+        while i < count {
+            match raw[i * ENTRY_SIZE] {
+                END_OF_DIRECTORY => break,
+                FILE => {
+                    let end = min(i + 1 + raw[i * ENTRY_SIZE + 1] as usize, count);
+                    match self.parse_set(&raw[i * ENTRY_SIZE..end * ENTRY_SIZE]) {
+                        Some(entry) => {
+                            entries.push(entry);
+                            i = end;
+                        }
+                        None => i += 1,
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        Ok(entries.into_iter())
+    }
+}
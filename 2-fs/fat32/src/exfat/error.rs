@@ -0,0 +1,49 @@
+use std::io;
+
+use gpt;
+use mbr;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The MBR could not be read or is invalid.
+    Mbr(mbr::Error),
+    /// The GUID partition table could not be read or is invalid.
+    Gpt(gpt::Error),
+    /// There was an I/O error while reading the volume.
+    Io(io::Error),
+    /// The boot sector does not carry the exFAT file system name and boot
+    /// signature.
+    BadSignature,
+    /// The boot sector describes an impossible volume; `.0` says why.
+    BadBootSector(&'static str),
+    /// The boot region checksum does not match the boot region.
+    BadChecksum,
+    /// The root directory lacks a valid allocation bitmap or up-case table;
+    /// `.0` says which.
+    BadRootDirectory(&'static str),
+    /// No exFAT volume was found on the device.
+    NotFound,
+    /// Partition `.0` (an MBR slot or a GPT entry index) does not exist or is
+    /// empty.
+    NoPartition(usize),
+    /// The partition has type `.0`, which cannot hold an exFAT volume.
+    UnsupportedPartitionType(u8),
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Error {
+        Error::Mbr(error)
+    }
+}
+
+impl From<gpt::Error> for Error {
+    fn from(error: gpt::Error) -> Error {
+        Error::Gpt(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
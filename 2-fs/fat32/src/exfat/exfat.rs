@@ -0,0 +1,481 @@
+use std::cmp::min;
+use std::io;
+use std::path::{Component, Path};
+
+use exfat::{BootSector, Dir, Entry, Error, File};
+use exfat::boot::boot_checksum;
+use exfat::dir::{ALLOCATION_BITMAP, END_OF_DIRECTORY, ENTRY_SIZE, UPCASE_TABLE, VOLUME_LABEL};
+use exfat::upcase::{table_checksum, UpcaseTable};
+use gpt::{Guid, GuidPartitionTable};
+use mbr::MasterBootRecord;
+use traits::{BlockDevice, FileSystem};
+use vfat::{CachedDevice, Cluster, Partition, Shared};
+
+/// The FAT entry marking the end of a cluster chain.
+const END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+
+/// The data of a file or directory: where it starts, whether its clusters
+/// are contiguous and absent from the FAT, and how long it is.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stream {
+    pub first_cluster: Cluster,
+    /// Set if the stream's clusters are contiguous and its FAT entries are
+    /// not used (the NoFatChain flag).
+    pub no_fat_chain: bool,
+    /// The number of bytes written; bytes beyond read as zero.
+    pub valid_len: u64,
+    pub len: u64,
+}
+
+/// A read-only exFAT volume.
+#[derive(Debug)]
+pub struct ExFat {
+    device: CachedDevice,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    /// The first sector of the FAT in use.
+    fat_start_sector: u64,
+    cluster_heap_start_sector: u64,
+    cluster_count: u32,
+    root_dir_cluster: Cluster,
+    volume_id: u32,
+    volume_label: Option<String>,
+    dirty: bool,
+    upcase: UpcaseTable,
+    /// The allocation bitmap in use.
+    bitmap: Stream,
+}
+
+impl ExFat {
+    /// Mounts the first exFAT volume on `device`: the first partition of
+    /// type 0x07 in the MBR, or the first Microsoft basic data partition in
+    /// the GUID partition table of a GPT disk, whose boot sector names the
+    /// exFAT file system. Partition type 0x07 is shared with NTFS, so the
+    /// boot sector decides. If no partition holds an exFAT volume but sector
+    /// 0 holds an exFAT boot sector, the whole device is mounted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no exFAT volume is found, `Mbr` if the MBR is
+    /// invalid and the device holds no exFAT volume in sector 0, or `Gpt` if
+    /// the GPT of a GPT disk is invalid.
+    pub fn from<T>(mut device: T) -> Result<Shared<ExFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        let error = match MasterBootRecord::from(&mut device) {
+            Ok(ref mbr) if (0..4).any(|i| mbr.get_partition(i).is_gpt_protective()) => {
+                let gpt = GuidPartitionTable::from(&mut device)?;
+                let start = gpt.partitions()
+                    .filter(|&(_, partition)| partition.type_guid == Guid::MICROSOFT_BASIC_DATA)
+                    .map(|(_, partition)| partition.first_lba)
+                    .find(|&start| BootSector::from(&mut device, start).is_ok());
+                if let Some(start) = start {
+                    return ExFat::mount(device, start);
+                }
+                return Err(Error::NotFound);
+            }
+            Ok(mbr) => {
+                let start = (0..4)
+                    .map(|i| mbr.get_partition(i))
+                    .filter(|partition| partition.is_ntfs_or_exfat())
+                    .map(|partition| partition.relative_sector as u64)
+                    .find(|&start| BootSector::from(&mut device, start).is_ok());
+                if let Some(start) = start {
+                    return ExFat::mount(device, start);
+                }
+                Error::NotFound
+            }
+            Err(e) => Error::Mbr(e),
+        };
+
+        match BootSector::from(&mut device, 0) {
+            Ok(_) => ExFat::mount(device, 0),
+            _ => Err(error),
+        }
+    }
+
+    /// Mounts the exFAT volume in slot `index` (0 to 3) of the MBR of
+    /// `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoPartition` if `index` is not a valid slot or the slot is
+    /// empty, `UnsupportedPartitionType` if the partition type is not 0x07
+    /// and `BadSignature` if the partition holds no exFAT volume.
+    pub fn from_partition<T>(mut device: T, index: usize) -> Result<Shared<ExFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        if index >= 4 {
+            return Err(Error::NoPartition(index));
+        }
+
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let partition = mbr.get_partition(index);
+        match partition.partition_type {
+            0x00 => Err(Error::NoPartition(index)),
+            _ if partition.is_ntfs_or_exfat() => ExFat::mount(device, partition.relative_sector as u64),
+            partition_type => Err(Error::UnsupportedPartitionType(partition_type)),
+        }
+    }
+
+    /// Mounts `device` as an exFAT volume without a partition table; the
+    /// boot sector is sector 0.
+    pub fn from_raw<T>(device: T) -> Result<Shared<ExFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        ExFat::mount(device, 0)
+    }
+
+    /// Mounts the exFAT volume whose boot sector is physical sector
+    /// `partition_start` of `device`, verifying the boot region checksum and
+    /// loading the up-case table and the location of the allocation bitmap.
+    fn mount<T>(mut device: T, partition_start: u64) -> Result<Shared<ExFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        let boot = BootSector::from(&mut device, partition_start)?;
+        boot.validate()?;
+        if boot.bytes_per_sector() < device.sector_size() {
+            return Err(Error::BadBootSector("logical sectors smaller than device sectors"));
+        }
+        if boot.cluster_count > 0x0FFF_FFF0 {
+            return Err(Error::BadBootSector("too many clusters"));
+        }
+
+        let bytes_per_sector = boot.bytes_per_sector();
+        let partition = Partition { start: partition_start, sector_size: bytes_per_sector };
+        let mut device = CachedDevice::new(device, partition);
+
+        // Sectors 0 to 10 of the boot region are checksummed; sector 11
+        // repeats the checksum.
+        let mut region = Vec::new();
+        for i in 0..11 {
+            region.extend_from_slice(device.get(partition_start + i)?);
+        }
+        let checksum = boot_checksum(&region);
+        let stored = device.get(partition_start + 11)?;
+        if stored.chunks(4).any(|bytes| bytes != &checksum.to_le_bytes()[..]) {
+            return Err(Error::BadChecksum);
+        }
+
+        let active_fat = boot.active_fat() as u64;
+        let mut exfat = ExFat {
+            device,
+            bytes_per_sector,
+            sectors_per_cluster: boot.sectors_per_cluster(),
+            fat_start_sector: partition_start + boot.fat_offset as u64 + active_fat * boot.fat_length as u64,
+            cluster_heap_start_sector: partition_start + boot.cluster_heap_offset as u64,
+            cluster_count: boot.cluster_count,
+            root_dir_cluster: Cluster::from(boot.first_cluster_of_root_directory),
+            volume_id: boot.volume_serial_number(),
+            volume_label: None,
+            dirty: boot.is_dirty(),
+            upcase: UpcaseTable::default(),
+            bitmap: Stream { first_cluster: Cluster::from(0), no_fat_chain: false, valid_len: 0, len: 0 },
+        };
+        exfat.load_root_entries(active_fat as u8)?;
+        Ok(Shared::new(exfat))
+    }
+
+    /// Reads the allocation bitmap, up-case table and volume label entries
+    /// of the root directory. Of two allocation bitmaps, the one belonging
+    /// to FAT `active_fat` is used.
+    fn load_root_entries(&mut self, active_fat: u8) -> Result<(), Error> {
+        let mut raw = Vec::new();
+        let root = self.root_dir_cluster;
+        self.read_chain(root, &mut raw)?;
+
+        let mut bitmap = None;
+        let mut upcase = None;
+        for entry in raw.chunks(ENTRY_SIZE) {
+            let stream = Stream {
+                first_cluster: Cluster::from(u32_at(entry, 20)),
+                no_fat_chain: false,
+                valid_len: u64_at(entry, 24),
+                len: u64_at(entry, 24),
+            };
+            match entry[0] {
+                END_OF_DIRECTORY => break,
+                ALLOCATION_BITMAP if entry[1] & 0x01 == active_fat => bitmap = Some(stream),
+                UPCASE_TABLE => upcase = Some((stream, u32_at(entry, 4))),
+                VOLUME_LABEL => {
+                    let len = min(entry[1] as usize, 11);
+                    let units: Vec<u16> = entry[2..2 + 2 * len].chunks(2)
+                        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                        .collect();
+                    self.volume_label = Some(String::from_utf16_lossy(&units));
+                }
+                _ => {}
+            }
+        }
+
+        // The bitmap holds a bit per cluster and is stored in whole clusters.
+        let bitmap_len = (self.cluster_count as u64 + 7) / 8;
+        let bytes_per_cluster = self.bytes_per_cluster() as u64;
+        let max_bitmap_len = (bitmap_len + bytes_per_cluster - 1) / bytes_per_cluster * bytes_per_cluster;
+        self.bitmap = match bitmap {
+            Some(bitmap) if bitmap.len >= bitmap_len && bitmap.len <= max_bitmap_len => bitmap,
+            Some(_) => return Err(Error::BadRootDirectory("allocation bitmap has the wrong size")),
+            None => return Err(Error::BadRootDirectory("allocation bitmap missing")),
+        };
+        let (upcase, checksum) = upcase.ok_or(Error::BadRootDirectory("up-case table missing"))?;
+        if upcase.len > 0x20000 * 2 {
+            return Err(Error::BadRootDirectory("up-case table too large"));
+        }
+        let mut table = Vec::new();
+        self.read_stream(&upcase, &mut table)?;
+        if table_checksum(&table) != checksum {
+            return Err(Error::BadRootDirectory("up-case table checksum mismatch"));
+        }
+        self.upcase = UpcaseTable::from_bytes(&table);
+        Ok(())
+    }
+
+    /// The volume serial number.
+    pub fn volume_id(&self) -> u32 {
+        self.volume_id
+    }
+
+    /// The volume label, if the volume has one.
+    pub fn volume_label(&self) -> Option<&str> {
+        self.volume_label.as_ref().map(|label| label.as_str())
+    }
+
+    /// Whether the volume was not unmounted cleanly and may be
+    /// inconsistent.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns the number of bytes in a cluster.
+    pub fn bytes_per_cluster(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    /// The number of clusters in the cluster heap.
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    /// Returns the size of the cluster heap in bytes.
+    pub fn total_space(&self) -> u64 {
+        self.cluster_count as u64 * self.bytes_per_cluster() as u64
+    }
+
+    /// Returns the number of bytes in clusters that the allocation bitmap
+    /// marks as free.
+    pub fn free_space(&mut self) -> io::Result<u64> {
+        let bitmap = self.bitmap;
+        let mut bits = Vec::new();
+        self.read_stream(&bitmap, &mut bits)?;
+        let free = (0..self.cluster_count as usize)
+            .filter(|&i| bits[i / 8] & (1 << (i % 8)) == 0)
+            .count();
+        Ok(free as u64 * self.bytes_per_cluster() as u64)
+    }
+
+    pub(crate) fn upcase(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    pub(crate) fn root_dir_cluster(&self) -> Cluster {
+        self.root_dir_cluster
+    }
+
+    /// Returns whether `cluster` is a cluster of the cluster heap.
+    pub(crate) fn is_data_cluster(&self, cluster: Cluster) -> bool {
+        cluster.is_valid() && cluster.index() < self.cluster_count
+    }
+
+    /// Returns the first sector of the cluster `cluster`.
+    fn cluster_sector(&self, cluster: Cluster) -> u64 {
+        self.cluster_heap_start_sector + cluster.index() as u64 * self.sectors_per_cluster
+    }
+
+    /// Returns the cluster following `cluster` in its chain, or `None` if
+    /// `cluster` is the last.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the FAT entry is neither
+    /// the end of the chain nor a cluster of the cluster heap.
+    pub(crate) fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        let offset = cluster.id() as u64 * 4;
+        let sector = self.fat_start_sector + offset / self.bytes_per_sector;
+        let data = self.device.get(sector)?;
+        let raw = u32_at(data, (offset % self.bytes_per_sector) as usize);
+        match raw {
+            END_OF_CHAIN => Ok(None),
+            next if next >= 2 && next - 2 < self.cluster_count => Ok(Some(Cluster::from(next))),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster chain")),
+        }
+    }
+
+    /// Returns the clusters of the chain starting at `start`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the chain is invalid or
+    /// loops.
+    pub(crate) fn chain(&mut self, start: Cluster) -> io::Result<Vec<Cluster>> {
+        let mut chain = Vec::new();
+        let mut next = Some(start);
+        while let Some(cluster) = next {
+            if !self.is_data_cluster(cluster) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster out of range"));
+            }
+            if chain.len() >= self.cluster_count as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+            chain.push(cluster);
+            next = self.next_cluster(cluster)?;
+        }
+        Ok(chain)
+    }
+
+    /// Reads `buf.len()` bytes starting at byte `offset` of the run of
+    /// contiguous clusters beginning at `start` into `buf`, like
+    /// `VFat::read_run()`.
+    pub(crate) fn read_run(&mut self, start: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_per_cluster = self.bytes_per_cluster();
+        let clusters = (offset + buf.len() + bytes_per_cluster - 1) / bytes_per_cluster;
+        let last = Cluster::from(start.id().saturating_add(clusters.saturating_sub(1) as u32));
+        if !self.is_data_cluster(start) || !self.is_data_cluster(last) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster out of range"));
+        }
+
+        let sector = self.cluster_sector(start);
+        self.device.read_bytes(sector, offset, bytes_per_cluster, buf)
+    }
+
+    /// Appends the clusters of the chain starting at `start` to `buf`.
+    pub(crate) fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let bytes_per_cluster = self.bytes_per_cluster();
+        let chain = self.chain(start)?;
+        let begin = buf.len();
+        buf.resize(begin + chain.len() * bytes_per_cluster, 0);
+        for (i, &cluster) in chain.iter().enumerate() {
+            let offset = begin + i * bytes_per_cluster;
+            self.read_run(cluster, 0, &mut buf[offset..offset + bytes_per_cluster])?;
+        }
+        Ok(chain.len() * bytes_per_cluster)
+    }
+
+    /// Appends the `stream.len` bytes of `stream` to `buf`. Bytes beyond the
+    /// stream's valid length read as zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the stream is larger than
+    /// the cluster heap or its cluster chain is shorter than its valid
+    /// length. Both are checked before `buf` grows.
+    pub(crate) fn read_stream(&mut self, stream: &Stream, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let bytes_per_cluster = self.bytes_per_cluster() as u64;
+        if stream.len > self.cluster_count as u64 * bytes_per_cluster {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream larger than the cluster heap"));
+        }
+        let valid = min(stream.valid_len, stream.len) as usize;
+        let clusters = (valid as u64 + bytes_per_cluster - 1) / bytes_per_cluster;
+        let chain = if valid == 0 || stream.no_fat_chain {
+            Vec::new()
+        } else {
+            let chain = self.chain(stream.first_cluster)?;
+            if (chain.len() as u64) < clusters {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain shorter than stream"));
+            }
+            chain
+        };
+
+        let begin = buf.len();
+        buf.resize(begin + stream.len as usize, 0);
+        if valid == 0 {
+            return Ok(stream.len as usize);
+        }
+
+        if stream.no_fat_chain {
+            self.read_run(stream.first_cluster, 0, &mut buf[begin..begin + valid])?;
+        } else {
+            for (i, &cluster) in chain.iter().take(clusters as usize).enumerate() {
+                let start = begin + i * bytes_per_cluster as usize;
+                let end = min(start + bytes_per_cluster as usize, begin + valid);
+                self.read_run(cluster, 0, &mut buf[start..end])?;
+            }
+        }
+        Ok(stream.len as usize)
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u32_at(data, offset) as u64 | (u32_at(data, offset + 4) as u64) << 32
+}
+
+pub(crate) fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "exFAT volumes are read-only")
+}
+
+impl<'a> FileSystem for &'a Shared<ExFat> {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    /// Opens the entry at `path`. exFAT directories have no `.` and `..`
+    /// entries; `..` components are resolved from the path.
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        use traits::Entry;
+
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path must be absolute"));
+        }
+
+        let components: Vec<_> = path.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                Component::ParentDir => Some("..".as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let mut parents: Vec<Dir> = Vec::new();
+        let mut entry = super::Entry::Dir(Dir::root(self));
+        for (i, &name) in components.iter().enumerate() {
+            let dir = entry.into_dir().ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path component is not a directory",
+            ))?;
+            if name == ".." {
+                // The root directory is its own parent.
+                entry = super::Entry::Dir(parents.pop().unwrap_or(dir));
+                continue;
+            }
+            entry = match dir.find(name) {
+                Ok(entry) => entry,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && i + 1 < components.len() => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "path component does not exist"));
+                }
+                Err(e) => return Err(e),
+            };
+            parents.push(dir);
+        }
+        Ok(entry)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
+        Err(read_only())
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, _path: P, _parents: bool) -> io::Result<Self::Dir> {
+        Err(read_only())
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, _from: P, _to: Q) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
+        Err(read_only())
+    }
+}
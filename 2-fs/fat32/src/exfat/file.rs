@@ -0,0 +1,148 @@
+use std::cmp::min;
+use std::io::{self, SeekFrom};
+
+use exfat::{ExFat, Metadata};
+use exfat::exfat::{read_only, Stream};
+use traits;
+use vfat::{Cluster, Shared};
+
+#[derive(Debug)]
+pub struct File {
+    pub name: String,
+    pub metadata: Metadata,
+    fs: Shared<ExFat>,
+    stream: Stream,
+    offset: u64,
+    /// The clusters of the file's chain known so far, in order. Unused if
+    /// the file's clusters are contiguous and absent from the FAT.
+    clusters: Vec<Cluster>,
+}
+
+impl File {
+    pub(crate) fn new(fs: Shared<ExFat>, name: String, metadata: Metadata, stream: Stream) -> File {
+        let clusters = if stream.first_cluster.is_valid() { vec![stream.first_cluster] } else { Vec::new() };
+        File { name, metadata, fs, stream, offset: 0, clusters }
+    }
+
+    /// Returns the `index`th cluster of the file and the length of the run
+    /// of contiguous clusters starting there, limited to `max` clusters, or
+    /// `None` if the chain ends before.
+    fn run_at(&mut self, fs: &mut ExFat, index: usize, max: usize) -> io::Result<Option<(Cluster, usize)>> {
+        if self.stream.no_fat_chain {
+            let first = self.stream.first_cluster;
+            return Ok(Some((Cluster::from(first.id().saturating_add(index as u32)), max)));
+        }
+
+        while self.clusters.len() < index + max {
+            let last = match self.clusters.last() {
+                Some(&last) => last,
+                None => return Ok(None),
+            };
+            if self.clusters.len() > fs.cluster_count() as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+            match fs.next_cluster(last)? {
+                Some(next) => self.clusters.push(next),
+                None => break,
+            }
+        }
+        if index >= self.clusters.len() {
+            return Ok(None);
+        }
+
+        let first = self.clusters[index];
+        let run = self.clusters[index..min(index + max, self.clusters.len())]
+            .iter()
+            .enumerate()
+            .take_while(|&(i, cluster)| cluster.id() == first.id() + i as u32)
+            .count();
+        Ok(Some((first, run)))
+    }
+}
+
+impl traits::File for File {
+    /// Files of read-only volumes are never modified; there is nothing to
+    /// write back.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.stream.len
+    }
+}
+
+impl io::Read for File {
+    /// Reads from the current offset into `buf`. Bytes beyond the file's
+    /// valid data length read as zero; reading at or beyond the end of the
+    /// file returns `0`.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let can_be_read = min(buf.len() as u64, self.stream.len.saturating_sub(self.offset)) as usize;
+        let valid = min(can_be_read as u64, self.stream.valid_len.saturating_sub(self.offset)) as usize;
+        let fs = self.fs.clone();
+        let mut fs = fs.borrow_mut();
+        let bytes_per_cluster = fs.bytes_per_cluster();
+
+        let mut bytes_read = 0;
+        while bytes_read < valid {
+            let remaining = valid - bytes_read;
+            let index = (self.offset / bytes_per_cluster as u64) as usize;
+            let cluster_offset = (self.offset % bytes_per_cluster as u64) as usize;
+            let clusters = (cluster_offset + remaining + bytes_per_cluster - 1) / bytes_per_cluster;
+            let (cluster, run) = self.run_at(&mut fs, index, clusters)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "cluster chain shorter than file")
+            })?;
+            let len = min(run * bytes_per_cluster - cluster_offset, remaining);
+            let bytes = fs.read_run(cluster, cluster_offset, &mut buf[bytes_read..bytes_read + len])?;
+            bytes_read += bytes;
+            self.offset += bytes as u64;
+        }
+
+        for b in &mut buf[valid..can_be_read] {
+            *b = 0;
+        }
+        self.offset += (can_be_read - valid) as u64;
+        Ok(can_be_read)
+    }
+}
+
+impl io::Write for File {
+    /// exFAT volumes are read-only.
+    ///
+    /// # Errors
+    ///
+    /// Always returns an error of kind `PermissionDenied`.
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for File {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// Seeking to or beyond the end of the file is allowed; reads there
+    /// return no data.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of the file or to an offset that overflows
+    /// results in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let seek_offset = match pos {
+            SeekFrom::Current(offset) => (self.offset as i64).checked_add(offset),
+            SeekFrom::End(offset) => (min(self.stream.len, ::std::i64::MAX as u64) as i64).checked_add(offset),
+            SeekFrom::Start(offset) if offset <= ::std::i64::MAX as u64 => Some(offset as i64),
+            SeekFrom::Start(_) => None,
+        };
+
+        self.offset = match seek_offset {
+            Some(offset) if offset >= 0 => offset as u64,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        };
+        Ok(self.offset)
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use traits;
+use vfat::Timestamp;
+
+const READ_ONLY: u16 = 0x01;
+const HIDDEN: u16 = 0x02;
+const SYSTEM: u16 = 0x04;
+const DIRECTORY: u16 = 0x10;
+const ARCHIVE: u16 = 0x20;
+
+/// Metadata of a file or directory, from its file directory entry and
+/// stream extension entry.
+///
+/// exFAT timestamps use the same date and time encoding as FAT with 10 ms
+/// increments. Their offsets from UTC are ignored: timestamps are reported
+/// as recorded.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Metadata {
+    attributes: u16,
+    created: Timestamp,
+    modified: Timestamp,
+    accessed: Timestamp,
+    len: u64,
+}
+
+impl Metadata {
+    /// Returns the metadata of the root directory, which has no directory
+    /// entry.
+    pub(crate) fn root() -> Metadata {
+        Metadata { attributes: DIRECTORY, ..Metadata::default() }
+    }
+
+    /// Parses the metadata from the file directory entry `file` and sets the
+    /// length of files to `len`.
+    pub(crate) fn from_entry(file: &[u8], len: u64) -> Metadata {
+        let u16_at = |i: usize| u16::from_le_bytes([file[i], file[i + 1]]);
+        let timestamp = |i: usize, centiseconds: u8| {
+            Timestamp::from_dos(u16_at(i + 2), u16_at(i), if centiseconds < 200 { centiseconds } else { 0 })
+        };
+        let attributes = u16_at(4);
+        Metadata {
+            attributes,
+            created: timestamp(8, file[20]),
+            modified: timestamp(12, file[21]),
+            accessed: timestamp(16, 0),
+            len: if attributes & DIRECTORY != 0 { 0 } else { len },
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & DIRECTORY != 0
+    }
+
+    /// The raw attribute bits of the entry.
+    pub fn attributes(&self) -> u16 {
+        self.attributes
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes & READ_ONLY != 0
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes & HIDDEN != 0
+    }
+
+    fn system(&self) -> bool {
+        self.attributes & SYSTEM != 0
+    }
+
+    fn archive(&self) -> bool {
+        self.attributes & ARCHIVE != 0
+    }
+
+    /// The volume label of an exFAT volume is not a directory entry of its
+    /// own kind; entries are never labels.
+    fn is_volume_label(&self) -> bool {
+        false
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        use traits::Metadata;
+        f.debug_struct("Metadata")
+            .field("directory", &self.is_dir())
+            .field("read_only", &self.read_only())
+            .field("hidden", &self.hidden())
+            .field("system", &self.system())
+            .field("archive", &self.archive())
+            .field("len", &self.len())
+            .field("created", &self.created())
+            .field("accessed", &self.accessed())
+            .field("modified", &self.modified())
+            .finish()
+    }
+}
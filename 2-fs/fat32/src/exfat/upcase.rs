@@ -0,0 +1,48 @@
+/// The up-case table of an exFAT volume, which maps UTF-16 code units to
+/// their upper-case form. Names are compared and hashed after mapping; code
+/// units beyond the end of the table map to themselves.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UpcaseTable(Vec<u16>);
+
+impl UpcaseTable {
+    /// Decodes the on-disk table `data`. In the compressed form, the code
+    /// unit `0xFFFF` followed by a count stands for that many code units that
+    /// map to themselves.
+    pub fn from_bytes(data: &[u8]) -> UpcaseTable {
+        let mut table = Vec::new();
+        let mut units = data.chunks(2)
+            .filter(|unit| unit.len() == 2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+        while let Some(unit) = units.next() {
+            match (unit, table.len() < 0x10000) {
+                (_, false) => break,
+                (0xFFFF, true) => {
+                    let count = units.next().unwrap_or(0) as usize;
+                    for _ in 0..count {
+                        let identity = table.len() as u16;
+                        table.push(identity);
+                    }
+                }
+                (unit, true) => table.push(unit),
+            }
+        }
+        table.truncate(0x10000);
+        UpcaseTable(table)
+    }
+
+    pub fn upcase(&self, unit: u16) -> u16 {
+        self.0.get(unit as usize).cloned().unwrap_or(unit)
+    }
+
+    /// Returns whether `a` and `b` are equal after mapping them to upper
+    /// case.
+    pub fn eq_ignore_case(&self, a: &str, b: &str) -> bool {
+        a.encode_utf16().map(|unit| self.upcase(unit)).eq(b.encode_utf16().map(|unit| self.upcase(unit)))
+    }
+}
+
+/// Returns the checksum of the up-case table `data` as stored in its
+/// directory entry.
+pub(crate) fn table_checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |checksum, &b| checksum.rotate_right(1).wrapping_add(b as u32))
+}
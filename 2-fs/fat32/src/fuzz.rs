@@ -0,0 +1,53 @@
+//! Entry points for fuzzing the file systems with arbitrary images.
+//!
+//! The `mount` target in `fuzz/` feeds its input to `exercise_image()`; run
+//! it with `cargo +nightly fuzz run mount`. The unit tests run the same code
+//! over seeded mutations of valid images.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use device::MemBlockDevice;
+use traits::{Dir, Entry, File, FileSystem};
+use vfat::{self, Shared, VFat};
+
+/// Mounts `data` as a disk with 512-byte sectors and exercises it: walks the
+/// directory tree up to a small depth, reads the start of every file and
+/// checks the volume. Errors are ignored; only panics, including aborts on
+/// oversized allocations, are failures.
+pub fn exercise_image(data: &[u8]) {
+    fn walk(vfat: &Shared<VFat>, dir: vfat::Dir, depth: usize) {
+        let entries = match dir.entries() {
+            Ok(entries) => entries.take(64).collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        for entry in entries {
+            let name = entry.name().to_string();
+            if entry.is_dir() {
+                if let Some(dir) = entry.into_dir() {
+                    if depth > 0 && name != "." && name != ".." {
+                        walk(vfat, dir, depth - 1);
+                    }
+                }
+            } else if let Some(mut file) = entry.into_file() {
+                let mut buf = [0u8; 1024];
+                for _ in 0..4 {
+                    match file.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {}
+                    }
+                }
+                let _ = file.seek(SeekFrom::Start(file.size() / 2));
+            }
+        }
+    }
+
+    let vfat = match VFat::from(MemBlockDevice::from_vec(data.to_vec(), 512)) {
+        Ok(vfat) => vfat,
+        Err(_) => return,
+    };
+    if let Ok(root) = vfat.open_dir("/") {
+        walk(&vfat, root, 3);
+    }
+    let _ = vfat.borrow_mut().free_space();
+    let _ = vfat::check(&vfat, true);
+}
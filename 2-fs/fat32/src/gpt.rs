@@ -0,0 +1,294 @@
+use std::{fmt, io};
+use std::mem::size_of;
+
+use mbr::{self, MasterBootRecord};
+use traits::BlockDevice;
+use util::crc32;
+
+/// A globally unique identifier as stored on disk: the first three fields are
+/// little-endian, the last two big-endian.
+#[repr(C, packed)]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// The partition type of Microsoft basic data partitions (FAT, exFAT,
+    /// NTFS), `EBD0A0A2-B9E5-4433-87C0-68B6B72699C7`.
+    pub const MICROSOFT_BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+        0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+    ]);
+
+    /// The partition type of EFI system partitions, which are FAT formatted,
+    /// `C12A7328-F81F-11D2-BA4B-00A0C93EC93B`.
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11,
+        0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+    ]);
+
+    /// Whether the GUID is all zeroes, marking an unused partition entry.
+    pub fn is_nil(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = self.0;
+        write!(f, "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+               b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9])?;
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Guid({})", self)
+    }
+}
+
+/// The GPT header, as stored in the first 92 bytes of its sector.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct Header {
+    signature: [u8; 8],
+    revision: u32,
+    header_size: u32,
+    header_crc32: u32,
+    __reserved: u32,
+    current_lba: u64,
+    backup_lba: u64,
+    first_usable_lba: u64,
+    last_usable_lba: u64,
+    disk_guid: Guid,
+    partition_entries_lba: u64,
+    number_of_entries: u32,
+    entry_size: u32,
+    entries_crc32: u32,
+}
+
+/// A partition entry as stored in the partition entry array.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct RawPartitionEntry {
+    type_guid: Guid,
+    unique_guid: Guid,
+    first_lba: u64,
+    last_lba: u64,
+    attributes: u64,
+    name: [u16; 36],
+}
+
+/// A used entry of a GUID partition table.
+#[derive(Debug, Clone)]
+pub struct GptPartition {
+    /// The partition type, e.g. `Guid::MICROSOFT_BASIC_DATA`.
+    pub type_guid: Guid,
+    /// The GUID unique to this partition.
+    pub unique_guid: Guid,
+    /// The first sector of the partition.
+    pub first_lba: u64,
+    /// The last sector of the partition, inclusive.
+    pub last_lba: u64,
+    /// The partition attribute flags.
+    pub attributes: u64,
+    /// The partition's name.
+    pub name: String,
+}
+
+impl GptPartition {
+    /// The number of sectors in the partition.
+    pub fn sectors(&self) -> u64 {
+        self.last_lba - self.first_lba + 1
+    }
+}
+
+/// A GUID partition table (GPT).
+#[derive(Debug)]
+pub struct GuidPartitionTable {
+    /// The GUID of the disk.
+    pub disk_guid: Guid,
+    /// The first sector usable by partitions.
+    pub first_usable_lba: u64,
+    /// The last sector usable by partitions, inclusive.
+    pub last_usable_lba: u64,
+    /// `true` if the primary header or partition array was damaged and the
+    /// table was read from the backup copy at the end of the disk.
+    pub used_backup: bool,
+    partitions: Vec<(usize, GptPartition)>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// There was an I/O error while reading the GPT.
+    Io(io::Error),
+    /// The protective MBR could not be read.
+    Mbr(mbr::Error),
+    /// The MBR does not contain a protective (type 0xEE) partition.
+    NotProtective,
+    /// Neither the primary nor the backup header has a valid signature.
+    BadSignature,
+    /// Neither the primary nor the backup header passed validation; `.0`
+    /// describes why the primary header was rejected.
+    BadHeader(&'static str),
+    /// The CRC32 of the header does not match its contents.
+    BadHeaderCrc,
+    /// The CRC32 of the partition entry array does not match its contents.
+    BadEntriesCrc,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Self {
+        Error::Mbr(error)
+    }
+}
+
+/// The largest partition entry array, in bytes, that will be read.
+const MAX_ENTRIES_BYTES: u64 = 1 << 20;
+
+impl GuidPartitionTable {
+    /// Reads and validates the GUID partition table of `device`.
+    ///
+    /// The primary header (sector 1) is tried first. If it or its partition
+    /// entry array fails validation, the backup copy is used instead. The
+    /// backup header is located by the primary header if that is intact, and
+    /// otherwise assumed to be in the last sector of the device, or of the
+    /// protective MBR partition if the size of the device is not known.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotProtective` if the MBR has no protective GPT partition.
+    /// If both copies fail validation, returns the error of the primary copy.
+    pub fn from<T: BlockDevice>(mut device: T) -> Result<GuidPartitionTable, Error> {
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let protective = (0..4)
+            .map(|i| mbr.get_partition(i))
+            .find(|partition| partition.is_gpt_protective())
+            .ok_or(Error::NotProtective)?;
+
+        let (primary_error, backup_lba) = match GuidPartitionTable::read_header(&mut device, 1) {
+            Ok(header) => match GuidPartitionTable::read(&mut device, &header) {
+                Ok(table) => return Ok(table),
+                Err(e) => (e, header.backup_lba),
+            },
+            Err(e) => {
+                let last_lba = match device.sector_count() {
+                    Some(sectors) => sectors.saturating_sub(1),
+                    None => (protective.relative_sector as u64 + protective.sectors() as u64).saturating_sub(1),
+                };
+                (e, last_lba)
+            }
+        };
+
+        let table = GuidPartitionTable::read_header(&mut device, backup_lba)
+            .and_then(|header| GuidPartitionTable::read(&mut device, &header));
+        match table {
+            Ok(mut table) => {
+                table.used_backup = true;
+                Ok(table)
+            }
+            Err(_) => Err(primary_error),
+        }
+    }
+
+    /// Reads the header at `lba` and validates its signature, size, CRC32
+    /// and own LBA.
+    fn read_header<T: BlockDevice>(device: &mut T, lba: u64) -> Result<Header, Error> {
+        let sector_size = device.sector_size() as usize;
+        let mut sector = vec![0u8; sector_size];
+        device.read_sector(lba, &mut sector)?;
+
+        let header: Header = unsafe { ::std::ptr::read_unaligned(sector.as_ptr() as *const Header) };
+        if &header.signature != b"EFI PART" {
+            return Err(Error::BadSignature);
+        }
+
+        let header_size = header.header_size as usize;
+        if header_size < size_of::<Header>() || header_size > sector_size {
+            return Err(Error::BadHeader("invalid header size"));
+        }
+        for byte in &mut sector[16..20] {
+            *byte = 0;
+        }
+        if crc32(&sector[..header_size]) != header.header_crc32 {
+            return Err(Error::BadHeaderCrc);
+        }
+        if header.current_lba != lba {
+            return Err(Error::BadHeader("header is not at its own LBA"));
+        }
+        Ok(header)
+    }
+
+    /// Reads and validates the partition entries of `header`.
+    fn read<T: BlockDevice>(device: &mut T, header: &Header) -> Result<GuidPartitionTable, Error> {
+        let sector_size = device.sector_size() as usize;
+        let entry_size = header.entry_size as u64;
+        let entries_bytes = header.number_of_entries as u64 * entry_size;
+        if entry_size < size_of::<RawPartitionEntry>() as u64 || entry_size % 128 != 0
+            || entries_bytes > MAX_ENTRIES_BYTES
+        {
+            return Err(Error::BadHeader("invalid partition entry array size"));
+        }
+
+        let mut entries = Vec::new();
+        let entries_sectors = (entries_bytes + sector_size as u64 - 1) / sector_size as u64;
+        if header.partition_entries_lba.checked_add(entries_sectors).is_none() {
+            return Err(Error::BadHeader("partition entry array out of range"));
+        }
+        for i in 0..entries_sectors {
+            device.read_all_sector(header.partition_entries_lba + i, &mut entries)?;
+        }
+        entries.truncate(entries_bytes as usize);
+        if entries.len() as u64 != entries_bytes || crc32(&entries) != header.entries_crc32 {
+            return Err(Error::BadEntriesCrc);
+        }
+
+        let partitions = entries.chunks(entry_size as usize)
+            .map(|chunk| unsafe { ::std::ptr::read_unaligned(chunk.as_ptr() as *const RawPartitionEntry) })
+            .enumerate()
+            .filter(|&(_, ref raw)| !raw.type_guid.is_nil())
+            .map(|(i, raw)| {
+                let name = { raw.name };
+                let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                (i, GptPartition {
+                    type_guid: raw.type_guid,
+                    unique_guid: raw.unique_guid,
+                    first_lba: raw.first_lba,
+                    last_lba: raw.last_lba,
+                    attributes: raw.attributes,
+                    name: String::from_utf16_lossy(&name[..len]),
+                })
+            })
+            .filter(|&(_, ref partition)| partition.first_lba <= partition.last_lba)
+            .collect();
+
+        Ok(GuidPartitionTable {
+            disk_guid: header.disk_guid,
+            first_usable_lba: header.first_usable_lba,
+            last_usable_lba: header.last_usable_lba,
+            used_backup: false,
+            partitions,
+        })
+    }
+
+    /// Returns the used partition entries with their indices in the entry
+    /// array.
+    pub fn partitions<'a>(&'a self) -> impl Iterator<Item = (usize, &'a GptPartition)> + 'a {
+        self.partitions.iter().map(|&(i, ref partition)| (i, partition))
+    }
+
+    /// Returns the partition at index `index` of the entry array, if that
+    /// entry is used.
+    pub fn get_partition(&self, index: usize) -> Option<&GptPartition> {
+        self.partitions().find(|&(i, _)| i == index).map(|(_, partition)| partition)
+    }
+}
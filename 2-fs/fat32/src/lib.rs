@@ -12,9 +12,17 @@ extern crate core;
 
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod conformance;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
 mod mbr;
 mod util;
 
+pub mod device;
+pub mod exfat;
+pub mod gpt;
+pub mod ramfs;
 pub mod vfat;
 pub mod traits;
 
@@ -28,6 +28,40 @@ pub struct PartitionEntry {
     sectors: u32,
 }
 
+impl PartitionEntry {
+    /// Whether the partition type is FAT32 with CHS (0x0B) or LBA (0x0C)
+    /// addressing.
+    pub fn is_fat32(&self) -> bool {
+        self.partition_type == 0x0B || self.partition_type == 0x0C
+    }
+
+    /// Whether the partition type is FAT12 (0x01), FAT16 (0x04, 0x06 or 0x0E)
+    /// or FAT32.
+    pub fn is_fat(&self) -> bool {
+        match self.partition_type {
+            0x01 | 0x04 | 0x06 | 0x0E => true,
+            _ => self.is_fat32(),
+        }
+    }
+
+    /// Whether the partition type is 0x07, which NTFS and exFAT volumes
+    /// share; only the volume's boot sector tells them apart.
+    pub fn is_ntfs_or_exfat(&self) -> bool {
+        self.partition_type == 0x07
+    }
+
+    /// Whether the partition is the protective partition (type 0xEE) of a
+    /// disk with a GUID partition table.
+    pub fn is_gpt_protective(&self) -> bool {
+        self.partition_type == 0xEE
+    }
+
+    /// The number of sectors in the partition.
+    pub fn sectors(&self) -> u32 {
+        self.sectors
+    }
+}
+
 /// The master boot record (MBR).
 #[repr(C, packed)]
 pub struct MasterBootRecord {
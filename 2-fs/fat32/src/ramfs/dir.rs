@@ -0,0 +1,65 @@
+use std::ffi::OsStr;
+use std::io;
+use std::vec;
+
+use ramfs::{Entry, Metadata, RamFs};
+use ramfs::ramfs::NodeId;
+use traits;
+use vfat::Shared;
+
+#[derive(Debug)]
+pub struct Dir {
+    pub name: String,
+    pub metadata: Metadata,
+    fs: Shared<RamFs>,
+    id: NodeId,
+}
+
+impl Dir {
+    pub(crate) fn new(fs: Shared<RamFs>, name: String, metadata: Metadata, id: NodeId) -> Dir {
+        Dir { name, metadata, fs, id }
+    }
+
+    /// The node of the directory.
+    pub(crate) fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Finds the entry named `name` in `self`. Comparison is
+    /// case-insensitive, using Unicode simple case folding.
+    ///
+    /// # Errors
+    ///
+    /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
+    /// is returned. If no entry with name `name` exists in `self` or `self`
+    /// has been removed, an error of `NotFound` is returned.
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
+        let name = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid UTF-8 in name"))?;
+        let fs = self.fs.borrow();
+        match fs.child(self.id, name)? {
+            Some((name, id)) => fs.entry(&self.fs, name.to_string(), id),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+        }
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    /// Returns the files and directories of `self` in the order they were
+    /// added. There are no `.` and `..` entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if `self` has been removed.
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let fs = self.fs.borrow();
+        let entries = fs.children(self.id)?
+            .iter()
+            .map(|&(ref name, id)| fs.entry(&self.fs, name.clone(), id))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(entries.into_iter())
+    }
+}
@@ -0,0 +1,140 @@
+use std::cmp::min;
+use std::io::{self, SeekFrom};
+
+use ramfs::{Metadata, RamFs};
+use ramfs::ramfs::{Contents, NodeId};
+use traits;
+use vfat::Shared;
+
+#[derive(Debug)]
+pub struct File {
+    pub name: String,
+    /// The file's metadata as of when it was opened or last written through
+    /// this handle.
+    pub metadata: Metadata,
+    fs: Shared<RamFs>,
+    id: NodeId,
+    offset: u64,
+}
+
+impl File {
+    pub(crate) fn new(fs: Shared<RamFs>, name: String, metadata: Metadata, id: NodeId) -> File {
+        File { name, metadata, fs, id, offset: 0 }
+    }
+
+    /// The node of the file.
+    pub(crate) fn id(&self) -> NodeId {
+        self.id
+    }
+}
+
+impl traits::File for File {
+    /// Writes take effect immediately; there is nothing to write back.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the current size of the file, including writes through other
+    /// handles. Removed files keep the size last seen through `self`.
+    fn size(&self) -> u64 {
+        use traits::Metadata;
+        match self.fs.borrow().node(self.id) {
+            Ok(node) => node.metadata.len(),
+            Err(_) => self.metadata.len(),
+        }
+    }
+}
+
+impl io::Read for File {
+    /// Reads from the current offset into `buf`. Reading at or beyond the
+    /// end of the file returns `0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if the file has been removed.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let fs = self.fs.borrow();
+        let data = match fs.node(self.id)?.contents {
+            Contents::File(ref data) => data,
+            Contents::Dir(_) => return Err(io::Error::new(io::ErrorKind::Other, "not a regular file")),
+        };
+
+        let start = min(self.offset, data.len() as u64) as usize;
+        let len = min(buf.len(), data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.offset += len as u64;
+        Ok(len)
+    }
+}
+
+impl io::Write for File {
+    /// Writes `buf` at the current offset, overwriting existing data and
+    /// extending the file as needed. If the offset is beyond the end of the
+    /// file, the gap is filled with zeroes first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if the file has been removed and
+    /// of kind `InvalidInput` if the file would not fit in memory's address
+    /// space.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = match self.offset.checked_add(buf.len() as u64) {
+            Some(end) if end <= ::std::usize::MAX as u64 => end as usize,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large")),
+        };
+
+        let mut fs = self.fs.borrow_mut();
+        let now = fs.now();
+        let node = fs.node_mut(self.id)?;
+        let len = match node.contents {
+            Contents::File(ref mut data) => {
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[self.offset as usize..end].copy_from_slice(buf);
+                data.len()
+            }
+            Contents::Dir(_) => return Err(io::Error::new(io::ErrorKind::Other, "not a regular file")),
+        };
+
+        node.metadata.set_len(len as u64);
+        node.metadata.touch(now);
+        self.metadata = node.metadata;
+        self.offset = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
+
+impl io::Seek for File {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// Seeking to or beyond the end of the file is allowed: reads there
+    /// return no data and writes fill the gap with zeroes.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of the file or to an offset that overflows
+    /// results in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = min(traits::File::size(self), ::std::i64::MAX as u64) as i64;
+        let seek_offset = match pos {
+            SeekFrom::Current(offset) => (self.offset as i64).checked_add(offset),
+            SeekFrom::End(offset) => size.checked_add(offset),
+            SeekFrom::Start(offset) if offset <= ::std::i64::MAX as u64 => Some(offset as i64),
+            SeekFrom::Start(_) => None,
+        };
+
+        self.offset = match seek_offset {
+            Some(offset) if offset >= 0 => offset as u64,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        };
+        Ok(self.offset)
+    }
+}
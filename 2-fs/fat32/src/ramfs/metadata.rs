@@ -0,0 +1,162 @@
+use std::fmt;
+
+use traits;
+use vfat::Timestamp;
+
+const READ_ONLY: u8 = 0x01;
+const HIDDEN: u8 = 0x02;
+const SYSTEM: u8 = 0x04;
+const DIRECTORY: u8 = 0x10;
+const ARCHIVE: u8 = 0x20;
+
+/// Metadata of a file or directory in a `RamFs`.
+///
+/// The attributes are those of FAT. Timestamps keep their full 10 ms
+/// resolution; nothing is rounded as on disk.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Metadata {
+    attributes: u8,
+    created: Timestamp,
+    accessed: Timestamp,
+    modified: Timestamp,
+    len: u64,
+}
+
+impl Metadata {
+    /// Returns metadata for a new file or, if `directory` is `true`, a new
+    /// directory, created at `timestamp`.
+    pub(crate) fn new(directory: bool, timestamp: Timestamp) -> Metadata {
+        Metadata {
+            attributes: if directory { DIRECTORY } else { ARCHIVE },
+            created: timestamp,
+            accessed: timestamp,
+            modified: timestamp,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn set_len(&mut self, len: u64) {
+        self.len = len;
+    }
+
+    /// Records a modification at `timestamp` and sets the archive bit.
+    pub(crate) fn touch(&mut self, timestamp: Timestamp) {
+        self.modified = timestamp;
+        self.accessed = timestamp;
+        self.set_archive(true);
+    }
+
+    /// Whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.attributes & DIRECTORY != 0
+    }
+
+    fn set_attribute(&mut self, attribute: u8, value: bool) {
+        if value {
+            self.attributes |= attribute;
+        } else {
+            self.attributes &= !attribute;
+        }
+    }
+
+    /// Sets or clears the read-only attribute.
+    pub fn set_read_only(&mut self, value: bool) {
+        self.set_attribute(READ_ONLY, value);
+    }
+
+    /// Sets or clears the hidden attribute.
+    pub fn set_hidden(&mut self, value: bool) {
+        self.set_attribute(HIDDEN, value);
+    }
+
+    /// Sets or clears the system attribute.
+    pub fn set_system(&mut self, value: bool) {
+        self.set_attribute(SYSTEM, value);
+    }
+
+    /// Sets or clears the archive attribute.
+    pub fn set_archive(&mut self, value: bool) {
+        self.set_attribute(ARCHIVE, value);
+    }
+
+    pub fn set_created(&mut self, timestamp: Timestamp) {
+        self.created = timestamp;
+    }
+
+    pub fn set_accessed(&mut self, timestamp: Timestamp) {
+        self.accessed = timestamp;
+    }
+
+    pub fn set_modified(&mut self, timestamp: Timestamp) {
+        self.modified = timestamp;
+    }
+
+    /// Copies the read-only, hidden, system and archive attributes and the
+    /// timestamps of `other` into `self`, leaving the entry's type and length
+    /// untouched.
+    pub(crate) fn update_from(&mut self, other: &Metadata) {
+        const SETTABLE: u8 = READ_ONLY | HIDDEN | SYSTEM | ARCHIVE;
+        self.attributes = (self.attributes & !SETTABLE) | (other.attributes & SETTABLE);
+        self.created = other.created;
+        self.accessed = other.accessed;
+        self.modified = other.modified;
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes & READ_ONLY != 0
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes & HIDDEN != 0
+    }
+
+    fn system(&self) -> bool {
+        self.attributes & SYSTEM != 0
+    }
+
+    fn archive(&self) -> bool {
+        self.attributes & ARCHIVE != 0
+    }
+
+    /// A `RamFs` has no volume label; entries are never labels.
+    fn is_volume_label(&self) -> bool {
+        false
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        use traits::Metadata;
+        f.debug_struct("Metadata")
+            .field("directory", &self.is_dir())
+            .field("read_only", &self.read_only())
+            .field("hidden", &self.hidden())
+            .field("system", &self.system())
+            .field("archive", &self.archive())
+            .field("len", &self.len())
+            .field("created", &self.created())
+            .field("accessed", &self.accessed())
+            .field("modified", &self.modified())
+            .finish()
+    }
+}
@@ -0,0 +1,11 @@
+pub(crate) mod dir;
+pub(crate) mod entry;
+pub(crate) mod file;
+pub(crate) mod metadata;
+pub(crate) mod ramfs;
+
+pub use self::dir::Dir;
+pub use self::entry::Entry;
+pub use self::file::File;
+pub use self::metadata::Metadata;
+pub use self::ramfs::RamFs;
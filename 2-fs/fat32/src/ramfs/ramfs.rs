@@ -0,0 +1,375 @@
+extern crate hashbrown;
+
+use std::io;
+use std::path::{Component, Path};
+
+use self::hashbrown::HashMap;
+
+use ramfs::{Dir, Entry, File, Metadata};
+use traits::FileSystem;
+use vfat::{name, Shared, SystemClock, Timestamp, TimeSource};
+
+/// Identifies a file or directory of a `RamFs` for as long as it exists.
+/// Identifiers are never reused, so handles to removed entries cannot
+/// alias new ones.
+pub(crate) type NodeId = u64;
+
+/// The node of the root directory.
+pub(crate) const ROOT: NodeId = 0;
+
+#[derive(Debug)]
+pub(crate) enum Contents {
+    File(Vec<u8>),
+    /// The names and nodes of the directory's entries, in the order they
+    /// were added.
+    Dir(Vec<(String, NodeId)>),
+}
+
+/// A file or directory.
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub metadata: Metadata,
+    /// The directory holding the node; the root directory is its own parent.
+    pub parent: NodeId,
+    pub contents: Contents,
+}
+
+/// A file system held entirely in memory, e.g. for a kernel's `/tmp` or for
+/// tests.
+///
+/// Names behave as on FAT: they are matched ignoring case and must be valid
+/// long file names. Writes to files are visible to every handle of the file
+/// at once; there is nothing to sync.
+#[derive(Debug)]
+pub struct RamFs {
+    nodes: HashMap<NodeId, Node>,
+    next_id: NodeId,
+    time_source: Box<dyn TimeSource>,
+}
+
+impl RamFs {
+    /// Returns a new, empty file system.
+    pub fn new() -> Shared<RamFs> {
+        let mut fs = RamFs { nodes: HashMap::new(), next_id: ROOT + 1, time_source: Box::new(SystemClock) };
+        let root = Node {
+            metadata: Metadata::new(true, fs.now()),
+            parent: ROOT,
+            contents: Contents::Dir(Vec::new()),
+        };
+        fs.nodes.insert(ROOT, root);
+        Shared::new(fs)
+    }
+
+    /// Sets the clock used to stamp entries as they are created and
+    /// modified, replacing the host's system clock.
+    pub fn set_time_source<T: TimeSource + 'static>(&mut self, source: T) {
+        self.time_source = Box::new(source);
+    }
+
+    /// Returns the current time according to the file system's time source,
+    /// or the FAT epoch if the time is not known.
+    pub fn now(&self) -> Timestamp {
+        self.time_source.now().unwrap_or(Timestamp::EPOCH)
+    }
+
+    /// The total length of the files in the file system, in bytes.
+    pub fn used_space(&self) -> u64 {
+        self.nodes.values()
+            .map(|node| match node.contents {
+                Contents::File(ref data) => data.len() as u64,
+                Contents::Dir(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the node `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if the node has been removed.
+    pub(crate) fn node(&self, id: NodeId) -> io::Result<&Node> {
+        self.nodes.get(&id).ok_or_else(removed)
+    }
+
+    pub(crate) fn node_mut(&mut self, id: NodeId) -> io::Result<&mut Node> {
+        self.nodes.get_mut(&id).ok_or_else(removed)
+    }
+
+    /// Returns the entries of the directory `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if `dir` has been removed and of
+    /// kind `Other` if it is not a directory.
+    pub(crate) fn children(&self, dir: NodeId) -> io::Result<&[(String, NodeId)]> {
+        match self.node(dir)?.contents {
+            Contents::Dir(ref children) => Ok(children),
+            Contents::File(_) => Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+        }
+    }
+
+    /// Returns the name and node of the entry of `dir` named `name`, ignoring
+    /// case, if there is one.
+    pub(crate) fn child(&self, dir: NodeId, name: &str) -> io::Result<Option<(&str, NodeId)>> {
+        Ok(self.children(dir)?
+            .iter()
+            .find(|&&(ref child, _)| name::eq_ignore_case(child, name))
+            .map(|&(ref child, id)| (&child[..], id)))
+    }
+
+    /// Returns an entry for the node `id` named `name`.
+    pub(crate) fn entry(&self, fs: &Shared<RamFs>, name: String, id: NodeId) -> io::Result<Entry> {
+        let node = self.node(id)?;
+        Ok(match node.contents {
+            Contents::File(_) => Entry::File(File::new(fs.clone(), name, node.metadata, id)),
+            Contents::Dir(_) => Entry::Dir(Dir::new(fs.clone(), name, node.metadata, id)),
+        })
+    }
+
+    /// Adds an entry named `name` to `parent` holding a new node with
+    /// metadata `metadata` and contents `contents`, and returns the new node.
+    fn insert(&mut self, parent: NodeId, name: &str, metadata: Metadata, contents: Contents) -> io::Result<NodeId> {
+        let id = self.next_id;
+        match self.node_mut(parent)?.contents {
+            Contents::Dir(ref mut children) => children.push((name.to_string(), id)),
+            Contents::File(_) => return Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+        }
+        self.nodes.insert(id, Node { metadata, parent, contents });
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Removes the entry for the node `id` from its parent directory and
+    /// returns its index there.
+    fn unlink(&mut self, id: NodeId) -> io::Result<usize> {
+        let parent = self.node(id)?.parent;
+        match self.node_mut(parent)?.contents {
+            Contents::Dir(ref mut children) => {
+                let index = children.iter()
+                    .position(|&(_, child)| child == id)
+                    .ok_or_else(removed)?;
+                children.remove(index);
+                Ok(index)
+            }
+            Contents::File(_) => Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+        }
+    }
+
+    /// Frees the node `id` and, if it is a directory, all nodes below it.
+    fn free(&mut self, id: NodeId) {
+        if let Some(node) = self.nodes.remove(&id) {
+            if let Contents::Dir(children) = node.contents {
+                for (_, child) in children {
+                    self.free(child);
+                }
+            }
+        }
+    }
+
+    /// Whether the node `id` is `ancestor` or lies below it.
+    fn is_within(&self, mut id: NodeId, ancestor: NodeId) -> io::Result<bool> {
+        loop {
+            if id == ancestor {
+                return Ok(true);
+            }
+            if id == ROOT {
+                return Ok(false);
+            }
+            id = self.node(id)?.parent;
+        }
+    }
+}
+
+fn removed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "entry was removed")
+}
+
+fn invalid_input(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Splits the absolute path `path` into its parent and its final component.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `path` is not absolute, has no
+/// final component (e.g. `/` or `/a/..`) or its final component is not valid
+/// UTF-8.
+fn split_path(path: &Path) -> io::Result<(&Path, &str)> {
+    if !path.is_absolute() {
+        return Err(invalid_input("path must be absolute"));
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let name = name.to_str().ok_or(invalid_input("invalid UTF-8 in path"))?;
+            Ok((parent, name))
+        }
+        _ => Err(invalid_input("path has no final component")),
+    }
+}
+
+impl Shared<RamFs> {
+    /// Sets the read-only, hidden, system and archive attributes and the
+    /// timestamps of the entry at `path` to those of `metadata`. The entry's
+    /// type and length are left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `path` is the root
+    /// directory.
+    pub fn set_metadata<P: AsRef<Path>>(&self, path: P, metadata: &Metadata) -> io::Result<()> {
+        let id = self.open(path)?.id();
+        if id == ROOT {
+            return Err(invalid_input("the root directory has no metadata"));
+        }
+        self.borrow_mut().node_mut(id)?.metadata.update_from(metadata);
+        Ok(())
+    }
+
+    /// Opens the directory at `path` for use as the parent of a new entry.
+    /// Failures are reported as `InvalidInput`.
+    fn parent_dir(&self, path: &Path) -> io::Result<Dir> {
+        use traits::Entry;
+        self.open(path)
+            .map_err(|_| invalid_input("parent directory does not exist"))?
+            .into_dir()
+            .ok_or(invalid_input("parent is not a directory"))
+    }
+}
+
+impl<'a> FileSystem for &'a Shared<RamFs> {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(invalid_input("path must be absolute"));
+        }
+
+        let components: Vec<_> = path.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                Component::ParentDir => Some("..".as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let fs = self.borrow();
+        // The directories leading to `current`, to resolve `..` with.
+        let mut ancestors = Vec::new();
+        let mut current = (String::new(), ROOT);
+        for (i, &name) in components.iter().enumerate() {
+            if !fs.node(current.1)?.metadata.is_dir() {
+                return Err(invalid_input("path component is not a directory"));
+            }
+            if name == ".." {
+                // The root directory is its own parent.
+                current = ancestors.pop().unwrap_or((String::new(), ROOT));
+                continue;
+            }
+
+            let name = name.to_str().ok_or(invalid_input("invalid UTF-8 in path"))?;
+            let child = match fs.child(current.1, name)? {
+                Some((name, id)) => (name.to_string(), id),
+                None if i + 1 < components.len() => {
+                    return Err(invalid_input("path component does not exist"));
+                }
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+            };
+            ancestors.push(current);
+            current = child;
+        }
+        fs.entry(self, current.0, current.1)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (parent, name) = split_path(path.as_ref())?;
+        let dir = self.parent_dir(parent)?;
+        let mut fs = self.borrow_mut();
+        if fs.child(dir.id(), name)?.is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+        name::validate(name)?;
+
+        let metadata = Metadata::new(false, fs.now());
+        let id = fs.insert(dir.id(), name, metadata, Contents::File(Vec::new()))?;
+        Ok(File::new(self.clone(), name.to_string(), metadata, id))
+    }
+
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
+        where P: AsRef<Path>
+    {
+        let (parent, name) = split_path(path.as_ref())?;
+        let dir = if parents && parent.parent().is_some() {
+            // Creates the missing ancestors first. One that already exists
+            // must be a directory.
+            match self.create_dir(parent, true) {
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => self.parent_dir(parent)?,
+                result => result?,
+            }
+        } else {
+            self.parent_dir(parent)?
+        };
+        let mut fs = self.borrow_mut();
+        if fs.child(dir.id(), name)?.is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+        name::validate(name)?;
+
+        let metadata = Metadata::new(true, fs.now());
+        let id = fs.insert(dir.id(), name, metadata, Contents::Dir(Vec::new()))?;
+        Ok(Dir::new(self.clone(), name.to_string(), metadata, id))
+    }
+
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path>, Q: AsRef<Path>
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        split_path(from)?;
+        let (to_parent, to_name) = split_path(to)?;
+        name::validate(to_name)?;
+
+        let id = self.open(from)?.id();
+        if id == ROOT {
+            return Err(invalid_input("cannot rename the root directory"));
+        }
+        match self.open(to) {
+            Ok(ref existing) if existing.id() != id => {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+            }
+            _ => {}
+        }
+
+        let dir = self.parent_dir(to_parent)?.id();
+        let mut fs = self.borrow_mut();
+        if fs.is_within(dir, id)? {
+            return Err(invalid_input("cannot move a directory into itself"));
+        }
+
+        // An entry renamed within its directory keeps its place there.
+        let index = fs.unlink(id)?;
+        let index = if fs.node(id)?.parent == dir { index } else { fs.children(dir)?.len() };
+        if let Contents::Dir(ref mut children) = fs.node_mut(dir)?.contents {
+            children.insert(index, (to_name.to_string(), id));
+        }
+        fs.node_mut(id)?.parent = dir;
+        Ok(())
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        use traits::Entry;
+
+        split_path(path.as_ref())?;
+        let entry = self.open(path)?;
+        if entry.is_dir() && !children {
+            return Err(io::Error::new(io::ErrorKind::Other, "entry is a directory"));
+        }
+
+        let mut fs = self.borrow_mut();
+        fs.unlink(entry.id())?;
+        fs.free(entry.id());
+        Ok(())
+    }
+}
@@ -1,11 +1,13 @@
 extern crate rand;
 
 use std::io::prelude::*;
-use std::io::Cursor;
+use std::io::{Cursor, SeekFrom};
 use std::path::Path;
 
-use vfat::{Shared, VFat, BiosParameterBlock};
+use vfat::{Shared, VFat, BiosParameterBlock, FatType};
 use mbr::{MasterBootRecord, CHS, PartitionEntry};
+use device::{FileBlockDevice, MemBlockDevice, PartitionDevice};
+use vfat::format::put;
 use traits::*;
 
 macro check_size($T:ty, $size:expr) {
@@ -349,3 +351,1811 @@ fn shared_fs_is_sync_send_static() {
     fn f<T: Sync + Send + 'static>() {  }
     f::<Shared<VFat>>();
 }
+
+/// A block device backed by an in-memory image that outlives the `VFat`
+/// mounted on it, so tests can remount the image after writing to it.
+#[derive(Clone)]
+struct SharedImage(MemBlockDevice);
+
+impl SharedImage {
+    fn new(data: Vec<u8>) -> SharedImage {
+        SharedImage(MemBlockDevice::from_vec(data, 512))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn remount(&self) -> Shared<VFat> {
+        VFat::from(self.clone()).expect("failed to remount image")
+    }
+}
+
+impl BlockDevice for SharedImage {
+    fn sector_count(&self) -> Option<u64> {
+        self.0.sector_count()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        self.0.read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> ::std::io::Result<usize> {
+        self.0.write_sector(n, buf)
+    }
+}
+
+/// Builds a FAT32 image with 512-byte sectors and clusters, one partition
+/// starting at sector 1 and 1000 data clusters. Each of `files` is a raw 8.3
+/// name and its contents, stored contiguously in the root directory.
+fn fat32_image(files: &[(&str, &[u8])]) -> SharedImage {
+    fat_image(FatType::Fat32, files)
+}
+
+/// Builds an image with an MBR and a single FAT partition at sector 1 with
+/// 512-byte sectors and clusters whose root directory holds `files`, each
+/// stored contiguously after the previous one. FAT32 volumes have 1000
+/// clusters and the root directory in cluster 2; FAT16 volumes have 4100
+/// clusters, FAT12 volumes 1000, both with a 512-entry root directory.
+fn fat_image(fat_type: FatType, files: &[(&str, &[u8])]) -> SharedImage {
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        put(data, offset, &value.to_le_bytes());
+    }
+
+    // (partition type, reserved sectors, sectors per FAT, root entries, clusters)
+    let (partition_type, reserved, per_fat, root_entries, clusters) = match fat_type {
+        FatType::Fat12 => (0x01, 1, 3, 512, 1000),
+        FatType::Fat16 => (0x06, 1, 17, 512, 4100),
+        FatType::Fat32 => (0x0C, 32, 8, 0, 1000),
+    };
+    let root_sectors = root_entries * 32 / 512;
+
+    const PART: usize = 512;
+    let fat_start = PART + reserved * 512;
+    let root_start = fat_start + 2 * per_fat * 512;
+    let data_start = root_start + root_sectors * 512;
+    let total_sectors = (reserved + 2 * per_fat + root_sectors + clusters) as u32;
+    let mut data = vec![0u8; PART + total_sectors as usize * 512];
+
+    // MBR: a single FAT partition.
+    data[446 + 4] = partition_type;
+    put_u32(&mut data, 446 + 8, 1);
+    put_u32(&mut data, 446 + 12, total_sectors);
+    put(&mut data, 510, &[0x55, 0xAA]);
+
+    // BPB, followed by the FAT32 or FAT12/16 extended fields.
+    put(&mut data, PART, &[0xEB, 0x58, 0x90]);
+    put(&mut data, PART + 3, b"MSWIN4.1");
+    put(&mut data, PART + 11, &512u16.to_le_bytes());
+    data[PART + 13] = 1;
+    put(&mut data, PART + 14, &(reserved as u16).to_le_bytes());
+    data[PART + 16] = 2;
+    put(&mut data, PART + 17, &(root_entries as u16).to_le_bytes());
+    data[PART + 21] = 0xF8;
+    put(&mut data, PART + 510, &[0x55, 0xAA]);
+    let mut fat = match fat_type {
+        FatType::Fat32 => {
+            put_u32(&mut data, PART + 32, total_sectors);
+            put_u32(&mut data, PART + 36, per_fat as u32);
+            put_u32(&mut data, PART + 44, 2);
+            data[PART + 66] = 0x29;
+            put(&mut data, PART + 82, b"FAT32   ");
+            vec![0x0FFFFFF8, 0x0FFFFFFF, 0x0FFFFFFF]
+        }
+        _ => {
+            put(&mut data, PART + 19, &(total_sectors as u16).to_le_bytes());
+            put(&mut data, PART + 22, &(per_fat as u16).to_le_bytes());
+            data[PART + 38] = 0x29;
+            put(&mut data, PART + 54, if fat_type == FatType::Fat12 { b"FAT12   " } else { b"FAT16   " });
+            vec![0x0FFFFFF8, 0x0FFFFFFF]
+        }
+    };
+
+    let root = if fat_type == FatType::Fat32 { data_start } else { root_start };
+    for (i, &(name, contents)) in files.iter().enumerate() {
+        let clusters = (contents.len() + 511) / 512;
+        let start = if clusters == 0 { 0 } else { fat.len() as u32 };
+        for c in 0..clusters {
+            let next = if c + 1 == clusters { 0x0FFFFFFF } else { fat.len() as u32 + 1 };
+            put(&mut data, data_start + (fat.len() - 2) * 512, &contents[c * 512..::std::cmp::min((c + 1) * 512, contents.len())]);
+            fat.push(next);
+        }
+
+        let entry = root + i * 32;
+        put(&mut data, entry, name.as_bytes());
+        put(&mut data, entry + 20, &((start >> 16) as u16).to_le_bytes());
+        put(&mut data, entry + 26, &(start as u16).to_le_bytes());
+        put_u32(&mut data, entry + 28, contents.len() as u32);
+    }
+
+    for copy in 0..2 {
+        let base = fat_start + copy * per_fat * 512;
+        for (i, &value) in fat.iter().enumerate() {
+            match fat_type {
+                FatType::Fat12 => {
+                    let offset = base + i + i / 2;
+                    let value = value as u16 & 0xFFF;
+                    if i % 2 == 0 {
+                        data[offset] = value as u8;
+                        data[offset + 1] = (data[offset + 1] & 0xF0) | (value >> 8) as u8;
+                    } else {
+                        data[offset] = (data[offset] & 0x0F) | ((value << 4) as u8);
+                        data[offset + 1] = (value >> 4) as u8;
+                    }
+                }
+                FatType::Fat16 => put(&mut data, base + i * 2, &(value as u16).to_le_bytes()),
+                FatType::Fat32 => put_u32(&mut data, base + i * 4, value),
+            }
+        }
+    }
+
+    SharedImage::new(data)
+}
+
+fn read_all<T: File>(mut file: T) -> Vec<u8> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).expect("read file");
+    assert_eq!(data.len() as u64, file.size());
+    data
+}
+
+#[test]
+fn test_write_overwrite_in_place() {
+    use vfat::{FixedClock, Timestamp};
+
+    let image = fat32_image(&[("HELLO   TXT", b"hello, world!")]);
+    let vfat = image.remount();
+    let modified = Timestamp::new(2020, 2, 29, 23, 59, 58, 0).unwrap();
+    vfat.borrow_mut().set_time_source(FixedClock(modified));
+
+    let mut file = vfat.open_file("/hello.txt").expect("file exists");
+    assert_eq!(file.write(b"HELLO").unwrap(), 5);
+    file.sync().expect("sync");
+    assert_eq!(file.size(), 13);
+
+    let entry = image.remount().open("/HELLO.TXT").expect("file exists");
+    assert_eq!(entry.metadata().modified(), modified);
+    assert_eq!(read_all(entry.into_file().unwrap()), b"HELLO, world!");
+}
+
+#[test]
+fn test_write_append_grows_chain() {
+    let original: Vec<u8> = (0..600u32).map(|i| i as u8).collect();
+    let appended: Vec<u8> = (0..1500u32).map(|i| (i * 7) as u8).collect();
+    let image = fat32_image(&[("A       BIN", &original), ("B       BIN", b"b")]);
+    let vfat = image.remount();
+
+    let mut file = vfat.open_file("/a.bin").expect("file exists");
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 600);
+    file.write_all(&appended).expect("append");
+    file.flush().expect("flush");
+
+    let vfat = image.remount();
+    let mut expected = original.clone();
+    expected.extend_from_slice(&appended);
+    assert_eq!(read_all(vfat.open_file("/a.bin").unwrap()), expected);
+    assert_eq!(read_all(vfat.open_file("/b.bin").unwrap()), b"b");
+}
+
+#[test]
+fn test_write_empty_file() {
+    let image = fat32_image(&[("EMPTY      ", b"")]);
+    let vfat = image.remount();
+
+    let mut file = vfat.open_file("/empty").expect("file exists");
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 0);
+    file.write_all(&[0xAB; 1025]).expect("write");
+    file.sync().expect("sync");
+
+    let file = image.remount().open_file("/empty").unwrap();
+    assert_eq!(read_all(file), vec![0xAB; 1025]);
+}
+
+fn entry_names<T: Dir>(dir: T) -> Vec<String> {
+    let mut names: Vec<String> = dir.entries()
+        .expect("entries interator")
+        .map(|entry| entry.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+fn expect_error_kind<T: ::std::fmt::Debug>(result: ::std::io::Result<T>, kind: ::std::io::ErrorKind) {
+    match result {
+        Err(ref e) if e.kind() == kind => {}
+        o => panic!("expected error of kind {:?} but found {:?}", kind, o),
+    }
+}
+
+#[test]
+fn test_short_name_generation() {
+    use vfat::name::{exact_short_name, generate_short_name};
+
+    assert_eq!(&exact_short_name("README.TXT").unwrap(), b"README  TXT");
+    assert_eq!(&exact_short_name("KERNEL").unwrap(), b"KERNEL     ");
+    assert!(exact_short_name("readme.txt").is_none());
+    assert!(exact_short_name("LONGNAME1.TXT").is_none());
+    assert!(exact_short_name("A.TEXT").is_none());
+
+    let first = generate_short_name("long file name.txt", &[]).unwrap();
+    assert_eq!(&first, b"LONGFI~1TXT");
+    let second = generate_short_name("long file name.txt", &[first]).unwrap();
+    assert_eq!(&second, b"LONGFI~2TXT");
+    assert_eq!(&generate_short_name(".bashrc", &[]).unwrap(), b"BASHRC~1   ");
+    assert_eq!(&generate_short_name("a+b.tar.gz", &[]).unwrap(), b"A_BTAR~1GZ ");
+}
+
+#[test]
+fn test_create_file_and_dir() {
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+
+    let mut file = vfat.create_file("/Long File Name.txt").expect("create file");
+    file.write_all(b"contents").unwrap();
+    file.sync().unwrap();
+    vfat.create_file("/SHORT.TXT").expect("create file");
+    vfat.create_dir("/a/b/c", true).expect("create dirs");
+
+    let vfat = image.remount();
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["Long File Name.txt", "SHORT.TXT", "a"]);
+    assert_eq!(entry_names(vfat.open_dir("/a/b").unwrap()), vec![".", "..", "c"]);
+    assert_eq!(entry_names(vfat.open_dir("/a/b/c").unwrap()), vec![".", ".."]);
+    assert_eq!(entry_names(vfat.open_dir("/a/b/c/../..").unwrap()), vec![".", "..", "b"]);
+    assert_eq!(entry_names(vfat.open_dir("/a/..").unwrap()).len(), 3);
+    assert_eq!(read_all(vfat.open_file("/long file name.TXT").unwrap()), b"contents");
+
+    expect_error_kind(vfat.create_file("/short.txt"), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(vfat.create_dir("/a", false), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(vfat.create_dir("/x/y", false), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.create_file("/SHORT.TXT/f"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.create_dir("/SHORT.TXT/d", true), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.create_dir("/SHORT.TXT/d/e", true), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.create_dir("/a/b/c", true), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(vfat.create_file("relative"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.create_file("/bad:name"), ::std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_create_extends_directory() {
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    vfat.create_dir("/dir", false).unwrap();
+
+    let mut expected = vec![".".to_string(), "..".to_string()];
+    for i in 0..40 {
+        let name = format!("a rather long file name {}", i);
+        vfat.create_file(format!("/dir/{}", name)).expect("create file");
+        expected.push(name);
+    }
+    expected.sort();
+
+    assert_eq!(entry_names(image.remount().open_dir("/dir").unwrap()), expected);
+
+    // The slots of two removed files straddle the first two clusters and
+    // are reused for a name taking as many entries.
+    let vfat = image.remount();
+    let dir_cluster = vfat.open_dir("/dir").unwrap().cluster();
+    let clusters = vfat.borrow_mut().chain(dir_cluster).unwrap().len();
+    vfat.remove("/dir/a rather long file name 4", false).unwrap();
+    vfat.remove("/dir/a rather long file name 5", false).unwrap();
+    let name = format!("/dir/{}", "n".repeat(60));
+    let file = vfat.create_file(&name).unwrap();
+    assert_eq!(file.position().map(|position| (position.first, position.index)), Some((14, 19)));
+    assert_eq!(vfat.borrow_mut().chain(dir_cluster).unwrap().len(), clusters);
+}
+
+#[test]
+fn test_rename() {
+    let image = fat32_image(&[("HELLO   TXT", b"hello")]);
+    let vfat = image.remount();
+    vfat.create_dir("/src/inner", true).unwrap();
+    vfat.create_dir("/dst", false).unwrap();
+
+    vfat.rename("/HELLO.TXT", "/dst/greeting.txt").expect("rename file");
+    vfat.rename("/src/inner", "/dst/moved").expect("rename dir");
+    vfat.rename("/dst", "/DST").expect("rename changing case");
+
+    let vfat = image.remount();
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["DST", "src"]);
+    assert_eq!(entry_names(vfat.open_dir("/src").unwrap()), vec![".", ".."]);
+    assert_eq!(entry_names(vfat.open_dir("/dst").unwrap()), vec![".", "..", "greeting.txt", "moved"]);
+    assert_eq!(read_all(vfat.open_file("/dst/greeting.txt").unwrap()), b"hello");
+    assert_eq!(entry_names(vfat.open_dir("/dst/moved/..").unwrap()), vec![".", "..", "greeting.txt", "moved"]);
+
+    expect_error_kind(vfat.rename("/missing", "/x"), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(vfat.rename("/src", "/dst"), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(vfat.rename("/dst", "/dst/moved/x"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(vfat.rename("/", "/x"), ::std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_remove() {
+    let data = vec![7u8; 4000];
+    let image = fat32_image(&[("BIG     BIN", &data)]);
+    let vfat = image.remount();
+    vfat.create_dir("/d/e", true).unwrap();
+    vfat.create_file("/d/e/file").unwrap().write_all(&data).unwrap();
+
+    expect_error_kind(vfat.remove("/d", false), ::std::io::ErrorKind::Other);
+    expect_error_kind(vfat.remove("/nope", false), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(vfat.remove("/", true), ::std::io::ErrorKind::InvalidInput);
+    vfat.remove("/BIG.BIN", false).expect("remove file");
+    vfat.remove("/d", true).expect("remove dir");
+
+    let vfat = image.remount();
+    assert!(entry_names(vfat.open_dir("/").unwrap()).is_empty());
+    expect_error_kind(vfat.open("/d/e"), ::std::io::ErrorKind::InvalidInput);
+
+    // All 999 clusters besides the root directory are free again.
+    let mut file = vfat.create_file("/fill").unwrap();
+    file.write_all(&vec![1u8; 999 * 512]).expect("fill volume");
+    expect_error_kind(file.write(&[1]), ::std::io::ErrorKind::Other);
+}
+
+#[test]
+fn test_cache_eviction_and_write_back() {
+    use vfat::{CachedDevice, CacheStats, Partition};
+
+    let mut data = vec![0u8; 512 * 8];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i / 512) as u8;
+    }
+    let image = SharedImage::new(data);
+    let partition = Partition { start: 0, sector_size: 512 };
+    let mut cache = CachedDevice::with_capacity(image.clone(), partition, 2);
+
+    assert_eq!(cache.get(0).unwrap()[0], 0);
+    assert_eq!(cache.get(1).unwrap()[0], 1);
+    assert_eq!(cache.get(0).unwrap()[0], 0);
+    cache.get_mut(2).unwrap()[0] = 0xAA;
+    cache.get_mut(3).unwrap()[0] = 0xBB;
+    assert_eq!(cache.get(2).unwrap()[0], 0xAA);
+    assert_eq!(cache.get(4).unwrap()[0], 4);
+    assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 5, evictions: 3, write_backs: 1, bypassed: 0, prefetched: 0 });
+
+    // Only the evicted dirty sector has reached the device so far.
+    let mut sector = [0u8; 512];
+    image.clone().read_sector(2, &mut sector).unwrap();
+    assert_eq!(sector[0], 0xAA);
+    image.clone().read_sector(3, &mut sector).unwrap();
+    assert_eq!(sector[0], 3);
+
+    drop(cache);
+    image.clone().read_sector(3, &mut sector).unwrap();
+    assert_eq!(sector[0], 0xBB);
+}
+
+#[test]
+fn test_cache_shrink() {
+    use vfat::{CachedDevice, CacheStats, Partition};
+
+    let mut data = vec![0u8; 512 * 8];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i / 512) as u8;
+    }
+    let image = SharedImage::new(data);
+    let partition = Partition { start: 0, sector_size: 512 };
+    let mut cache = CachedDevice::with_capacity(image.clone(), partition, 4);
+    expect_error_kind(cache.set_capacity(0), ::std::io::ErrorKind::InvalidInput);
+    assert_eq!(cache.capacity(), 4);
+
+    for sector in 0..5 {
+        cache.get(sector).unwrap();
+    }
+    cache.get_mut(3).unwrap()[0] = 0xCC;
+    assert_eq!(cache.stats().evictions, 1);
+
+    // The hand clears 1 and 2 on the way to filling the cache with 4, so
+    // they are evicted; the recently used 3 and 4 stay cached.
+    cache.set_capacity(2).unwrap();
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(cache.get(3).unwrap()[0], 0xCC);
+    assert_eq!(cache.get(4).unwrap()[0], 4);
+    assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 5, evictions: 3, write_backs: 0, bypassed: 0, prefetched: 0 });
+
+    // Shrinking does not flush sectors that stay cached.
+    let mut sector = [0u8; 512];
+    image.clone().read_sector(3, &mut sector).unwrap();
+    assert_eq!(sector[0], 3);
+}
+
+#[test]
+fn test_small_cache_capacity() {
+    let contents: Vec<u8> = (0..20000u32).map(|i| (i % 251) as u8).collect();
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    vfat.borrow_mut().set_cache_capacity(2).unwrap();
+
+    vfat.create_dir("/a/b", true).unwrap();
+    let mut file = vfat.create_file("/a/b/data").unwrap();
+    file.write_all(&contents).unwrap();
+    file.sync().unwrap();
+    assert!(vfat.borrow().cache_stats().evictions > 0);
+
+    let vfat = image.remount();
+    vfat.borrow_mut().set_cache_capacity(1).unwrap();
+    assert_eq!(read_all(vfat.open_file("/a/b/data").unwrap()), contents);
+}
+
+#[test]
+fn test_mount_any_partition() {
+    let mut data = fat32_image(&[("HELLO   TXT", b"hello")]).bytes();
+    let entry: Vec<u8> = data[446..462].to_vec();
+    for byte in &mut data[446..462] { *byte = 0; }
+    data[446 + 16 + 4] = 0x83;
+    data[446 + 32..446 + 48].copy_from_slice(&entry);
+
+    let vfat = VFat::from(SharedImage::new(data.clone())).expect("mount partition 2");
+    assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+
+    let vfat = VFat::from_partition(SharedImage::new(data.clone()), 2).expect("mount partition 2");
+    assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+
+    expect_variant!(VFat::from_partition(SharedImage::new(data.clone()), 0),
+                    Err(::vfat::Error::NoPartition(0)));
+    expect_variant!(VFat::from_partition(SharedImage::new(data.clone()), 1),
+                    Err(::vfat::Error::UnsupportedPartitionType(0x83)));
+    expect_variant!(VFat::from_partition(SharedImage::new(data.clone()), 4),
+                    Err(::vfat::Error::NoPartition(4)));
+
+    data[446 + 32 + 4] = 0x07;
+    expect_variant!(VFat::from(SharedImage::new(data)), Err(::vfat::Error::NotFound));
+}
+
+#[test]
+fn test_mount_superfloppy() {
+    let data = fat32_image(&[("HELLO   TXT", b"hello")]).bytes()[512..].to_vec();
+
+    let vfat = VFat::from(SharedImage::new(data.clone())).expect("mount superfloppy");
+    assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+
+    let vfat = VFat::from_raw(SharedImage::new(data)).expect("mount superfloppy");
+    vfat.create_file("/new").unwrap().write_all(b"new").unwrap();
+    assert_eq!(read_all(vfat.open_file("/new").unwrap()), b"new");
+
+    expect_variant!(VFat::from(SharedImage::new(vec![0; 4096])), Err(::vfat::Error::Mbr(_)));
+}
+
+#[test]
+fn test_fat12_and_fat16() {
+    let big: Vec<u8> = (0..3000u32).map(|i| (i * 13) as u8).collect();
+    for &fat_type in &[FatType::Fat12, FatType::Fat16] {
+        let image = fat_image(fat_type, &[("HELLO   TXT", b"hello"), ("BIG     BIN", &big)]);
+        let vfat = image.remount();
+        assert_eq!(vfat.borrow().fat_type(), fat_type);
+        assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+        assert_eq!(read_all(vfat.open_file("/big.bin").unwrap()), &big[..]);
+
+        // Growing a file allocates across odd and even FAT12 entries.
+        let appended: Vec<u8> = (0..2000u32).map(|i| (i * 3) as u8).collect();
+        let mut file = vfat.open_file("/hello.txt").unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(&appended).unwrap();
+        drop(file);
+
+        vfat.create_dir("/sub/dir", true).expect("create nested dirs");
+        vfat.create_file("/sub/dir/a long file name.txt").unwrap().write_all(b"nested").unwrap();
+        vfat.remove("/big.bin", false).expect("remove");
+
+        let vfat = image.remount();
+        let mut expected = b"hello".to_vec();
+        expected.extend_from_slice(&appended);
+        assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), expected);
+        assert_eq!(read_all(vfat.open_file("/sub/dir/a long file name.txt").unwrap()), b"nested");
+        assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["HELLO.TXT", "sub"]);
+        assert_eq!(vfat.open_dir("/sub/dir/../..").unwrap().entries().unwrap().count(), 2);
+        expect_error_kind(vfat.open("/big.bin"), ::std::io::ErrorKind::NotFound);
+    }
+}
+
+#[test]
+fn test_fixed_root_directory_full() {
+    let image = fat_image(FatType::Fat16, &[]);
+    let vfat = image.remount();
+    for i in 0..512 {
+        vfat.create_file(format!("/F{}", i)).expect("root has room");
+    }
+    expect_error_kind(vfat.create_file("/F512"), ::std::io::ErrorKind::Other);
+    assert_eq!(image.remount().open_dir("/").unwrap().entries().unwrap().count(), 512);
+}
+
+/// Wraps `volume` in a GPT disk with 512-byte sectors: one Microsoft basic
+/// data partition named "data" starting at sector 64, and primary and backup
+/// headers and partition arrays of 128 entries.
+fn gpt_image(volume: &[u8]) -> Vec<u8> {
+    use util::crc32;
+
+    let volume_sectors = (volume.len() as u64 + 511) / 512;
+    let last_lba = 64 + volume_sectors + 32;
+    let mut data = vec![0u8; (last_lba as usize + 1) * 512];
+
+    // Protective MBR covering the whole disk.
+    data[446 + 4] = 0xEE;
+    put(&mut data, 446 + 8, &1u32.to_le_bytes());
+    put(&mut data, 446 + 12, &(last_lba as u32).to_le_bytes());
+    put(&mut data, 510, &[0x55, 0xAA]);
+
+    let mut entries = vec![0u8; 128 * 128];
+    put(&mut entries, 0, &::gpt::Guid::MICROSOFT_BASIC_DATA.0);
+    put(&mut entries, 16, &[0x42; 16]);
+    put(&mut entries, 32, &64u64.to_le_bytes());
+    put(&mut entries, 40, &(64 + volume_sectors - 1).to_le_bytes());
+    for (i, c) in "data".encode_utf16().enumerate() {
+        put(&mut entries, 56 + i * 2, &c.to_le_bytes());
+    }
+    put(&mut data, 2 * 512, &entries);
+    put(&mut data, (last_lba as usize - 32) * 512, &entries);
+    put(&mut data, 64 * 512, volume);
+
+    for &(lba, backup, entries_lba) in &[(1, last_lba, 2), (last_lba, 1, last_lba - 32)] {
+        let mut header = vec![0u8; 92];
+        put(&mut header, 0, b"EFI PART");
+        put(&mut header, 8, &0x00010000u32.to_le_bytes());
+        put(&mut header, 12, &92u32.to_le_bytes());
+        put(&mut header, 24, &lba.to_le_bytes());
+        put(&mut header, 32, &backup.to_le_bytes());
+        put(&mut header, 40, &34u64.to_le_bytes());
+        put(&mut header, 48, &(last_lba - 33).to_le_bytes());
+        put(&mut header, 56, &[0x17; 16]);
+        put(&mut header, 72, &entries_lba.to_le_bytes());
+        put(&mut header, 80, &128u32.to_le_bytes());
+        put(&mut header, 84, &128u32.to_le_bytes());
+        put(&mut header, 88, &crc32(&entries).to_le_bytes());
+        let crc = crc32(&header);
+        put(&mut header, 16, &crc.to_le_bytes());
+        put(&mut data, lba as usize * 512, &header);
+    }
+
+    data
+}
+
+#[test]
+fn test_crc32_and_guid() {
+    assert_eq!(::util::crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(::gpt::Guid::MICROSOFT_BASIC_DATA.to_string(), "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7");
+    assert_eq!(::gpt::Guid::EFI_SYSTEM.to_string(), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+}
+
+#[test]
+fn test_gpt() {
+    use gpt::{Guid, GuidPartitionTable};
+
+    let volume = fat32_image(&[("HELLO   TXT", b"hello")]).bytes()[512..].to_vec();
+    let mut data = gpt_image(&volume);
+
+    let gpt = GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("valid GPT");
+    assert!(!gpt.used_backup);
+    assert_eq!(gpt.disk_guid, Guid([0x17; 16]));
+    let partitions: Vec<_> = gpt.partitions().collect();
+    assert_eq!(partitions.len(), 1);
+    assert_eq!(partitions[0].0, 0);
+    assert_eq!(partitions[0].1.name, "data");
+    assert_eq!(partitions[0].1.type_guid, Guid::MICROSOFT_BASIC_DATA);
+    assert_eq!(partitions[0].1.first_lba, 64);
+    assert_eq!(partitions[0].1.sectors(), volume.len() as u64 / 512);
+
+    let vfat = VFat::from(SharedImage::new(data.clone())).expect("mount GPT partition");
+    assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+    let vfat = VFat::from_gpt_partition(SharedImage::new(data.clone()), 0).expect("mount GPT partition");
+    assert_eq!(read_all(vfat.open_file("/hello.txt").unwrap()), b"hello");
+    expect_variant!(VFat::from_gpt_partition(SharedImage::new(data.clone()), 1),
+                    Err(::vfat::Error::NoPartition(1)));
+
+    // The backup header is found through the primary header or, if that is
+    // damaged, in the last sector of the device, not by the MBR's size.
+    let mut moved = data.clone();
+    let mbr_sectors = (data.len() / 512 - 11) as u32;
+    moved[446 + 12..446 + 16].copy_from_slice(&mbr_sectors.to_le_bytes());
+    moved[2 * 512 + 60] ^= 0xFF;
+    assert!(GuidPartitionTable::from(Cursor::new(&mut moved[..])).expect("backup GPT").used_backup);
+    moved[512 + 60] ^= 0xFF;
+    assert!(GuidPartitionTable::from(SharedImage::new(moved.clone())).expect("backup GPT").used_backup);
+    expect_variant!(GuidPartitionTable::from(Cursor::new(&mut moved[..])),
+                    Err(::gpt::Error::BadHeaderCrc));
+
+    // Damage the primary partition array: the backup copy is used.
+    data[2 * 512 + 60] ^= 0xFF;
+    let gpt = GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("valid backup GPT");
+    assert!(gpt.used_backup);
+    assert_eq!(gpt.get_partition(0).unwrap().name, "data");
+    VFat::from(SharedImage::new(data.clone())).expect("mount from backup GPT");
+
+    // Damage the backup header too.
+    let last = data.len() - 512;
+    data[last + 60] ^= 0xFF;
+    expect_variant!(GuidPartitionTable::from(Cursor::new(&mut data[..])),
+                    Err(::gpt::Error::BadEntriesCrc));
+    data[512 + 60] ^= 0xFF;
+    expect_variant!(VFat::from(SharedImage::new(data)),
+                    Err(::vfat::Error::Gpt(::gpt::Error::BadHeaderCrc)));
+}
+
+#[test]
+fn test_format_and_mount() {
+    let image = SharedImage::new(vec![0; 8 << 20]);
+    let mut options = ::vfat::FormatOptions::new(8 << 11);
+    options.volume_label = Some("my disk".to_string());
+    options.volume_id = Some(0x1234ABCD);
+    ::vfat::format(image.clone(), &options).expect("format");
+
+    let data = image.bytes();
+    let start = 2048 * 512;
+    assert_eq!(data[446 + 4], 0x0C);
+    assert_eq!(&data[446 + 8..446 + 12], &2048u32.to_le_bytes());
+    assert_eq!(&data[start + 67..start + 82], b"\xCD\xAB\x34\x12MY DISK    ");
+    assert_eq!(&data[start..start + 512], &data[start + 6 * 512..start + 7 * 512]);
+    assert_eq!(&data[start + 512..start + 516], b"RRaA");
+
+    let vfat = image.remount();
+    assert_eq!(vfat.borrow().fat_type(), FatType::Fat32);
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["MY DISK"]);
+    vfat.create_dir("/dir", false).unwrap();
+    vfat.create_file("/dir/hello.txt").unwrap().write_all(b"hello").unwrap();
+
+    let vfat = image.remount();
+    assert_eq!(read_all(vfat.open_file("/dir/hello.txt").unwrap()), b"hello");
+}
+
+#[test]
+fn test_format_options() {
+    let image = SharedImage::new(vec![0; 4 << 20]);
+    let mut options = ::vfat::FormatOptions::new(4 << 11);
+    options.partition_table = false;
+    options.cluster_size = Some(4096);
+    ::vfat::format(image.clone(), &options).expect("format superfloppy");
+    assert_eq!(&image.bytes()[510..512], &[0x55, 0xAA]);
+
+    let vfat = VFat::from_raw(image.clone()).expect("mount superfloppy");
+    assert_eq!(vfat.borrow().bytes_per_cluster(), 4096);
+    assert_eq!(vfat.open_dir("/").unwrap().entries().unwrap().count(), 0);
+    let contents: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+    vfat.create_file("/big").unwrap().write_all(&contents).unwrap();
+    assert_eq!(read_all(image.remount().open_file("/big").unwrap()), contents);
+
+    let invalid = |options: &::vfat::FormatOptions| {
+        let result = ::vfat::format(SharedImage::new(vec![0; 4 << 20]), options);
+        expect_error_kind(result, ::std::io::ErrorKind::InvalidInput);
+    };
+    let mut options = ::vfat::FormatOptions::new(4 << 11);
+    options.cluster_size = Some(3000);
+    invalid(&options);
+    options.cluster_size = Some(128 << 10);
+    invalid(&options);
+    options.cluster_size = None;
+    options.volume_label = Some("bad/label".to_string());
+    invalid(&options);
+    invalid(&::vfat::FormatOptions::new(32));
+}
+
+#[test]
+fn test_check_and_repair() {
+    use vfat::{check, Problem};
+
+    const FAT: usize = 512 + 32 * 512;
+    const FAT_2: usize = FAT + 8 * 512;
+    const DATA: usize = FAT + 16 * 512;
+
+    let image = fat32_image(&[("A       BIN", &[1; 600]), ("B       BIN", &[2; 100]), ("C       BIN", &[3; 100])]);
+    let report = check(&image.remount(), false).expect("check");
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!((report.files, report.directories), (3, 1));
+
+    let mut data = image.bytes();
+    for &fat in &[FAT, FAT_2] {
+        // A lost cluster, B's chain extended by a cluster, C cross-linked
+        // into B.
+        put(&mut data, fat + 100 * 4, &0x0FFFFFFFu32.to_le_bytes());
+        put(&mut data, fat + 5 * 4, &7u32.to_le_bytes());
+        put(&mut data, fat + 7 * 4, &0x0FFFFFFFu32.to_le_bytes());
+        put(&mut data, fat + 6 * 4, &5u32.to_le_bytes());
+    }
+    put(&mut data, FAT_2 + 200 * 4, &0x0FFFFFFFu32.to_le_bytes());
+    // An orphaned LFN entry.
+    put(&mut data, DATA + 3 * 32, &[0x41, b'x', 0]);
+    data[DATA + 3 * 32 + 11] = 0x0F;
+    let image = SharedImage::new(data);
+
+    let report = check(&image.remount(), false).expect("check");
+    let problems: Vec<Problem> = report.findings.iter().map(|f| f.problem.clone()).collect();
+    assert_eq!(problems, vec![
+        Problem::FatMismatch { copy: 1, entries: 1 },
+        Problem::ChainLength { path: "/B.BIN".to_string(), size: 100, clusters: 2, expected: 1 },
+        Problem::CrossLinked { first: "/B.BIN".to_string(), second: "/C.BIN".to_string(), cluster: 5 },
+        Problem::BadLfn { path: "/".to_string(), first: 3, last: 3 },
+        Problem::LostChain { start: 100, clusters: 1 },
+    ]);
+    assert!(report.findings.iter().all(|f| !f.repaired));
+
+    let report = check(&image.remount(), true).expect("repair");
+    assert_eq!(report.findings.len(), 5);
+    assert!(report.all_repaired());
+
+    let vfat = image.remount();
+    assert!(check(&vfat, false).unwrap().is_clean());
+    assert_eq!(read_all(vfat.open_file("/B.BIN").unwrap()), &[2; 100][..]);
+    assert_eq!(read_all(vfat.open_file("/C.BIN").unwrap()), &[3; 100][..]);
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["A.BIN", "B.BIN", "C.BIN"]);
+
+    // Reports name entries as listings do, in the volume's code page.
+    let image = fat32_image(&[("\u{5}E      BIN", &[4; 100])]);
+    let mut data = image.bytes();
+    for &fat in &[FAT, FAT_2] {
+        put(&mut data, fat + 3 * 4, &4u32.to_le_bytes());
+        put(&mut data, fat + 4 * 4, &0x0FFFFFFFu32.to_le_bytes());
+    }
+    let vfat = SharedImage::new(data).remount();
+    vfat.borrow_mut().set_code_page(::vfat::CodePage::Cp850);
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["ÕE.BIN"]);
+    let report = check(&vfat, false).expect("check");
+    assert_eq!(report.findings.len(), 1);
+    assert_eq!(report.findings[0].problem,
+               Problem::ChainLength { path: "/ÕE.BIN".to_string(), size: 100, clusters: 2, expected: 1 });
+}
+
+#[test]
+fn test_check_dot_entries() {
+    use vfat::{check, Problem};
+
+    const DATA: usize = 512 + 32 * 512 + 16 * 512;
+
+    let image = fat32_image(&[]);
+    image.remount().create_dir("/sub", false).expect("create dir");
+    assert!(check(&image.remount(), false).unwrap().is_clean());
+
+    let mut data = image.bytes();
+    data[DATA + 512 + 32 + 26] = 9;
+    let image = SharedImage::new(data);
+    let report = check(&image.remount(), true).expect("repair");
+    assert_eq!(report.findings.len(), 1);
+    assert_eq!(report.findings[0].problem, Problem::BadDotEntries { path: "/sub".to_string() });
+    assert!(report.findings[0].repaired);
+    assert!(check(&image.remount(), false).unwrap().is_clean());
+}
+
+#[test]
+fn test_fs_info_free_space() {
+    let image = SharedImage::new(vec![0; 4 << 20]);
+    let mut options = ::vfat::FormatOptions::new(4 << 11);
+    options.partition_table = false;
+    ::vfat::format(image.clone(), &options).expect("format");
+    let fs_info_free = |image: &SharedImage| {
+        let data = image.bytes();
+        (u32::from_le_bytes([data[512 + 488], data[512 + 489], data[512 + 490], data[512 + 491]]),
+         u32::from_le_bytes([data[512 + 492], data[512 + 493], data[512 + 494], data[512 + 495]]))
+    };
+
+    let vfat = image.remount();
+    let total = vfat.borrow().total_space();
+    let bytes_per_cluster = vfat.borrow().bytes_per_cluster() as u64;
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), total - bytes_per_cluster);
+    let (free, next) = fs_info_free(&image);
+    assert_eq!((free as u64 * bytes_per_cluster, next), (total - bytes_per_cluster, 3));
+
+    vfat.create_file("/file").unwrap().write_all(&[7; 1500]).unwrap();
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), total - 4 * bytes_per_cluster);
+    assert_eq!(fs_info_free(&image), (free - 3, 6));
+
+    let vfat = image.remount();
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), total - 4 * bytes_per_cluster);
+    vfat.remove("/file", false).unwrap();
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), total - bytes_per_cluster);
+    assert_eq!(fs_info_free(&image), (free, 6));
+
+    // New clusters are taken from the hint onwards.
+    vfat.create_file("/next").unwrap().write_all(b"x").unwrap();
+    let entry = vfat.open("/next").unwrap();
+    assert_eq!(entry.metadata().start_cluster(), 6);
+}
+
+#[test]
+fn test_free_space_without_fs_info() {
+    let image = fat32_image(&[("A       BIN", &[1; 1100])]);
+    let vfat = image.remount();
+    assert_eq!(vfat.borrow().total_space(), 1000 * 512);
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), (1000 - 4) * 512);
+    vfat.remove("/a.bin", false).unwrap();
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), (1000 - 1) * 512);
+}
+
+#[test]
+fn test_fat_mirroring() {
+    use vfat::FatCopyMismatch;
+
+    const FAT: usize = 512 + 32 * 512;
+    const FAT_2: usize = FAT + 8 * 512;
+
+    let image = fat32_image(&[("A       BIN", &[1; 600])]);
+    let mut data = image.bytes();
+    data[FAT_2 + 50 * 4] = 0xFF;
+    let image = SharedImage::new(data);
+
+    let vfat = image.remount();
+    assert!(vfat.borrow().is_mirroring());
+    assert_eq!(vfat.borrow_mut().verify_fat_copies().unwrap(), vec![
+        FatCopyMismatch { copy: 1, first_cluster: 50, entries: 1 },
+    ]);
+    vfat.borrow_mut().sync_fat_copies().unwrap();
+    assert!(vfat.borrow_mut().verify_fat_copies().unwrap().is_empty());
+
+    vfat.create_file("/b").unwrap().write_all(&[2; 1500]).unwrap();
+    let data = image.bytes();
+    assert_eq!(&data[FAT..FAT + 8 * 512], &data[FAT_2..FAT_2 + 8 * 512]);
+    assert!(image.remount().borrow_mut().verify_fat_copies().unwrap().is_empty());
+}
+
+#[test]
+fn test_fat_mirroring_disabled() {
+    const FAT: usize = 512 + 32 * 512;
+    const FAT_2: usize = FAT + 8 * 512;
+
+    // Only the second FAT is active and holds the file's chain.
+    let image = fat32_image(&[("A       BIN", &[1; 600])]);
+    let mut data = image.bytes();
+    data[512 + 40] = 0x81;
+    for byte in &mut data[FAT..FAT + 8 * 512] {
+        *byte = 0;
+    }
+    let image = SharedImage::new(data);
+
+    let vfat = image.remount();
+    assert!(!vfat.borrow().is_mirroring());
+    assert_eq!(vfat.borrow().active_fat(), 1);
+    assert_eq!(read_all(vfat.open_file("/a.bin").unwrap()), &[1; 600][..]);
+    assert!(vfat.borrow_mut().verify_fat_copies().unwrap().is_empty());
+
+    vfat.create_file("/b").unwrap().write_all(&[2; 600]).unwrap();
+    let data = image.bytes();
+    assert!(data[FAT..FAT + 8 * 512].iter().all(|&b| b == 0));
+    assert_eq!(&data[FAT_2 + 5 * 4..FAT_2 + 7 * 4], &[6, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0x0F]);
+    assert_eq!(read_all(image.remount().open_file("/b").unwrap()), &[2; 600][..]);
+}
+
+#[test]
+fn test_streaming_dir_entries() {
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    vfat.create_dir("/big", false).unwrap();
+    let names: Vec<String> = (0..60).map(|i| format!("a rather long file name {:02}.text", i)).collect();
+    for name in &names {
+        vfat.create_file(format!("/big/{}", name)).unwrap();
+    }
+
+    // Each entry set takes three entries and so sets straddle the 16-entry
+    // clusters.
+    let vfat = image.remount();
+    let dir = vfat.open_dir("/big").unwrap();
+    let misses = vfat.borrow().cache_stats().misses;
+    assert_eq!(dir.entries().unwrap().next().unwrap().name(), ".");
+    assert!(vfat.borrow().cache_stats().misses - misses <= 2);
+
+    let mut listed = entry_names(dir);
+    listed.retain(|name| name != "." && name != "..");
+    assert_eq!(listed, names);
+    for name in &names {
+        let entry = vfat.open(format!("/big/{}", name)).expect("find entry");
+        assert_eq!(entry.name(), &name[..]);
+    }
+}
+
+#[test]
+fn test_corrupted_chains_and_names() {
+    const FAT: usize = 512 + 32 * 512;
+    const FAT_2: usize = FAT + 8 * 512;
+    const DATA: usize = FAT + 16 * 512;
+
+    let image = fat32_image(&[("A       BIN", &[1; 1100]), ("B       BIN", &[2; 10]), ("C       BIN", &[3; 600])]);
+    let mut data = image.bytes();
+    for &fat in &[FAT, FAT_2] {
+        // A's chain loops back onto itself; C's ends after one cluster.
+        data[fat + 5 * 4..fat + 6 * 4].copy_from_slice(&3u32.to_le_bytes());
+        data[fat + 7 * 4..fat + 8 * 4].copy_from_slice(&0u32.to_le_bytes());
+    }
+    // B starts past the end of the volume.
+    data[DATA + 32 + 26..DATA + 32 + 28].copy_from_slice(&5000u16.to_le_bytes());
+    // An LFN entry with sequence number 0, a non-ASCII short name and a
+    // directory pointing past the volume.
+    data[DATA + 3 * 32] = 0x40;
+    data[DATA + 3 * 32 + 11] = 0x0F;
+    data[DATA + 4 * 32..DATA + 4 * 32 + 11].copy_from_slice(b"\xFFBAD    TXT");
+    data[DATA + 5 * 32..DATA + 5 * 32 + 11].copy_from_slice(b"SUB        ");
+    data[DATA + 5 * 32 + 11] = 0x10;
+    data[DATA + 5 * 32 + 26..DATA + 5 * 32 + 28].copy_from_slice(&5000u16.to_le_bytes());
+    let vfat = SharedImage::new(data).remount();
+
+    let mut buf = vec![0; 1100];
+    assert!(vfat.open_file("/B.BIN").unwrap().read_exact(&mut buf[..10]).is_err());
+    assert!(vfat.open_file("/C.BIN").unwrap().read_exact(&mut buf[..600]).is_err());
+    let mut a = vfat.open_file("/A.BIN").unwrap();
+    assert!(a.seek(SeekFrom::Current(-2000)).is_err());
+    assert!(a.seek(SeekFrom::End(::std::i64::MAX)).is_err());
+    assert!(vfat.remove("/A.BIN", false).is_err());
+    assert_eq!(entry_names(vfat.open_dir("/").unwrap()), vec!["A.BIN", "B.BIN", "C.BIN", "SUB", "\u{A0}BAD.TXT"]);
+    assert!(vfat.open_dir("/SUB").and_then(|dir| dir.entries().map(|_| ())).is_err());
+
+    // A subdirectory whose second cluster is past the end of the volume: the
+    // error is reported, not taken for the end of the directory, and
+    // removing the directory changes nothing.
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    vfat.create_dir("/SUB", false).unwrap();
+    for i in 0..20 {
+        let mut file = vfat.create_file(format!("/SUB/F{}.BIN", i)).unwrap();
+        file.write_all(&[i as u8]).unwrap();
+        file.sync().unwrap();
+    }
+    let sub = vfat.open_dir("/SUB").unwrap().cluster().id() as usize;
+    let mut data = image.bytes();
+    for &fat in &[FAT, FAT_2] {
+        data[fat + sub * 4..fat + sub * 4 + 4].copy_from_slice(&5000u32.to_le_bytes());
+    }
+    let image = SharedImage::new(data);
+    let vfat = image.remount();
+    let mut entries = vfat.open_dir("/SUB").unwrap().entries().unwrap();
+    assert_eq!(entries.by_ref().count(), 16);
+    assert!(entries.take_error().is_err());
+    let error = vfat.open("/SUB/F19.BIN").err().expect("read error");
+    assert_ne!(error.kind(), ::std::io::ErrorKind::NotFound);
+    let (before, free) = (image.bytes(), vfat.borrow_mut().free_space().unwrap());
+    assert!(vfat.remove("/SUB", true).is_err());
+    vfat.borrow_mut().flush().unwrap();
+    assert!(image.bytes() == before);
+    assert_eq!(vfat.borrow_mut().free_space().unwrap(), free);
+
+    let mut data = image.bytes();
+    data[512 + 13] = 3;
+    match VFat::from(SharedImage::new(data)) {
+        Err(::vfat::Error::BadBpb(_)) => {}
+        other => panic!("expected BadBpb, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_corrupted_and_random_images() {
+    use tests::rand::{Rng, SeedableRng, XorShiftRng};
+
+    let mut rng = XorShiftRng::from_seed([0x243F6A88, 0x85A308D3, 0x13198A2E, 0x03707344]);
+
+    let base = fat32_image(&[]);
+    {
+        let vfat = base.remount();
+        vfat.create_dir("/dir", false).unwrap();
+        vfat.create_file("/dir/a long file name.txt").unwrap().write_all(&[7; 1500]).unwrap();
+        vfat.create_file("/SHORT.BIN").unwrap().write_all(&[8; 600]).unwrap();
+    }
+    let images = vec![
+        base.bytes(),
+        fat_image(FatType::Fat16, &[("A       BIN", &[1; 700])]).bytes(),
+        fat_image(FatType::Fat12, &[("A       BIN", &[1; 700])]).bytes(),
+    ];
+
+    for i in 0..300 {
+        let mut data = images[i % images.len()].clone();
+        // Most mutations target the boot sector, FATs and first directory
+        // clusters, where a single flipped byte matters most.
+        let limit = ::std::cmp::min(data.len(), 512 + 64 * 512);
+        for _ in 0..rng.gen_range(1, 32) {
+            let offset = if rng.gen_weighted_bool(4) { rng.gen_range(0, data.len()) } else { rng.gen_range(0, limit) };
+            data[offset] = rng.gen();
+        }
+        ::fuzz::exercise_image(&data);
+    }
+
+    for _ in 0..100 {
+        let mut data = vec![0u8; 64 * 512];
+        rng.fill_bytes(&mut data);
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        data[446 + 8..446 + 12].copy_from_slice(&[1, 0, 0, 0]);
+        data[512 + 510] = 0x55;
+        data[512 + 511] = 0xAA;
+        ::fuzz::exercise_image(&data);
+    }
+}
+
+#[test]
+fn test_mem_and_partition_devices() {
+    let mut disk = MemBlockDevice::new(64, 512);
+    assert_eq!(disk.sectors(), 64);
+    disk.write_sector(3, &[7; 512]).unwrap();
+    let mut buf = [0; 512];
+    assert_eq!(disk.clone().read_sector(3, &mut buf).unwrap(), 512);
+    assert_eq!(&buf[..], &[7; 512][..]);
+    assert!(disk.read_sector(64, &mut buf).is_err());
+    assert!(disk.write_sector(64, &buf).is_err());
+    assert!(disk.write_sector(0, &buf[..100]).is_err());
+
+    let mut part = PartitionDevice::new(disk.clone(), 4, 60).unwrap();
+    part.write_sector(0, &[9; 512]).unwrap();
+    assert_eq!(&disk.to_vec()[4 * 512..5 * 512], &[9; 512][..]);
+    assert!(part.read_sector(60, &mut buf).is_err());
+    assert!(PartitionDevice::new(disk.clone(), ::std::u64::MAX, 2).is_err());
+
+    let mut options = ::vfat::FormatOptions::new(60);
+    options.partition_table = false;
+    ::vfat::format(&mut part, &options).expect("format partition");
+    let vfat = VFat::from_raw(part).expect("mount partition");
+    vfat.create_file("/a.txt").unwrap().write_all(b"in a partition").unwrap();
+    ::std::mem::drop(vfat);
+
+    let vfat = VFat::from_raw(PartitionDevice::new(disk, 4, 60).unwrap()).unwrap();
+    assert_eq!(read_all(vfat.open_file("/a.txt").unwrap()), b"in a partition");
+}
+
+#[test]
+fn test_file_block_device() {
+    let path = ::std::env::temp_dir().join(format!("fat32-test-{}.img", ::std::process::id()));
+    let mut device = FileBlockDevice::create(&path, 2048, 4096).expect("create image");
+    assert_eq!((device.sector_size(), device.sectors()), (4096, 2048));
+    assert!(FileBlockDevice::open(&path, 1000).is_err());
+
+    ::vfat::format(&mut device, &::vfat::FormatOptions::new(2048)).expect("format");
+    let vfat = VFat::from(device).expect("mount");
+    assert_eq!(vfat.borrow().bytes_per_sector, 4096);
+    vfat.create_file("/big.bin").unwrap().write_all(&[5; 10000]).unwrap();
+    ::std::mem::drop(vfat);
+
+    let mut device = FileBlockDevice::open_read_only(&path, 4096).unwrap();
+    assert!(device.write_sector(0, &[0; 4096]).is_err());
+    assert!(device.read_sector(2048, &mut [0; 4096]).is_err());
+    let vfat = VFat::from(device).expect("remount");
+    assert_eq!(read_all(vfat.open_file("/big.bin").unwrap()), &[5; 10000][..]);
+    ::std::mem::drop(vfat);
+    ::std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cluster_chain() {
+    let image = fat32_image(&[("A       BIN", &[1; 1100]), ("EMPTY      ", &[])]);
+    let vfat = image.remount();
+    assert_eq!(vfat.borrow().volume_start(), 1);
+    assert_eq!(vfat.cluster_chain("/A.BIN").unwrap(), vec![3, 4, 5]);
+    assert_eq!(vfat.cluster_chain("/EMPTY").unwrap(), Vec::<u32>::new());
+    assert_eq!(vfat.cluster_chain("/").unwrap(), vec![2]);
+    assert!(vfat.cluster_chain("/missing").is_err());
+
+    let vfat = fat_image(FatType::Fat16, &[]).remount();
+    assert_eq!(vfat.cluster_chain("/").unwrap(), Vec::<u32>::new());
+}
+
+#[test]
+fn test_metadata_and_volume_label() {
+    use traits::Metadata;
+
+    let image = SharedImage::new(vec![0; 4 << 20]);
+    let mut options = ::vfat::FormatOptions::new(4 << 11);
+    options.volume_label = Some("Photos".to_string());
+    options.volume_id = Some(0x1234ABCD);
+    ::vfat::format(image.clone(), &options).expect("format");
+
+    let vfat = image.remount();
+    assert_eq!(vfat.borrow_mut().volume_label().unwrap(), Some("PHOTOS".to_string()));
+    assert_eq!(vfat.borrow().volume_id(), Some(0x1234ABCD));
+    let label = vfat.open("/PHOTOS").unwrap();
+    assert!(label.metadata().is_volume_label());
+
+    vfat.create_dir("/dir", false).unwrap();
+    vfat.create_file("/dir/a.txt").unwrap().write_all(&[1; 1234]).unwrap();
+    let vfat = image.remount();
+    let file = vfat.open("/dir/a.txt").unwrap();
+    let metadata = *file.metadata();
+    assert_eq!(metadata.len(), 1234);
+    assert!(metadata.archive() && !metadata.system() && !metadata.is_volume_label());
+    assert_eq!(vfat.open("/dir").unwrap().metadata().len(), 0);
+
+    let mut changed = metadata;
+    changed.set_read_only(true);
+    changed.set_hidden(true);
+    changed.set_system(true);
+    changed.set_archive(false);
+    changed.set_created(vfat.open("/dir").unwrap().metadata().created());
+    vfat.set_metadata("/dir/a.txt", &changed).unwrap();
+    assert!(vfat.set_metadata("/", &changed).is_err());
+
+    let vfat = image.remount();
+    let metadata = *vfat.open("/dir/a.txt").unwrap().metadata();
+    assert!(metadata.read_only() && metadata.hidden() && metadata.system() && !metadata.archive());
+    assert_eq!((metadata.len(), metadata.start_cluster()), (1234, changed.start_cluster()));
+    assert_eq!(read_all(vfat.open_file("/dir/a.txt").unwrap()), &[1; 1234][..]);
+
+    // Without a label entry, the boot sector's label is used: at offset 43
+    // on FAT12/16 volumes, which the test images leave blank.
+    let vfat = fat32_image(&[]).remount();
+    assert_eq!(vfat.borrow_mut().volume_label().unwrap(), None);
+    assert_eq!(vfat.borrow().volume_id(), Some(0));
+    let mut data = fat_image(FatType::Fat16, &[]).bytes();
+    data[512 + 43..512 + 54].copy_from_slice(b"OLD LABEL  ");
+    let vfat = SharedImage::new(data).remount();
+    assert_eq!(vfat.borrow_mut().volume_label().unwrap(), Some("OLD LABEL".to_string()));
+}
+
+#[test]
+fn test_timestamps() {
+    use vfat::Timestamp;
+
+    let ts = Timestamp::new(2019, 2, 28, 13, 37, 59, 456).expect("valid timestamp");
+    assert_eq!((ts.year(), ts.month(), ts.day()), (2019, 2, 28));
+    assert_eq!((ts.hour(), ts.minute(), ts.second(), ts.millisecond()), (13, 37, 59, 450));
+    assert_eq!(Timestamp::from_dos(ts.to_dos().0, ts.to_dos().1, ts.to_dos().2), ts);
+    assert_eq!(ts.truncated().second(), 58);
+    assert_eq!(ts.truncated().millisecond(), 0);
+
+    assert!(Timestamp::new(1979, 12, 31, 0, 0, 0, 0).is_none());
+    assert!(Timestamp::new(2108, 1, 1, 0, 0, 0, 0).is_none());
+    assert!(Timestamp::new(2019, 2, 29, 0, 0, 0, 0).is_none());
+    assert!(Timestamp::new(2020, 2, 29, 0, 0, 0, 0).is_some());
+    assert!(Timestamp::new(2019, 4, 31, 0, 0, 0, 0).is_none());
+    assert!(Timestamp::new(2019, 1, 1, 24, 0, 0, 0).is_none());
+    assert!(Timestamp::new(2019, 1, 1, 0, 0, 0, 1000).is_none());
+
+    assert_eq!(Timestamp::from_unix(315532800, 0), Some(Timestamp::EPOCH));
+    assert_eq!(Timestamp::EPOCH.to_unix(), 315532800);
+    assert!(Timestamp::from_unix(315532799, 0).is_none());
+    let ts = Timestamp::from_unix(1551361079, 456_000_000).unwrap();
+    assert_eq!(ts, Timestamp::new(2019, 2, 28, 13, 37, 59, 456).unwrap());
+    assert_eq!(ts.to_unix(), 1551361079);
+    let last = Timestamp::new(2107, 12, 31, 23, 59, 59, 990).unwrap();
+    assert_eq!(Timestamp::from_unix(last.to_unix(), 990_000_000), Some(last));
+    assert!(Timestamp::from_unix(last.to_unix() + 1, 0).is_none());
+}
+
+#[test]
+fn test_time_source() {
+    use vfat::{FixedClock, Timestamp};
+    use traits::Metadata;
+
+    let image = fat32_image(&[]);
+    let created = Timestamp::new(2019, 6, 18, 10, 20, 31, 250).unwrap();
+    let modified = Timestamp::new(2019, 6, 19, 8, 0, 5, 0).unwrap();
+
+    let vfat = image.remount();
+    vfat.borrow_mut().set_time_source(FixedClock(created));
+    assert_eq!(vfat.borrow().now(), created);
+    vfat.create_dir("/dir", false).unwrap();
+    let mut file = vfat.create_file("/dir/a.txt").unwrap();
+    vfat.borrow_mut().set_time_source(FixedClock(modified));
+    file.write_all(b"hello").unwrap();
+    file.sync().unwrap();
+
+    let vfat = image.remount();
+    let metadata = *vfat.open("/dir/a.txt").unwrap().metadata();
+    assert_eq!(metadata.created(), created);
+    assert_eq!(metadata.modified(), modified.truncated());
+    assert_eq!(metadata.modified().second(), 4);
+    assert_eq!(metadata.accessed(), Timestamp::new(2019, 6, 19, 0, 0, 0, 0).unwrap());
+    assert_eq!(vfat.open("/dir").unwrap().metadata().created(), created);
+}
+
+#[test]
+fn test_find_long_and_short_names() {
+    use vfat::{CodePage, Dir};
+
+    const DATA: usize = 512 + 32 * 512 + 16 * 512;
+
+    let mut data = fat32_image(&[("APFEL   TXT", b"apples"), ("X       BIN", b"x")]).bytes();
+    data[DATA] = 0x8E;
+    data[DATA + 32] = 0x9B;
+    let vfat = SharedImage::new(data).remount();
+    vfat.create_file("/Program Files.txt").unwrap();
+    vfat.create_file("/Ärger.txt").unwrap();
+    vfat.create_dir("/ΣΟΦΟΣ", false).unwrap();
+
+    // Long names and their 8.3 aliases both match, in any case.
+    for path in &["/program files.TXT", "/PROGRA~1.TXT", "/progra~1.txt"] {
+        assert_eq!(vfat.open(path).unwrap().name(), "Program Files.txt");
+    }
+    assert_eq!(vfat.open("/ärger.TXT").unwrap().name(), "Ärger.txt");
+    assert_eq!(vfat.open("/_RGER~1.TXT").unwrap().name(), "Ärger.txt");
+    assert_eq!(vfat.open("/σοφοσ").unwrap().name(), "ΣΟΦΟΣ");
+    assert_eq!(vfat.open("/σοφος").unwrap().name(), "ΣΟΦΟΣ");
+    expect_error_kind(vfat.open("/PROGRA~2.TXT"), ::std::io::ErrorKind::NotFound);
+
+    // Short names are decoded with the configured code page.
+    assert_eq!(read_all(vfat.open_file("/äpfel.txt").unwrap()), b"apples");
+    assert!(vfat.open("/\u{A2}.BIN").is_ok());
+    vfat.borrow_mut().set_code_page(CodePage::Cp850);
+    assert!(vfat.open("/\u{A2}.BIN").is_err());
+    assert!(vfat.open("/Ø.BIN").is_ok());
+    let names = entry_names(Dir::new_root(&vfat));
+    assert!(names.contains(&"ÄPFEL.TXT".to_string()) && names.contains(&"ø.BIN".to_string()));
+
+    assert_eq!(CodePage::from_number(850), Some(CodePage::Cp850));
+    assert_eq!(CodePage::Cp437.encode('Ä'), Some(0x8E));
+    assert_eq!(CodePage::Cp437.decode(0xE1), 'ß');
+    assert_eq!(CodePage::Cp850.encode('€'), None);
+}
+
+#[test]
+fn test_dentry_cache() {
+    use vfat::DentryStats;
+
+    let vfat = fat32_image(&[]).remount();
+    vfat.create_dir("/a/b", true).unwrap();
+    vfat.create_file("/a/b/Long File.txt").unwrap().write_all(b"one").unwrap();
+
+    let start = vfat.borrow().dentry_stats();
+    for _ in 0..3 {
+        assert_eq!(read_all(vfat.open_file("/a/b/long file.TXT").unwrap()), b"one");
+    }
+    let stats = vfat.borrow().dentry_stats();
+    // Creating the file already cached the lookups of its parents.
+    assert_eq!(stats.misses - start.misses, 1);
+    assert_eq!(stats.hits - start.hits, 8);
+
+    // Hits read the entry's current metadata.
+    vfat.open_file("/a/b/Long File.txt").unwrap().write_all(b"two!").unwrap();
+    assert_eq!(vfat.open("/a/b/long file.txt").unwrap().name(), "Long File.txt");
+    assert_eq!(read_all(vfat.open_file("/a/b/LONGFI~1.TXT").unwrap()), b"two!");
+
+    // Renames and removals invalidate lookups of the entries involved.
+    vfat.rename("/a/b/Long File.txt", "/a/moved.txt").unwrap();
+    expect_error_kind(vfat.open("/a/b/long file.txt"), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(vfat.open("/a/b/LONGFI~1.TXT"), ::std::io::ErrorKind::NotFound);
+    assert_eq!(read_all(vfat.open_file("/a/moved.txt").unwrap()), b"two!");
+    vfat.rename("/a/b", "/c").unwrap();
+    expect_error_kind(vfat.open("/a/b"), ::std::io::ErrorKind::NotFound);
+    assert_eq!(vfat.open("/c/..").unwrap().name(), "..");
+    vfat.remove("/a", true).unwrap();
+    expect_error_kind(vfat.open("/a"), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(vfat.open("/a/moved.txt"), ::std::io::ErrorKind::InvalidInput);
+    vfat.create_file("/a").unwrap().write_all(b"new").unwrap();
+    assert_eq!(read_all(vfat.open_file("/a").unwrap()), b"new");
+    assert!(vfat.borrow().dentry_stats().invalidations > 0);
+
+    // The cache is bounded, and can be disabled.
+    vfat.borrow_mut().set_dentry_capacity(2);
+    for name in &["/c", "/a", "/c/.", "/c/.."] {
+        vfat.open(name).unwrap();
+    }
+    assert_eq!(vfat.borrow().dentry_capacity(), 2);
+    assert!(vfat.borrow().dentry_stats().evictions >= 2);
+    vfat.borrow_mut().set_dentry_capacity(0);
+    let before = vfat.borrow().dentry_stats();
+    vfat.open("/c").unwrap();
+    vfat.open("/c").unwrap();
+    let after = vfat.borrow().dentry_stats();
+    assert_eq!(DentryStats { misses: after.misses - 2, ..after }, before);
+}
+
+#[test]
+fn test_deleted_entries_and_undelete() {
+    use vfat::Dir;
+
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    let data: Vec<u8> = (0..1500u32).map(|i| i as u8).collect();
+    vfat.create_file("/A Long Name.bin").unwrap().write_all(&data).unwrap();
+    vfat.create_file("/SHORT.TXT").unwrap().write_all(b"short").unwrap();
+    vfat.create_file("/empty").unwrap();
+    vfat.create_dir("/sub", false).unwrap();
+    vfat.create_file("/sub/child.txt").unwrap().write_all(b"child").unwrap();
+    vfat.create_file("/keep.txt").unwrap().write_all(b"keep").unwrap();
+    vfat.create_dir("/restored", false).unwrap();
+    for path in &["/A Long Name.bin", "/SHORT.TXT", "/empty"] {
+        vfat.remove(path, false).unwrap();
+    }
+    vfat.remove("/sub", true).unwrap();
+
+    let vfat = image.remount();
+    let deleted = Dir::new_root(&vfat).deleted_entries().unwrap();
+    let names: Vec<(&str, bool, u64, bool)> = deleted.iter()
+        .map(|d| (&d.name[..], d.name_complete, d.metadata.len(), d.recoverable))
+        .collect();
+    assert_eq!(names, vec![
+        ("A Long Name.bin", true, 1500, true),
+        ("?HORT.TXT", false, 5, true),
+        ("empty", true, 0, true),
+        ("sub", true, 0, true),
+    ]);
+    assert!(deleted[3].metadata.is_dir());
+
+    // Restore in place and into another directory; the data and its chain
+    // come back.
+    let restored = vfat.undelete(&deleted[0], "/A Long Name.bin").unwrap();
+    assert_eq!(restored.name(), "A Long Name.bin");
+    assert_eq!(read_all(vfat.open_file("/a long name.bin").unwrap()), data);
+    expect_error_kind(vfat.undelete(&deleted[0], "/again.bin"), ::std::io::ErrorKind::NotFound);
+    vfat.undelete(&deleted[1], "/SHORT.TXT").unwrap();
+    vfat.undelete(&deleted[2], "/empty").unwrap();
+    expect_error_kind(vfat.undelete(&deleted[3], "/keep.txt"), ::std::io::ErrorKind::AlreadyExists);
+    vfat.undelete(&deleted[3], "/restored/sub").unwrap();
+    assert_eq!(vfat.open("/restored/sub/..").unwrap().as_dir().unwrap().cluster(),
+               vfat.open_dir("/restored").unwrap().cluster());
+    let children = vfat.open_dir("/restored/sub").unwrap().deleted_entries().unwrap();
+    assert_eq!(children[0].name, "child.txt");
+    vfat.undelete(&children[0], "/restored/sub/child.txt").unwrap();
+
+    let vfat = image.remount();
+    assert_eq!(read_all(vfat.open_file("/SHORT.TXT").unwrap()), b"short");
+    assert_eq!(read_all(vfat.open_file("/empty").unwrap()), b"");
+    assert_eq!(read_all(vfat.open_file("/restored/sub/child.txt").unwrap()), b"child");
+    assert_eq!(read_all(vfat.open_file("/keep.txt").unwrap()), b"keep");
+    assert!(::vfat::check(&vfat, false).unwrap().findings.is_empty());
+
+    // Entries whose clusters were reused cannot be restored.
+    vfat.remove("/SHORT.TXT", false).unwrap();
+    vfat.create_file("/new.txt").unwrap().write_all(&[7; 3000]).unwrap();
+    let deleted = Dir::new_root(&vfat).deleted_entries().unwrap();
+    let short = deleted.iter().find(|d| d.name == "?HORT.TXT").unwrap();
+    assert!(!short.recoverable);
+    expect_error_kind(vfat.undelete(short, "/S.TXT"), ::std::io::ErrorKind::Other);
+}
+
+#[test]
+fn test_fragmentation_and_defragment() {
+    use vfat::{defragment, fragmentation};
+
+    let image = fat32_image(&[]);
+    let vfat = image.remount();
+    vfat.create_dir("/dir", false).unwrap();
+    vfat.create_dir("/dir/sub", false).unwrap();
+    // Interleave two files and grow the directory so that all three end up
+    // fragmented.
+    let mut a = vfat.create_file("/a.bin").unwrap();
+    let mut b = vfat.create_file("/b.bin").unwrap();
+    for i in 0..4u8 {
+        a.write_all(&[i; 512]).unwrap();
+        a.sync().unwrap();
+        b.write_all(&[i + 10; 512]).unwrap();
+        b.sync().unwrap();
+        for j in 0..8 {
+            vfat.create_file(format!("/dir/file {} {}", i, j)).unwrap();
+        }
+    }
+    vfat.create_file("/small.txt").unwrap().write_all(b"small").unwrap();
+    vfat.remove("/b.bin", false).unwrap();
+
+    let report = fragmentation(&vfat).unwrap();
+    let get = |path: &str| report.entries.iter().find(|entry| entry.path == path).unwrap().clone();
+    assert_eq!((get("/a.bin").clusters, get("/a.bin").fragments), (4, 4));
+    assert!(get("/dir").fragments > 1 && get("/dir").is_dir);
+    assert_eq!(get("/small.txt").fragments, 1);
+    assert_eq!(get("/dir/file 0 0").fragments, 0);
+    assert!(report.fragmented().len() == 2 && report.fragmented_percent() > 0);
+    assert!(report.free_runs > 1);
+
+    assert_eq!(defragment(&vfat, "/a.bin").unwrap(), 4);
+    assert_eq!(defragment(&vfat, "/dir").unwrap(), get("/dir").fragments);
+    assert_eq!(defragment(&vfat, "/small.txt").unwrap(), 1);
+    expect_error_kind(defragment(&vfat, "/"), ::std::io::ErrorKind::InvalidInput);
+
+    let vfat = image.remount();
+    let report = fragmentation(&vfat).unwrap();
+    assert!(report.fragmented().is_empty());
+    let chain = vfat.cluster_chain("/a.bin").unwrap();
+    assert!(chain.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    let expected: Vec<u8> = (0..4u8).flat_map(|i| vec![i; 512]).collect();
+    assert_eq!(read_all(vfat.open_file("/a.bin").unwrap()), expected);
+    assert_eq!(entry_names(vfat.open_dir("/dir").unwrap()).len(), 2 + 1 + 32);
+    assert_eq!(vfat.open("/dir/sub/..").unwrap().as_dir().unwrap().cluster(),
+               vfat.open_dir("/dir").unwrap().cluster());
+    assert!(::vfat::check(&vfat, false).unwrap().is_clean());
+
+    // Without a large enough free run, nothing is moved.
+    let vfat = fat32_image(&[]).remount();
+    let mut a = vfat.create_file("/a").unwrap();
+    let mut b = vfat.create_file("/b").unwrap();
+    loop {
+        if a.write_all(&[1; 512]).and_then(|_| a.sync()).is_err()
+            || b.write_all(&[2; 512]).and_then(|_| b.sync()).is_err() {
+            break;
+        }
+    }
+    drop(b);
+    vfat.remove("/b", false).unwrap();
+    expect_error_kind(defragment(&vfat, "/a"), ::std::io::ErrorKind::Other);
+}
+
+#[test]
+fn test_file_seek_and_positional_io() {
+    let data: Vec<u8> = (0..2000u32).map(|i| (i * 3) as u8).collect();
+    let image = fat32_image(&[("DATA    BIN", &data), ("EMPTY      ", b"")]);
+    let vfat = image.remount();
+
+    // Empty files can be seeked, including past their end.
+    let mut empty = vfat.open_file("/empty").unwrap();
+    assert_eq!(empty.seek(SeekFrom::Start(0)).unwrap(), 0);
+    assert_eq!(empty.seek(SeekFrom::End(10)).unwrap(), 10);
+    assert_eq!(empty.read(&mut [0; 4]).unwrap(), 0);
+    assert!(empty.seek(SeekFrom::Current(-11)).is_err());
+    assert!(empty.seek(SeekFrom::Start(::std::u64::MAX)).is_err());
+
+    // Seeking backwards and forwards across clusters reads the right data.
+    let mut file = vfat.open_file("/data.bin").unwrap();
+    let mut buf = [0; 100];
+    for &offset in &[1500u64, 10, 1020, 511, 1999, 0] {
+        assert_eq!(file.seek(SeekFrom::Start(offset)).unwrap(), offset);
+        let n = file.read(&mut buf).unwrap();
+        assert_eq!(n, ::std::cmp::min(100, 2000 - offset as usize));
+        assert_eq!(&buf[..n], &data[offset as usize..offset as usize + n]);
+    }
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 2000);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    assert_eq!(file.seek(SeekFrom::Current(5000)).unwrap(), 7000);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+    // Positional reads and writes leave the offset alone.
+    file.seek(SeekFrom::Start(300)).unwrap();
+    assert_eq!(file.read_at(&mut buf[..4], 1024).unwrap(), 4);
+    assert_eq!(&buf[..4], &data[1024..1028]);
+    assert_eq!(file.write_at(b"abc", 600).unwrap(), 3);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 300);
+    assert_eq!(file.read_at(&mut buf, 3000).unwrap(), 0);
+
+    // Writing past the end fills the gap with zeroes.
+    assert_eq!(file.write_at(b"end", 2600).unwrap(), 3);
+    assert_eq!(file.size(), 2603);
+    assert_eq!(empty.write(b"x").unwrap(), 1);
+    assert_eq!(empty.size(), 11);
+    assert!(file.write_at(b"x", ::std::u32::MAX as u64).is_err());
+    file.sync().unwrap();
+    empty.sync().unwrap();
+
+    let vfat = image.remount();
+    let mut expected = data.clone();
+    expected[600..603].copy_from_slice(b"abc");
+    expected.extend_from_slice(&[0; 600]);
+    expected.extend_from_slice(b"end");
+    assert_eq!(read_all(vfat.open_file("/data.bin").unwrap()), expected);
+    assert_eq!(read_all(vfat.open_file("/empty").unwrap()), b"\0\0\0\0\0\0\0\0\0\0x");
+    assert!(::vfat::check(&vfat, false).unwrap().is_clean());
+}
+
+#[test]
+fn test_multi_sector_reads_and_read_ahead() {
+    let mut device = MemBlockDevice::from_vec((0..4096u32).map(|i| (i / 512) as u8).collect(), 512);
+    let mut buf = vec![0; 1100];
+    assert_eq!(device.read_sectors(2, &mut buf).unwrap(), 1100);
+    assert!(buf[..512].iter().all(|&b| b == 2) && buf[1024..].iter().all(|&b| b == 4));
+    assert!(device.read_sectors(6, &mut buf).is_err());
+
+    let data: Vec<u8> = (0..8000u32).map(|i| (i * 7) as u8).collect();
+    let image = fat32_image(&[("DATA    BIN", &data)]);
+    let mut expected = data.clone();
+    expected[4000..4005].copy_from_slice(b"dirty");
+
+    // Whole sectors of a large read bypass the cache, unless they are cached
+    // and possibly dirty.
+    let vfat = image.remount();
+    let mut file = vfat.open_file("/data.bin").unwrap();
+    file.write_at(b"dirty", 4000).unwrap();
+    let before = vfat.borrow().cache_stats();
+    let mut buf = vec![0; 8000];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, expected);
+    let stats = vfat.borrow().cache_stats();
+    assert_eq!((stats.bypassed - before.bypassed, stats.misses - before.misses), (14, 1));
+    drop(file);
+
+    // Small sequential reads are served from clusters read ahead.
+    let read_in_chunks = |read_ahead: u32| {
+        let vfat = image.remount();
+        vfat.borrow_mut().set_read_ahead(read_ahead);
+        let mut file = vfat.open_file("/data.bin").unwrap();
+        let before = vfat.borrow().cache_stats();
+        let (mut read, mut chunk) = (Vec::new(), [0; 100]);
+        loop {
+            match file.read(&mut chunk).unwrap() {
+                0 => break,
+                n => read.extend_from_slice(&chunk[..n]),
+            }
+        }
+        assert_eq!(read, expected);
+        let stats = vfat.borrow().cache_stats();
+        (stats.misses - before.misses, stats.prefetched - before.prefetched)
+    };
+    assert_eq!(read_in_chunks(4), (0, 16));
+    assert_eq!(read_in_chunks(0), (16, 0));
+}
+
+/// Returns the exFAT directory entry set of the entry `name`: a file
+/// directory entry, a stream extension entry and file name entries.
+fn exfat_entry_set(name: &str, attributes: u16, flags: u8, first_cluster: u32, valid_len: u64, len: u64) -> Vec<u8> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    let name_entries = (units.len() + 14) / 15;
+    let mut set = vec![0u8; 32 * (2 + name_entries)];
+    set[0] = 0x85;
+    set[1] = (1 + name_entries) as u8;
+    set[4..6].copy_from_slice(&attributes.to_le_bytes());
+    // 2019-05-17 12:30:10, modified 1.5 seconds later.
+    let timestamp = (((2019 - 1980) << 9 | 5 << 5 | 17) as u32) << 16 | (12 << 11 | 30 << 5 | 5);
+    set[8..12].copy_from_slice(&timestamp.to_le_bytes());
+    set[12..16].copy_from_slice(&timestamp.to_le_bytes());
+    set[21] = 150;
+
+    set[32] = 0xC0;
+    set[33] = flags;
+    set[35] = units.len() as u8;
+    set[40..48].copy_from_slice(&valid_len.to_le_bytes());
+    set[52..56].copy_from_slice(&first_cluster.to_le_bytes());
+    set[56..64].copy_from_slice(&len.to_le_bytes());
+    for (i, unit) in units.iter().enumerate() {
+        let offset = 64 + 32 * (i / 15) + 2 + 2 * (i % 15);
+        set[64 + 32 * (i / 15)] = 0xC1;
+        set[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+
+    let checksum = set.iter().enumerate()
+        .filter(|&(i, _)| i != 2 && i != 3)
+        .fold(0u16, |checksum, (_, &b)| checksum.rotate_right(1).wrapping_add(b as u16));
+    set[2..4].copy_from_slice(&checksum.to_le_bytes());
+    set
+}
+
+/// Builds a disk with an MBR and an exFAT volume at sector 1 with 512-byte
+/// sectors and clusters and 64 clusters: the allocation bitmap in cluster
+/// 2, the up-case table in 3, the root directory in 4 and the directory
+/// `Docs` in 5. The root holds the label `SDCARD`, `Hello.txt` (`hello`,
+/// contiguous in clusters 6 to 8 without a FAT chain) and `frag.bin` (the
+/// first 1000 bytes of `frag` in clusters 9, 11 and 10, then zeroes);
+/// `Docs` holds `Über.txt` (`über`, cluster 12).
+fn exfat_image(hello: &[u8], frag: &[u8], uber: &[u8]) -> Vec<u8> {
+    const VOLUME: usize = 512;
+    let cluster = |n: usize| VOLUME + (32 + n - 2) * 512;
+    let mut data = vec![0u8; VOLUME + 96 * 512];
+
+    data[446 + 4] = 0x07;
+    data[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    data[446 + 12..446 + 16].copy_from_slice(&96u32.to_le_bytes());
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    {
+        let boot = &mut data[VOLUME..VOLUME + 512];
+        boot[..3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+        boot[3..11].copy_from_slice(b"EXFAT   ");
+        boot[64..72].copy_from_slice(&1u64.to_le_bytes());
+        boot[72..80].copy_from_slice(&96u64.to_le_bytes());
+        boot[80..84].copy_from_slice(&24u32.to_le_bytes());
+        boot[84..88].copy_from_slice(&1u32.to_le_bytes());
+        boot[88..92].copy_from_slice(&32u32.to_le_bytes());
+        boot[92..96].copy_from_slice(&64u32.to_le_bytes());
+        boot[96..100].copy_from_slice(&4u32.to_le_bytes());
+        boot[100..104].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        boot[104..106].copy_from_slice(&0x0100u16.to_le_bytes());
+        boot[108] = 9;
+        boot[109] = 0;
+        boot[110] = 1;
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+    let checksum = data[VOLUME..VOLUME + 11 * 512].iter().enumerate()
+        .filter(|&(i, _)| i != 106 && i != 107 && i != 112)
+        .fold(0u32, |checksum, (_, &b)| checksum.rotate_right(1).wrapping_add(b as u32));
+    for i in 0..128 {
+        let offset = VOLUME + 11 * 512 + 4 * i;
+        data[offset..offset + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    // The FAT: single-cluster chains, except for frag.bin. Hello.txt has no
+    // FAT chain.
+    let fat = VOLUME + 24 * 512;
+    let mut set_fat = |cluster: usize, value: u32| {
+        data[fat + 4 * cluster..fat + 4 * cluster + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    for &(cluster, value) in &[(0, 0xFFFF_FFF8), (1, 0xFFFF_FFFF), (2, !0), (3, !0), (4, !0), (5, !0),
+                               (9, 11), (11, 10), (10, !0), (12, !0)] {
+        set_fat(cluster, value);
+    }
+    // Clusters 2 to 12 are in use.
+    data[cluster(2)..cluster(2) + 2].copy_from_slice(&[0xFF, 0x07]);
+
+    let mut upcase: Vec<u16> = vec![0xFFFF, 0x61];
+    upcase.extend(0x41..0x5B);
+    upcase.extend_from_slice(&[0xFFFF, 0xFC - 0x7B, 0xDC, 0xFFFF, (0x10000 - 0xFD) as u16]);
+    let upcase: Vec<u8> = upcase.iter().flat_map(|unit| unit.to_le_bytes().to_vec()).collect();
+    data[cluster(3)..cluster(3) + upcase.len()].copy_from_slice(&upcase);
+    let upcase_checksum = upcase.iter().fold(0u32, |checksum, &b| checksum.rotate_right(1).wrapping_add(b as u32));
+
+    let mut root = vec![0u8; 96];
+    root[0] = 0x83;
+    root[1] = 6;
+    for (i, unit) in "SDCARD".encode_utf16().enumerate() {
+        root[2 + 2 * i..4 + 2 * i].copy_from_slice(&unit.to_le_bytes());
+    }
+    root[32] = 0x81;
+    root[32 + 20..32 + 24].copy_from_slice(&2u32.to_le_bytes());
+    root[32 + 24..32 + 32].copy_from_slice(&8u64.to_le_bytes());
+    root[64] = 0x82;
+    root[64 + 4..64 + 8].copy_from_slice(&upcase_checksum.to_le_bytes());
+    root[64 + 20..64 + 24].copy_from_slice(&3u32.to_le_bytes());
+    root[64 + 24..64 + 32].copy_from_slice(&(upcase.len() as u64).to_le_bytes());
+    root.extend(exfat_entry_set("Docs", 0x10, 0x03, 5, 512, 512));
+    root.extend(exfat_entry_set("Hello.txt", 0x20, 0x03, 6, hello.len() as u64, hello.len() as u64));
+    root.extend(exfat_entry_set("frag.bin", 0x20, 0x01, 9, 1000, frag.len() as u64));
+    data[cluster(4)..cluster(4) + root.len()].copy_from_slice(&root);
+
+    let docs = exfat_entry_set("Über.txt", 0x21, 0x01, 12, uber.len() as u64, uber.len() as u64);
+    data[cluster(5)..cluster(5) + docs.len()].copy_from_slice(&docs);
+
+    data[cluster(6)..cluster(6) + hello.len()].copy_from_slice(hello);
+    for (i, chunk) in frag[..1000].chunks(512).enumerate() {
+        let offset = cluster([9, 11, 10][i]);
+        data[offset..offset + chunk.len()].copy_from_slice(chunk);
+    }
+    // Stale data beyond the valid data length.
+    data[cluster(11) + 1000 - 512..cluster(11) + 512].iter_mut().for_each(|b| *b = 0xEE);
+    data[cluster(12)..cluster(12) + uber.len()].copy_from_slice(uber);
+    data
+}
+
+#[test]
+fn test_exfat_read() {
+    use exfat::{self, ExFat};
+
+    let hello: Vec<u8> = (0..1200u32).map(|i| (i * 7) as u8).collect();
+    let frag: Vec<u8> = (0..1300u32).map(|i| (i % 251) as u8).collect();
+    let image = exfat_image(&hello, &frag, "grüße".as_bytes());
+
+    let exfat = ExFat::from(MemBlockDevice::from_vec(image.clone(), 512)).expect("mounted");
+    {
+        let mut volume = exfat.borrow_mut();
+        assert_eq!(volume.volume_label(), Some("SDCARD"));
+        assert_eq!(volume.volume_id(), 0x1234_5678);
+        assert_eq!(volume.free_space().unwrap(), (64 - 11) * 512);
+        assert!(!volume.is_dirty());
+    }
+
+    assert_eq!(entry_names(exfat.open_dir("/").unwrap()), vec!["Docs", "Hello.txt", "frag.bin"]);
+    assert_eq!(read_all(exfat.open_file("/HELLO.TXT").unwrap()), hello);
+    let mut expected = frag[..1000].to_vec();
+    expected.extend_from_slice(&[0; 300]);
+    assert_eq!(read_all(exfat.open_file("/frag.bin").unwrap()), expected);
+    assert_eq!(read_all(exfat.open_file("/docs/../Docs/üBER.TXT").unwrap()), "grüße".as_bytes());
+    assert!(exfat.open("/Docs/..").unwrap().as_dir().unwrap().is_root());
+    expect_error_kind(exfat.open("/missing/Über.txt"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(exfat.open("/Hello.txt/x"), ::std::io::ErrorKind::InvalidInput);
+
+    let entry = exfat.open("/Docs/Über.txt").unwrap();
+    let metadata = entry.metadata();
+    assert!(metadata.read_only() && metadata.archive() && !metadata.hidden());
+    assert_eq!(metadata.len(), 7);
+    assert_eq!((metadata.created().year(), metadata.created().second()), (2019, 10));
+    assert_eq!((metadata.modified().hour(), metadata.modified().second()), (12, 11));
+    assert_eq!(exfat.open("/Docs").unwrap().metadata().len(), 0);
+
+    let mut file = exfat.open_file("/frag.bin").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(file.read_at(&mut buf, 996).unwrap(), 8);
+    assert_eq!(buf, [frag[996], frag[997], frag[998], frag[999], 0, 0, 0, 0]);
+    assert_eq!(file.seek(SeekFrom::End(10)).unwrap(), 1310);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    expect_error_kind(file.write(b"x"), ::std::io::ErrorKind::PermissionDenied);
+    expect_error_kind(exfat.create_file("/new"), ::std::io::ErrorKind::PermissionDenied);
+    expect_error_kind(exfat.remove("/Hello.txt", false), ::std::io::ErrorKind::PermissionDenied);
+
+    // A volume without a partition table mounts as well.
+    let volume = image[512..].to_vec();
+    let exfat = ExFat::from(MemBlockDevice::from_vec(volume, 512)).expect("mounted");
+    assert_eq!(read_all(exfat.open_file("/Hello.txt").unwrap()), hello);
+
+    // FAT volumes are not exFAT volumes, and exFAT volumes are not FAT.
+    match ExFat::from(MemBlockDevice::from_vec(fat32_image(&[]).bytes(), 512)) {
+        Err(exfat::Error::NotFound) => {}
+        other => panic!("expected NotFound, found {:?}", other.map(|_| ())),
+    }
+    assert!(VFat::from(MemBlockDevice::from_vec(image.clone(), 512)).is_err());
+
+    // A corrupted entry set is skipped; a corrupted boot region is rejected.
+    let mut corrupt = image.clone();
+    corrupt[512 + (32 + 2) * 512 + 96 + 40] ^= 1;
+    let exfat = ExFat::from(MemBlockDevice::from_vec(corrupt, 512)).expect("mounted");
+    assert_eq!(entry_names(exfat.open_dir("/").unwrap()), vec!["Hello.txt", "frag.bin"]);
+    let mut corrupt = image.clone();
+    corrupt[512 + 120] ^= 1;
+    match ExFat::from(MemBlockDevice::from_vec(corrupt, 512)) {
+        Err(exfat::Error::BadChecksum) => {}
+        other => panic!("expected BadChecksum, found {:?}", other.map(|_| ())),
+    }
+
+    // Oversized lengths are rejected before anything is allocated for them.
+    const BITMAP_LEN: usize = 512 + (32 + 4 - 2) * 512 + 32 + 24;
+    for &len in &[513u64, 1 << 50] {
+        let mut corrupt = image.clone();
+        corrupt[BITMAP_LEN..BITMAP_LEN + 8].copy_from_slice(&len.to_le_bytes());
+        match ExFat::from(MemBlockDevice::from_vec(corrupt, 512)) {
+            Err(exfat::Error::BadRootDirectory(_)) => {}
+            other => panic!("expected BadRootDirectory, found {:?}", other.map(|_| ())),
+        }
+    }
+    let mut corrupt = image.clone();
+    let docs = 512 + (32 + 5 - 2) * 512;
+    let mut sets = exfat_entry_set("Huge", 0x10, 0x03, 12, 1 << 50, 1 << 50);
+    sets.extend(exfat_entry_set("Sparse", 0x10, 0x03, 12, 64 << 10, 256 << 20));
+    sets.extend(exfat_entry_set("Unwritten", 0x10, 0x03, 12, 0, 32 << 10));
+    corrupt[docs..docs + sets.len()].copy_from_slice(&sets);
+    let exfat = ExFat::from(MemBlockDevice::from_vec(corrupt, 512)).expect("mounted");
+    for path in &["/Docs/Huge", "/Docs/Sparse"] {
+        expect_error_kind(exfat.open_dir(path).and_then(|dir| dir.entries().map(|_| ())),
+                          ::std::io::ErrorKind::InvalidData);
+    }
+    assert!(entry_names(exfat.open_dir("/Docs/Unwritten").unwrap()).is_empty());
+}
+
+#[test]
+fn test_ramfs() {
+    use ramfs::RamFs;
+    use vfat::{FixedClock, Timestamp};
+
+    let ramfs = RamFs::new();
+    let created = Timestamp::new(2019, 3, 1, 10, 20, 31, 0).unwrap();
+    ramfs.borrow_mut().set_time_source(FixedClock(created));
+
+    ramfs.create_dir("/a/b", true).unwrap();
+    let mut file = ramfs.create_file("/a/b/Notes.txt").unwrap();
+    assert_eq!(file.write(b"hello").unwrap(), 5);
+    file.seek(SeekFrom::Start(8)).unwrap();
+    file.write_all(b"world").unwrap();
+    assert_eq!(read_all(ramfs.open_file("/A/B/notes.TXT").unwrap()), b"hello\0\0\0world");
+    assert_eq!(ramfs.borrow().used_space(), 13);
+
+    // Writes are visible through every handle at once.
+    let mut other = ramfs.open_file("/a/b/Notes.txt").unwrap();
+    file.write_all(b"!").unwrap();
+    assert_eq!(other.size(), 14);
+    let mut buf = [0; 3];
+    assert_eq!(other.read_at(&mut buf, 12).unwrap(), 2);
+    assert_eq!(&buf[..2], b"d!");
+
+    let entry = ramfs.open("/a/b/Notes.txt").unwrap();
+    assert_eq!(entry.name(), "Notes.txt");
+    assert!(entry.metadata().archive() && !entry.metadata().read_only());
+    assert_eq!(entry.metadata().len(), 14);
+    assert_eq!(entry.metadata().created(), created);
+    assert_eq!(ramfs.open("/a/b").unwrap().metadata().len(), 0);
+    assert_eq!(ramfs.open("/a/b/../../a").unwrap().name(), "a");
+    assert_eq!(entry_names(ramfs.open_dir("/a/b/../..").unwrap()), vec!["a"]);
+
+    expect_error_kind(ramfs.create_file("/a/B"), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(ramfs.create_file("/a/b?"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(ramfs.create_file("/missing/x"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(ramfs.open("a"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(ramfs.open("/a/b/Notes.txt/x"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(ramfs.open("/a/x"), ::std::io::ErrorKind::NotFound);
+
+    // Renaming keeps open handles valid; directories cannot move into
+    // themselves.
+    ramfs.rename("/a/b", "/c").unwrap();
+    ramfs.rename("/c/notes.txt", "/c/NOTES.TXT").unwrap();
+    assert_eq!(entry_names(ramfs.open_dir("/c").unwrap()), vec!["NOTES.TXT"]);
+    file.write_all(b"?").unwrap();
+    assert_eq!(ramfs.open_file("/c/notes.txt").unwrap().size(), 15);
+    expect_error_kind(ramfs.rename("/c", "/c/d"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(ramfs.rename("/a", "/C"), ::std::io::ErrorKind::AlreadyExists);
+    expect_error_kind(ramfs.rename("/x", "/y"), ::std::io::ErrorKind::NotFound);
+
+    let mut metadata = *ramfs.open("/c").unwrap().metadata();
+    metadata.set_hidden(true);
+    ramfs.set_metadata("/C", &metadata).unwrap();
+    assert!(ramfs.open("/c").unwrap().metadata().hidden());
+    assert!(ramfs.open("/c").unwrap().is_dir());
+    expect_error_kind(ramfs.set_metadata("/", &metadata), ::std::io::ErrorKind::InvalidInput);
+
+    // Removed entries are gone for good, including through open handles.
+    expect_error_kind(ramfs.remove("/c", false), ::std::io::ErrorKind::Other);
+    let dir = ramfs.open_dir("/c").unwrap();
+    ramfs.remove("/c", true).unwrap();
+    expect_error_kind(ramfs.open("/c/notes.txt"), ::std::io::ErrorKind::InvalidInput);
+    expect_error_kind(dir.entries(), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(file.write(b"x"), ::std::io::ErrorKind::NotFound);
+    expect_error_kind(other.read(&mut buf), ::std::io::ErrorKind::NotFound);
+    assert_eq!(entry_names(ramfs.open_dir("/").unwrap()), vec!["a"]);
+    assert_eq!(ramfs.borrow().used_space(), 0);
+}
+
+#[test]
+fn test_conformance_vfat() {
+    ::conformance::check_all(|| fat32_image(&[]).remount());
+}
+
+#[test]
+fn test_conformance_ramfs() {
+    ::conformance::check_all(::ramfs::RamFs::new);
+}
+
+#[test]
+fn test_conformance_exfat() {
+    use exfat::ExFat;
+
+    let tree = ::conformance::Tree::new();
+    let image = exfat_image(&tree.hello, &tree.frag, &tree.uber);
+    let exfat = ExFat::from(MemBlockDevice::from_vec(image, 512)).expect("mounted");
+    ::conformance::check_read_only(&exfat);
+}
@@ -8,6 +8,12 @@ pub trait BlockDevice: Send {
         512
     }
 
+    /// The number of sectors of the device, or `None` if it is not known.
+    /// Defaults to `None`.
+    fn sector_count(&self) -> Option<u64> {
+        None
+    }
+
     /// Read sector number `n` into `buf`.
     ///
     /// `self.sector_size()` or `buf.len()` bytes, whichever is less, are read
@@ -41,6 +47,25 @@ pub trait BlockDevice: Send {
         Ok(read)
     }
 
+    /// Reads consecutive sectors starting at sector `n` into `buf`.
+    ///
+    /// `buf.len()` bytes are read: `buf` is filled sector by sector, the last
+    /// of which may be read partially. The number of bytes read is returned.
+    /// Devices that can transfer several sectors at once should override the
+    /// default implementation, which reads one sector at a time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or reading from `self` fails.
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let mut read = 0;
+        for (i, chunk) in buf.chunks_mut(sector_size).enumerate() {
+            read += self.read_sector(n + i as u64, chunk)?;
+        }
+        Ok(read)
+    }
+
     /// Overwrites sector `n` with the contents of `buf`.
     ///
     /// `self.sector_size()` or `buf.len()` bytes, whichever is less, are written
@@ -55,10 +80,22 @@ pub trait BlockDevice: Send {
 }
 
 impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
+    fn sector_size(&self) -> u64 {
+        (**self).sector_size()
+    }
+
+    fn sector_count(&self) -> Option<u64> {
+        (**self).sector_count()
+    }
+
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
         (*self).read_sector(n, buf)
     }
 
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (*self).read_sectors(n, buf)
+    }
+
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         (*self).write_sector(n, buf)
     }
@@ -76,6 +113,12 @@ macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
             Ok(to_read)
         }
 
+        fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+            self.seek(io::SeekFrom::Start(n * self.sector_size()))?;
+            self.read_exact(buf)?;
+            Ok(buf.len())
+        }
+
         fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
             let sector_size = self.sector_size();
             let to_write = ::std::cmp::min(sector_size as usize, buf.len());
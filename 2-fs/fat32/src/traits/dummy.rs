@@ -64,6 +64,10 @@ impl Metadata for Dummy {
     type Timestamp = Dummy;
     fn read_only(&self) -> bool { panic!("Dummy") }
     fn hidden(&self) -> bool { panic!("Dummy") }
+    fn system(&self) -> bool { panic!("Dummy") }
+    fn archive(&self) -> bool { panic!("Dummy") }
+    fn is_volume_label(&self) -> bool { panic!("Dummy") }
+    fn len(&self) -> u64 { panic!("Dummy") }
     fn created(&self) -> Self::Timestamp { panic!("Dummy") }
     fn accessed(&self) -> Self::Timestamp { panic!("Dummy") }
     fn modified(&self) -> Self::Timestamp { panic!("Dummy") }
@@ -10,6 +10,34 @@ pub trait File: io::Read + io::Write + io::Seek + Sized {
 
     /// Returns the size of the file in bytes.
     fn size(&self) -> u64;
+
+    /// Reads from the file at `offset` into `buf`, returning the number of
+    /// bytes read, without changing the current offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or reading fails.
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let current = self.seek(io::SeekFrom::Current(0))?;
+        self.seek(io::SeekFrom::Start(offset))?;
+        let result = self.read(buf);
+        self.seek(io::SeekFrom::Start(current))?;
+        result
+    }
+
+    /// Writes `buf` to the file at `offset`, returning the number of bytes
+    /// written, without changing the current offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or writing fails.
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let current = self.seek(io::SeekFrom::Current(0))?;
+        self.seek(io::SeekFrom::Start(offset))?;
+        let result = self.write(buf);
+        self.seek(io::SeekFrom::Start(current))?;
+        result
+    }
 }
 
 /// Trait implemented by directories in a file system.
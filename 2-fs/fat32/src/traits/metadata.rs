@@ -34,6 +34,24 @@ pub trait Metadata: Sized {
     /// Whether the entry should be "hidden" from directory traversals.
     fn hidden(&self) -> bool;
 
+    /// Whether the entry belongs to the operating system.
+    fn system(&self) -> bool;
+
+    /// Whether the entry was modified since it was last backed up.
+    fn archive(&self) -> bool;
+
+    /// Whether the entry holds the volume's label rather than a file or
+    /// directory.
+    fn is_volume_label(&self) -> bool;
+
+    /// The size of the entry's contents in bytes. Always 0 for directories.
+    fn len(&self) -> u64;
+
+    /// Whether the entry's contents are empty. Always `true` for directories.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// The timestamp when the entry was created.
     fn created(&self) -> Self::Timestamp;
 
@@ -1,14 +1,17 @@
 extern crate hashbrown;
 
-use std::{fmt, io};
+use std::{cmp, fmt, io};
 use self::hashbrown::HashMap;
 use traits::BlockDevice;
 use std::io::Write;
 
 #[derive(Debug)]
 struct CacheEntry {
+    sector: u64,
     data: Vec<u8>,
-    dirty: bool
+    dirty: bool,
+    /// Set on every access; cleared as the eviction hand passes the entry.
+    referenced: bool,
 }
 
 pub struct Partition {
@@ -18,9 +21,38 @@ pub struct Partition {
     pub sector_size: u64
 }
 
+/// Counters describing the effectiveness of a `CachedDevice`.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Accesses to sectors that were already cached.
+    pub hits: u64,
+    /// Accesses that required reading a sector from the device.
+    pub misses: u64,
+    /// Sectors dropped from the cache to make room for another.
+    pub evictions: u64,
+    /// Dirty sectors written back to the device.
+    pub write_backs: u64,
+    /// Sectors read directly from the device into the caller's buffer,
+    /// bypassing the cache.
+    pub bypassed: u64,
+    /// Sectors read into the cache ahead of being accessed.
+    pub prefetched: u64,
+}
+
+/// The number of sectors cached by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 512;
+
+/// A write-back cache of at most `capacity` sectors. When the cache is full,
+/// a sector is evicted using the CLOCK approximation of LRU; dirty sectors
+/// are written back to the device when evicted, when `flush()` is called and
+/// when the `CachedDevice` is dropped.
 pub struct CachedDevice {
     device: Box<dyn BlockDevice>,
-    cache: HashMap<u64, CacheEntry>,
+    cache: HashMap<u64, usize>,
+    entries: Vec<CacheEntry>,
+    capacity: usize,
+    hand: usize,
+    stats: CacheStats,
     partition: Partition
 }
 
@@ -46,16 +78,79 @@ impl CachedDevice {
     /// Panics if the partition's sector size is < the device's sector size.
     pub fn new<T>(device: T, partition: Partition) -> CachedDevice
         where T: BlockDevice + 'static
+    {
+        CachedDevice::with_capacity(device, partition, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Creates a new `CachedDevice` like `new()` that caches at most
+    /// `capacity` sectors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the partition's sector size is < the device's sector size or
+    /// if `capacity` is zero.
+    pub fn with_capacity<T>(device: T, partition: Partition, capacity: usize) -> CachedDevice
+        where T: BlockDevice + 'static
     {
         assert!(partition.sector_size >= device.sector_size());
+        assert!(capacity > 0);
 
         CachedDevice {
             device: Box::new(device),
             cache: HashMap::new(),
+            entries: Vec::new(),
+            capacity,
+            hand: 0,
+            stats: CacheStats::default(),
             partition
         }
     }
 
+    /// The maximum number of sectors held in the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the maximum number of sectors held in the cache. If the cache
+    /// currently holds more than `capacity` sectors, sectors are evicted in
+    /// clock order, writing back dirty ones, until `capacity` remain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `capacity` is zero, or the
+    /// error from writing an evicted sector back. The capacity is left
+    /// unchanged in either case, though sectors evicted before a failed
+    /// write-back stay evicted.
+    pub fn set_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        if capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "cache capacity must be nonzero"));
+        }
+
+        while self.entries.len() > capacity {
+            let slot = self.evict()?;
+            self.entries.swap_remove(slot);
+            if slot < self.entries.len() {
+                self.cache.insert(self.entries[slot].sector, slot);
+            }
+            if self.hand >= self.entries.len() {
+                self.hand = 0;
+            }
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// The physical sector where the partition begins.
+    pub fn partition_start(&self) -> u64 {
+        self.partition.start
+    }
+
+    /// Returns the cache's hit, miss and eviction counters.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
     /// Maps a user's request for a sector `virt` to the physical sector and
     /// number of physical sectors required to access `virt`.
     fn virtual_to_physical(&self, virt: u64) -> (u64, u64) {
@@ -72,17 +167,186 @@ impl CachedDevice {
         }
     }
 
-    fn load_cache(&mut self, sector: u64) {
-        if !self.cache.contains_key(&sector) {
-            let (physical_sector, factor) = self.virtual_to_physical(sector);
-            let mut data = Vec::new();
+    /// Writes the cached sector in `entries[slot]` to the device if it is
+    /// dirty and marks it clean.
+    fn write_back(&mut self, slot: usize) -> io::Result<()> {
+        if !self.entries[slot].dirty {
+            return Ok(());
+        }
+
+        let (physical_sector, factor) = self.virtual_to_physical(self.entries[slot].sector);
+        let physical_size = self.device.sector_size() as usize;
+        let entry = &mut self.entries[slot];
+        for i in 0..factor {
+            let start = i as usize * physical_size;
+            self.device.write_sector(physical_sector + i, &entry.data[start..start + physical_size])?;
+        }
+        entry.dirty = false;
+        self.stats.write_backs += 1;
+        Ok(())
+    }
+
+    /// Returns the slot that a newly loaded sector should be stored in,
+    /// evicting the first unreferenced sector under the clock hand if the
+    /// cache is full.
+    fn free_slot(&mut self) -> io::Result<usize> {
+        if self.entries.len() < self.capacity {
+            return Ok(self.entries.len());
+        }
+
+        self.evict()
+    }
+
+    /// Advances the clock hand to the first unreferenced slot, clearing
+    /// reference bits on the way, writes that slot back and removes its
+    /// sector from the cache. Returns the now unused slot.
+    fn evict(&mut self) -> io::Result<usize> {
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.entries.len();
+            if self.entries[slot].referenced {
+                self.entries[slot].referenced = false;
+            } else {
+                self.write_back(slot)?;
+                self.cache.remove(&self.entries[slot].sector);
+                self.stats.evictions += 1;
+                return Ok(slot);
+            }
+        }
+    }
+
+    /// Returns the slot holding `sector`, reading it from the device first if
+    /// it is not cached.
+    fn load_cache(&mut self, sector: u64) -> io::Result<usize> {
+        if let Some(&slot) = self.cache.get(&sector) {
+            self.stats.hits += 1;
+            self.entries[slot].referenced = true;
+            return Ok(slot);
+        }
+
+        let (physical_sector, factor) = self.virtual_to_physical(sector);
+        let mut data = Vec::new();
+        for i in 0..factor {
+            self.device.read_all_sector(physical_sector + i, &mut data)?;
+        }
+        if (data.len() as u64) < factor * self.device.sector_size() {
+            return Err(short_read());
+        }
+        self.stats.misses += 1;
+        self.insert(CacheEntry { sector, data, dirty: false, referenced: true })
+    }
+
+    /// Stores `entry`, which is not cached yet, in a free slot and returns
+    /// the slot.
+    fn insert(&mut self, entry: CacheEntry) -> io::Result<usize> {
+        let sector = entry.sector;
+        let slot = self.free_slot()?;
+        if slot == self.entries.len() {
+            self.entries.push(entry);
+        } else {
+            self.entries[slot] = entry;
+        }
+        self.cache.insert(sector, slot);
+        Ok(slot)
+    }
+
+    /// The size in bytes of the cached sector `sector`.
+    fn sector_len(&self, sector: u64) -> usize {
+        let (_, factor) = self.virtual_to_physical(sector);
+        (factor * self.device.sector_size()) as usize
+    }
 
-            for i in 0..factor {
-                self.device.read_all_sector(physical_sector + i, &mut data).unwrap();
+    /// Returns the number of consecutive sectors, at most `max`, starting at
+    /// `sector` that are not cached. The run ends at the start of the
+    /// partition, where the size of a sector may change.
+    fn uncached_run(&self, sector: u64, max: u64) -> u64 {
+        let mut count = 0;
+        while count < max {
+            let next = sector + count;
+            if self.cache.contains_key(&next) || (count > 0 && next == self.partition.start) {
+                break;
             }
+            count += 1;
+        }
+        count
+    }
 
-            self.cache.insert(sector, CacheEntry { data, dirty: false });
+    /// Reads the run of uncached sectors starting at `sector` that `buf`
+    /// covers from the device in a single transfer.
+    fn read_run(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        let (physical_sector, _) = self.virtual_to_physical(sector);
+        if self.device.read_sectors(physical_sector, buf)? < buf.len() {
+            return Err(short_read());
         }
+        Ok(())
+    }
+
+    /// Reads the sectors `sector..sector + count` that are not cached into
+    /// the cache without marking them accessed, reading each run of uncached
+    /// sectors from the device in a single transfer. At most half of the
+    /// cache's capacity is prefetched at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from the device or writing back an
+    /// evicted sector fails.
+    pub fn prefetch(&mut self, sector: u64, count: u64) -> io::Result<()> {
+        let count = cmp::min(count, cmp::max(1, self.capacity as u64 / 2));
+        let mut i = 0;
+        while i < count {
+            let start = sector + i;
+            if self.cache.contains_key(&start) {
+                i += 1;
+                continue;
+            }
+
+            let run = self.uncached_run(start, count - i);
+            let len = self.sector_len(start);
+            let mut data = vec![0; run as usize * len];
+            self.read_run(start, &mut data)?;
+            for (j, chunk) in data.chunks(len).enumerate() {
+                let sector = start + j as u64;
+                self.insert(CacheEntry { sector, data: chunk.to_vec(), dirty: false, referenced: false })?;
+            }
+            self.stats.prefetched += run;
+            i += run;
+        }
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes starting at byte `offset` of sector `sector`
+    /// into `buf`, continuing into the sectors that follow.
+    ///
+    /// Spans of whole sectors at least `min_direct` bytes long are read with
+    /// `read_sectors()` in a single transfer, bypassing the cache unless they
+    /// are already cached; the rest is read through the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from the device fails.
+    pub fn read_bytes(&mut self, sector: u64, offset: usize, min_direct: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_per_sector = self.sector_len(sector);
+        let mut sector = sector + (offset / bytes_per_sector) as u64;
+        let mut sector_offset = offset % bytes_per_sector;
+        let mut bytes_read = 0;
+        while bytes_read < buf.len() {
+            let remaining = buf.len() - bytes_read;
+            if sector_offset == 0 && remaining >= cmp::max(min_direct, bytes_per_sector) {
+                let len = remaining - remaining % bytes_per_sector;
+                self.read_sectors(sector, &mut buf[bytes_read..bytes_read + len])?;
+                bytes_read += len;
+                sector += (len / bytes_per_sector) as u64;
+                continue;
+            }
+
+            let len = cmp::min(bytes_per_sector - sector_offset, remaining);
+            let data = self.get(sector)?;
+            buf[bytes_read..bytes_read + len].copy_from_slice(&data[sector_offset..sector_offset + len]);
+            bytes_read += len;
+            sector += 1;
+            sector_offset = 0;
+        }
+        Ok(bytes_read)
     }
 
     /// Returns a mutable reference to the cached sector `sector`. If the sector
@@ -96,8 +360,9 @@ impl CachedDevice {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
-        self.load_cache(sector);
-        let entry = self.cache.get_mut(&sector).unwrap();
+        let slot = self.load_cache(sector)?;
+        let entry = &mut self.entries[slot];
+        entry.dirty = true;
         Ok(entry.data.as_mut_slice())
     }
 
@@ -108,10 +373,27 @@ impl CachedDevice {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
-        self.load_cache(sector);
-        let entry = self.cache.get_mut(&sector).unwrap();
-        entry.dirty = true;
-        Ok(entry.data.as_slice())
+        let slot = self.load_cache(sector)?;
+        Ok(self.entries[slot].data.as_slice())
+    }
+
+    /// Writes every dirty cached sector back to the underlying device and
+    /// marks it clean.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing any sector to the disk fails. Sectors that
+    /// were not written remain dirty.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<usize> = (0..self.entries.len())
+            .filter(|&slot| self.entries[slot].dirty)
+            .collect();
+        dirty.sort_by_key(|&slot| self.entries[slot].sector);
+
+        for slot in dirty {
+            self.write_back(slot)?;
+        }
+        Ok(())
     }
 }
 
@@ -123,17 +405,60 @@ impl BlockDevice for CachedDevice {
         buf.write(data)
     }
 
+    /// Reads consecutive sectors starting at `n` into `buf`. Cached sectors
+    /// are copied from the cache; runs of sectors that are not cached are
+    /// read from the device in a single transfer and are not cached.
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        let mut sector = n;
+        while read < buf.len() {
+            let len = self.sector_len(sector);
+            if let Some(&slot) = self.cache.get(&sector) {
+                let bytes = cmp::min(len, buf.len() - read);
+                buf[read..read + bytes].copy_from_slice(&self.entries[slot].data[..bytes]);
+                self.entries[slot].referenced = true;
+                self.stats.hits += 1;
+                read += bytes;
+                sector += 1;
+                continue;
+            }
+
+            let max = ((buf.len() - read + len - 1) / len) as u64;
+            let run = self.uncached_run(sector, max);
+            let bytes = cmp::min(run as usize * len, buf.len() - read);
+            self.read_run(sector, &mut buf[read..read + bytes])?;
+            self.stats.bypassed += run;
+            read += bytes;
+            sector += run;
+        }
+        Ok(read)
+    }
+
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         let mut data = self.get_mut(n)?;
         data.write(buf)
     }
 }
 
+fn short_read() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "short sector read")
+}
+
 impl fmt::Debug for CachedDevice {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CachedDevice")
             .field("device", &"<block device>")
-            .field("cache", &self.cache)
+            .field("cached", &self.entries.len())
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats)
             .finish()
     }
 }
+
+impl Drop for CachedDevice {
+    /// Writes all dirty sectors back to the device. Errors are ignored; call
+    /// `flush()` first to observe them.
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,480 @@
+use std::{fmt, io};
+
+use util::VecExt;
+use vfat::{Cluster, FatEntry, Shared, Status, VFat};
+use vfat::dir::{LfnParser, LongName, VFatDirEntry};
+use vfat::name;
+
+/// An inconsistency found by `check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// FAT copy `copy` differs from the active FAT in `entries` entries.
+    FatMismatch { copy: u8, entries: u32 },
+    /// The cluster chain of `path` links to `cluster`, which is free, bad,
+    /// reserved, beyond the end of the volume or already part of the chain.
+    /// `cluster` is 0 if a directory has no clusters at all.
+    BadChain { path: String, cluster: u32 },
+    /// `cluster` is part of the chains of both `first` and `second`.
+    CrossLinked { first: String, second: String, cluster: u32 },
+    /// The chain of the file `path` has `clusters` clusters but its size of
+    /// `size` bytes calls for `expected`.
+    ChainLength { path: String, size: u32, clusters: u32, expected: u32 },
+    /// Entries `first` to `last`, inclusive, of the directory `path` are long
+    /// file name entries that are out of sequence or whose checksum does not
+    /// match the entry that follows them.
+    BadLfn { path: String, first: usize, last: usize },
+    /// The `.` or `..` entry of the directory `path` is missing or refers to
+    /// the wrong cluster.
+    BadDotEntries { path: String },
+    /// The `clusters` allocated clusters of the chain starting at `start` are
+    /// not reachable from any directory entry.
+    LostChain { start: u32, clusters: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::FatMismatch { copy, entries } =>
+                write!(f, "FAT copy {} differs from the active FAT in {} entries", copy, entries),
+            Problem::BadChain { ref path, cluster } =>
+                write!(f, "{}: invalid cluster chain at cluster {}", path, cluster),
+            Problem::CrossLinked { ref first, ref second, cluster } =>
+                write!(f, "{} and {} are cross-linked at cluster {}", first, second, cluster),
+            Problem::ChainLength { ref path, size, clusters, expected } =>
+                write!(f, "{}: size {} needs {} clusters but the chain has {}", path, size, expected, clusters),
+            Problem::BadLfn { ref path, first, last } =>
+                write!(f, "{}: invalid long file name entries {} to {}", path, first, last),
+            Problem::BadDotEntries { ref path } =>
+                write!(f, "{}: missing or invalid `.` or `..` entry", path),
+            Problem::LostChain { start, clusters } =>
+                write!(f, "{} lost clusters starting at cluster {}", clusters, start),
+        }
+    }
+}
+
+/// A problem found by `check` and whether it was repaired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: Problem,
+    pub repaired: bool,
+}
+
+/// The result of `check`.
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    /// The problems found, in the order they were found.
+    pub findings: Vec<Finding>,
+    /// The number of files checked.
+    pub files: usize,
+    /// The number of directories checked, including the root directory.
+    pub directories: usize,
+}
+
+impl CheckReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Returns `true` if every problem found was repaired.
+    pub fn all_repaired(&self) -> bool {
+        self.findings.iter().all(|finding| finding.repaired)
+    }
+}
+
+/// Why a chain walk stopped before reaching an end-of-chain marker.
+enum ChainError {
+    /// The chain links to the given cluster, which can not be part of it.
+    Invalid(u32),
+    /// The chain links to a cluster owned by the given path.
+    CrossLinked(u32, usize),
+}
+
+/// The validated prefix of a cluster chain.
+struct Chain {
+    clusters: Vec<Cluster>,
+    error: Option<ChainError>,
+}
+
+struct Checker<'a> {
+    fs: &'a mut VFat,
+    repair: bool,
+    report: CheckReport,
+    /// The path owning each cluster, as an index into `paths` plus one.
+    owners: Vec<u32>,
+    paths: Vec<String>,
+}
+
+/// Checks the consistency of the volume `vfat`, walking every directory and
+/// cluster chain, and returns a report of the problems found.
+///
+/// If `repair` is `true`, problems are fixed where possible: FAT copies are
+/// resynchronized with the active FAT, broken and cross-linked chains are
+/// truncated, chains are trimmed to or sizes reduced to match each other,
+/// invalid long file name entries are deleted, `.` and `..` entries are
+/// pointed at the right clusters and lost clusters are freed. Changes are
+/// flushed to the device before returning.
+///
+/// # Errors
+///
+/// Returns an error if reading from or writing to the device fails. Problems
+/// with the volume are reported in the `CheckReport`, not as errors.
+pub fn check(vfat: &Shared<VFat>, repair: bool) -> io::Result<CheckReport> {
+    let mut fs = vfat.borrow_mut();
+    let report = {
+        let owners = vec![0; fs.data_clusters() as usize + 2];
+        let mut checker = Checker {
+            fs: &mut fs,
+            repair,
+            report: CheckReport::default(),
+            owners,
+            paths: Vec::new(),
+        };
+        checker.check_fat_copies()?;
+        checker.check_root()?;
+        checker.check_lost_clusters()?;
+        checker.report
+    };
+    if repair {
+        fs.clear_dentries();
+        fs.flush()?;
+    }
+    Ok(report)
+}
+
+impl<'a> Checker<'a> {
+    fn found(&mut self, problem: Problem, repaired: bool) {
+        self.report.findings.push(Finding { problem, repaired });
+    }
+
+    fn check_fat_copies(&mut self) -> io::Result<()> {
+        let mismatches = self.fs.verify_fat_copies()?;
+        if self.repair && !mismatches.is_empty() {
+            self.fs.sync_fat_copies()?;
+        }
+        for mismatch in mismatches {
+            let problem = Problem::FatMismatch { copy: mismatch.copy, entries: mismatch.entries };
+            self.found(problem, self.repair);
+        }
+        Ok(())
+    }
+
+    /// Follows the chain starting at `start`, claiming its clusters for the
+    /// path `owner` until the end of the chain or the first cluster that can
+    /// not be part of it.
+    fn walk_chain(&mut self, start: u32, owner: usize) -> io::Result<Chain> {
+        let end = self.owners.len() as u32;
+        let mut clusters = Vec::new();
+        let mut id = start;
+        loop {
+            if id < 2 || id >= end {
+                return Ok(Chain { clusters, error: Some(ChainError::Invalid(id)) });
+            }
+            match self.owners[id as usize] {
+                0 => {}
+                other if other as usize - 1 == owner => {
+                    return Ok(Chain { clusters, error: Some(ChainError::Invalid(id)) });
+                }
+                other => {
+                    let error = ChainError::CrossLinked(id, other as usize - 1);
+                    return Ok(Chain { clusters, error: Some(error) });
+                }
+            }
+
+            let cluster = Cluster::from(id);
+            match self.fs.fat_entry(cluster)?.status() {
+                Status::Data(next) => {
+                    self.owners[id as usize] = owner as u32 + 1;
+                    clusters.push(cluster);
+                    id = next.id();
+                }
+                Status::Eoc(_) => {
+                    self.owners[id as usize] = owner as u32 + 1;
+                    clusters.push(cluster);
+                    return Ok(Chain { clusters, error: None });
+                }
+                // A free entry in a chain most likely ends it: keep the
+                // cluster and let a repair mark it as the last one.
+                Status::Free => {
+                    self.owners[id as usize] = owner as u32 + 1;
+                    clusters.push(cluster);
+                    return Ok(Chain { clusters, error: Some(ChainError::Invalid(id)) });
+                }
+                Status::Bad | Status::Reserved => {
+                    return Ok(Chain { clusters, error: Some(ChainError::Invalid(id)) });
+                }
+            }
+        }
+    }
+
+    /// Walks the chain of `path` starting at `start` and reports and, when
+    /// repairing, truncates it if it is broken or cross-linked. Returns the
+    /// valid clusters of the chain.
+    fn claim_chain(&mut self, path: &str, start: u32) -> io::Result<Vec<Cluster>> {
+        self.paths.push(path.to_string());
+        let owner = self.paths.len() - 1;
+        let chain = self.walk_chain(start, owner)?;
+
+        let problem = match chain.error {
+            None => return Ok(chain.clusters),
+            Some(ChainError::Invalid(cluster)) => Problem::BadChain { path: path.to_string(), cluster },
+            Some(ChainError::CrossLinked(cluster, other)) => Problem::CrossLinked {
+                first: self.paths[other].clone(),
+                second: path.to_string(),
+                cluster,
+            },
+        };
+
+        let repaired = self.repair && !chain.clusters.is_empty();
+        if repaired {
+            let last = *chain.clusters.last().unwrap();
+            self.fs.set_fat_entry(last, FatEntry::EOC)?;
+        }
+        self.found(problem, repaired);
+        Ok(chain.clusters)
+    }
+
+    fn check_root(&mut self) -> io::Result<()> {
+        let root = self.fs.root_dir_cluster;
+        self.report.directories += 1;
+        if self.fs.is_fixed_root(root) {
+            let mut buf = Vec::new();
+            self.fs.read_dir(root, &mut buf)?;
+            return self.check_dir_entries("/", root, unsafe { buf.cast() });
+        }
+
+        let clusters = self.claim_chain("/", root.id())?;
+        self.check_dir("/", root, &clusters, None)
+    }
+
+    /// Checks the directory `path` made up of `clusters`, whose first cluster
+    /// is `start`. `parent` is the raw first cluster of the parent directory
+    /// or `None` for the root directory.
+    fn check_dir(&mut self, path: &str, start: Cluster, clusters: &[Cluster], parent: Option<u32>) -> io::Result<()> {
+        let bytes_per_cluster = self.fs.bytes_per_cluster();
+        let mut buf = vec![0u8; bytes_per_cluster * clusters.len()];
+        for (i, &cluster) in clusters.iter().enumerate() {
+            self.fs.read_cluster(cluster, 0, &mut buf[i * bytes_per_cluster..])?;
+        }
+        let entries: Vec<VFatDirEntry> = unsafe { buf.cast() };
+
+        if let Some(parent) = parent {
+            self.check_dot_entries(path, start, &entries, parent)?;
+        }
+        self.check_dir_entries(path, start, entries)
+    }
+
+    fn check_dot_entries(&mut self, path: &str, start: Cluster, entries: &[VFatDirEntry], parent: u32) -> io::Result<()> {
+        let expected = [(*b".          ", start.id()), (*b"..         ", parent)];
+        let mut valid = true;
+        let mut repairable = true;
+        for (index, &(name, cluster)) in expected.iter().enumerate() {
+            let entry = match entries.get(index) {
+                Some(entry) => entry,
+                None => {
+                    valid = false;
+                    repairable = false;
+                    continue;
+                }
+            };
+            let regular = entry.regular();
+            if regular.short_name() != name || !entry.unknown().is_regular_directory() {
+                valid = false;
+                repairable = false;
+            } else if regular.metadata.start_cluster() != cluster {
+                valid = false;
+            }
+        }
+
+        if valid {
+            return Ok(());
+        }
+        let repaired = self.repair && repairable;
+        if repaired {
+            for (index, &(_, cluster)) in expected.iter().enumerate() {
+                self.fs.dir_entry_mut(start, index)?.regular_mut().metadata.set_start_cluster(cluster);
+            }
+        }
+        self.found(Problem::BadDotEntries { path: path.to_string() }, repaired);
+        Ok(())
+    }
+
+    fn bad_lfn(&mut self, path: &str, dir: Cluster, first: usize, last: usize) -> io::Result<()> {
+        if self.repair {
+            for index in first..=last {
+                self.fs.dir_entry_mut(dir, index)?.mark_deleted();
+            }
+        }
+        self.found(Problem::BadLfn { path: path.to_string(), first, last }, self.repair);
+        Ok(())
+    }
+
+    /// Checks the entries of the directory `path` starting at `dir`,
+    /// recursing into subdirectories.
+    fn check_dir_entries(&mut self, path: &str, dir: Cluster, entries: Vec<VFatDirEntry>) -> io::Result<()> {
+        let mut lfn = LfnParser::default();
+        for (index, entry) in entries.iter().enumerate() {
+            let unknown = entry.unknown();
+            if unknown.prev_is_last_entry() {
+                break;
+            }
+
+            if unknown.is_deleted_or_unused() || unknown.is_volume_label() {
+                if let Some((first, last)) = lfn.abandon() {
+                    self.bad_lfn(path, dir, first, last)?;
+                }
+                continue;
+            }
+
+            if unknown.is_lnf() {
+                if let Some((first, last)) = lfn.push(index, entry.long_filename()) {
+                    self.bad_lfn(path, dir, first, last)?;
+                }
+                continue;
+            }
+
+            let short_name = entry.regular().short_name();
+            let long_name = match lfn.finish(&short_name) {
+                LongName::Valid(long_name, _) => Some(long_name),
+                LongName::Orphaned(first, last) => {
+                    self.bad_lfn(path, dir, first, last)?;
+                    None
+                }
+                LongName::None => None,
+            };
+
+            if &short_name == b".          " || &short_name == b"..         " {
+                continue;
+            }
+            let name = long_name.unwrap_or_else(|| name::decode_short_name(&short_name, self.fs.code_page()));
+            let child = match path {
+                "/" => format!("/{}", name),
+                _ => format!("{}/{}", path, name),
+            };
+            self.check_entry(&child, dir, index, entry, unknown.is_regular_directory())?;
+        }
+
+        if let Some((first, last)) = lfn.abandon() {
+            self.bad_lfn(path, dir, first, last)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the entry at `index` of the directory starting at `dir`, which
+    /// is the entry of `path`.
+    fn check_entry(&mut self, path: &str, dir: Cluster, index: usize, entry: &VFatDirEntry, is_dir: bool) -> io::Result<()> {
+        let regular = entry.regular();
+        let start = regular.metadata.start_cluster();
+        let bytes_per_cluster = self.fs.bytes_per_cluster() as u64;
+
+        if is_dir {
+            self.report.directories += 1;
+            let clusters = match start {
+                0 => {
+                    // Without clusters there is nothing to keep; drop the
+                    // entry.
+                    if self.repair {
+                        self.fs.dir_entry_mut(dir, index)?.mark_deleted();
+                    }
+                    self.found(Problem::BadChain { path: path.to_string(), cluster: 0 }, self.repair);
+                    return Ok(());
+                }
+                start => self.claim_chain(path, start)?,
+            };
+            if clusters.is_empty() {
+                return Ok(());
+            }
+            let parent = match self.fs.is_fixed_root(dir) || dir == self.fs.root_dir_cluster {
+                true => 0,
+                false => dir.id(),
+            };
+            return self.check_dir(path, clusters[0], &clusters, Some(parent));
+        }
+
+        self.report.files += 1;
+        let clusters = match start {
+            0 => Vec::new(),
+            start => self.claim_chain(path, start)?,
+        };
+
+        let size = regular.size();
+        let expected = ((size as u64 + bytes_per_cluster - 1) / bytes_per_cluster) as u32;
+        let count = clusters.len() as u32;
+        if count == expected {
+            return Ok(());
+        }
+
+        if self.repair {
+            if count > expected {
+                // Free the clusters past the end of the file.
+                for &cluster in &clusters[expected as usize..] {
+                    self.fs.set_fat_entry(cluster, FatEntry::FREE)?;
+                    self.owners[cluster.id() as usize] = 0;
+                }
+                match expected {
+                    0 => self.fs.dir_entry_mut(dir, index)?.regular_mut().metadata.set_start_cluster(0),
+                    n => self.fs.set_fat_entry(clusters[n as usize - 1], FatEntry::EOC)?,
+                }
+            } else {
+                let new_size = if start == 0 || clusters.is_empty() { 0 } else { count as u64 * bytes_per_cluster };
+                let regular = self.fs.dir_entry_mut(dir, index)?.regular_mut();
+                regular.set_size(new_size as u32);
+                if clusters.is_empty() {
+                    regular.metadata.set_start_cluster(0);
+                }
+            }
+        }
+        self.found(Problem::ChainLength { path: path.to_string(), size, clusters: count, expected }, self.repair);
+        Ok(())
+    }
+
+    fn check_lost_clusters(&mut self) -> io::Result<()> {
+        let end = self.owners.len();
+        let mut lost = vec![false; end];
+        let mut next = vec![0u32; end];
+        let mut referenced = vec![false; end];
+        for id in 2..end {
+            if self.owners[id] != 0 {
+                continue;
+            }
+            match self.fs.fat_entry(Cluster::from(id as u32))?.status() {
+                Status::Free | Status::Bad => {}
+                Status::Data(cluster) => {
+                    lost[id] = true;
+                    next[id] = cluster.id();
+                }
+                _ => lost[id] = true,
+            }
+        }
+        for id in 2..end {
+            let target = next[id] as usize;
+            if lost[id] && target < end && lost[target] {
+                referenced[target] = true;
+            }
+        }
+
+        // Report chains from their heads first; whatever remains is part of
+        // a loop with no head.
+        let mut visited = vec![false; end];
+        for &heads_only in &[true, false] {
+            for id in 2..end {
+                if !lost[id] || visited[id] || (heads_only && referenced[id]) {
+                    continue;
+                }
+
+                let mut count = 0;
+                let mut cur = id;
+                while cur < end && lost[cur] && !visited[cur] {
+                    visited[cur] = true;
+                    count += 1;
+                    if self.repair {
+                        self.fs.set_fat_entry(Cluster::from(cur as u32), FatEntry::FREE)?;
+                    }
+                    cur = next[cur] as usize;
+                }
+                self.found(Problem::LostChain { start: id as u32, clusters: count }, self.repair);
+            }
+        }
+        Ok(())
+    }
+}
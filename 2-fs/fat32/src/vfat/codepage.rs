@@ -0,0 +1,87 @@
+/// An OEM code page: the character set short (8.3) names are stored in.
+///
+/// Bytes below 0x80 are ASCII in every code page; the code page determines
+/// the characters of the bytes above.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CodePage {
+    /// Code page 437, the original IBM PC character set and the default.
+    Cp437,
+    /// Code page 850, DOS Latin-1, used by most Western European systems.
+    Cp850,
+}
+
+impl Default for CodePage {
+    fn default() -> CodePage {
+        CodePage::Cp437
+    }
+}
+
+impl CodePage {
+    /// Returns the code page with the given number, e.g. `437`, if it is
+    /// supported.
+    pub fn from_number(number: u32) -> Option<CodePage> {
+        match number {
+            437 => Some(CodePage::Cp437),
+            850 => Some(CodePage::Cp850),
+            _ => None,
+        }
+    }
+
+    /// The number of the code page, e.g. `437`.
+    pub fn number(&self) -> u32 {
+        match self {
+            CodePage::Cp437 => 437,
+            CodePage::Cp850 => 850,
+        }
+    }
+
+    /// Returns the character `byte` stands for.
+    pub fn decode(&self, byte: u8) -> char {
+        if byte < 0x80 {
+            return byte as char;
+        }
+
+        let high = match self {
+            CodePage::Cp437 => &CP437_HIGH,
+            CodePage::Cp850 => &CP850_HIGH,
+        };
+        high[byte as usize - 0x80]
+    }
+
+    /// Returns the byte standing for `c`, if the code page has one.
+    pub fn encode(&self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+
+        let high = match self {
+            CodePage::Cp437 => &CP437_HIGH,
+            CodePage::Cp850 => &CP850_HIGH,
+        };
+        high.iter().position(|&h| h == c).map(|i| (i + 0x80) as u8)
+    }
+}
+
+/// The characters of bytes 0x80 to 0xFF in code page 437.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// The characters of bytes 0x80 to 0xFF in code page 850.
+const CP850_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À', '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î', 'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ', 'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{AD}', '±', '‗', '¾', '¶', '§', '÷', '¸', '°', '¨', '·', '¹', '³', '²', '■', '\u{A0}',
+];
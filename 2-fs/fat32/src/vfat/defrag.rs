@@ -0,0 +1,204 @@
+use std::io;
+use std::path::Path;
+
+use traits::{Dir as DirTrait, Entry as EntryTrait, FileSystem, Metadata};
+use vfat::{Cluster, Entry, Shared, Status, VFat};
+
+/// The fragmentation of a single file or directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragments {
+    /// The absolute path of the entry.
+    pub path: String,
+    pub is_dir: bool,
+    /// The number of clusters in the entry's chain.
+    pub clusters: u32,
+    /// The number of contiguous runs the chain consists of; `1` if the entry
+    /// is contiguous and `0` if it occupies no clusters.
+    pub fragments: u32,
+}
+
+/// The result of `fragmentation`.
+#[derive(Debug, Clone, Default)]
+pub struct FragmentationReport {
+    /// Every file and directory of the volume except the root directory, in
+    /// directory order.
+    pub entries: Vec<Fragments>,
+    /// The number of contiguous runs of free clusters.
+    pub free_runs: u32,
+    /// The length, in clusters, of the longest run of free clusters: the
+    /// largest file that can be made contiguous.
+    pub largest_free_run: u32,
+}
+
+impl FragmentationReport {
+    /// Returns the entries that consist of more than one fragment.
+    pub fn fragmented(&self) -> Vec<&Fragments> {
+        self.entries.iter().filter(|entry| entry.fragments > 1).collect()
+    }
+
+    /// The percentage, rounded down, of entries occupying clusters that are
+    /// fragmented.
+    pub fn fragmented_percent(&self) -> u32 {
+        let allocated = self.entries.iter().filter(|entry| entry.clusters > 0).count();
+        match allocated {
+            0 => 0,
+            allocated => (self.fragmented().len() * 100 / allocated) as u32,
+        }
+    }
+}
+
+/// Returns the number of contiguous runs in `chain`.
+fn count_fragments(chain: &[Cluster]) -> u32 {
+    if chain.is_empty() {
+        return 0;
+    }
+    1 + chain.windows(2).filter(|pair| pair[1].id() != pair[0].id() + 1).count() as u32
+}
+
+/// Returns the first cluster and length of every run of free clusters.
+fn free_runs(fs: &mut VFat) -> io::Result<Vec<(u32, u32)>> {
+    let mut runs = Vec::new();
+    let mut run: Option<(u32, u32)> = None;
+    for id in 2..fs.data_clusters() + 2 {
+        if fs.fat_entry(Cluster::from(id))?.status() == Status::Free {
+            run = Some(match run {
+                Some((start, len)) => (start, len + 1),
+                None => (id, 1),
+            });
+        } else if let Some(finished) = run.take() {
+            runs.push(finished);
+        }
+    }
+    runs.extend(run);
+    Ok(runs)
+}
+
+/// Returns the cluster chain of `entry`, which is empty if the entry
+/// occupies no clusters.
+fn entry_chain(fs: &mut VFat, entry: &Entry) -> io::Result<Vec<Cluster>> {
+    let start = match *entry {
+        Entry::File(ref file) => file.start_cluster(),
+        Entry::Dir(ref dir) => dir.cluster(),
+    };
+    if !start.is_valid() || fs.is_fixed_root(start) {
+        return Ok(Vec::new());
+    }
+    fs.chain(start)
+}
+
+/// Walks every file and directory of the volume `vfat` and reports how
+/// fragmented their cluster chains are, along with the fragmentation of the
+/// free space.
+///
+/// # Errors
+///
+/// Returns an error if reading the volume fails or a cluster chain is
+/// invalid.
+pub fn fragmentation(vfat: &Shared<VFat>) -> io::Result<FragmentationReport> {
+    fn walk(vfat: &Shared<VFat>, path: &Path, report: &mut FragmentationReport) -> io::Result<()> {
+        let dir = match vfat.open(path)? {
+            Entry::Dir(dir) => dir,
+            Entry::File(_) => return Ok(()),
+        };
+        let mut iter = dir.entries()?;
+        let entries: Vec<Entry> = iter.by_ref()
+            .filter(|entry| entry.name() != "." && entry.name() != ".." && !entry.metadata().is_volume_label())
+            .collect();
+        iter.take_error()?;
+
+        for entry in entries {
+            let child = path.join(entry.name());
+            let chain = entry_chain(&mut vfat.borrow_mut(), &entry)?;
+            report.entries.push(Fragments {
+                path: child.to_string_lossy().into_owned(),
+                is_dir: entry.is_dir(),
+                clusters: chain.len() as u32,
+                fragments: count_fragments(&chain),
+            });
+            if entry.is_dir() {
+                walk(vfat, &child, report)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut report = FragmentationReport::default();
+    walk(vfat, Path::new("/"), &mut report)?;
+
+    let runs = free_runs(&mut vfat.borrow_mut())?;
+    report.free_runs = runs.len() as u32;
+    report.largest_free_run = runs.iter().map(|&(_, len)| len).max().unwrap_or(0);
+    Ok(report)
+}
+
+/// Moves the clusters of the file or directory at `path` into a single
+/// contiguous run, updating the FAT and the entry's directory entry, and
+/// returns the number of fragments it consisted of before. Entries that are
+/// already contiguous are left in place.
+///
+/// The data is copied to the first run of free clusters large enough to hold
+/// it before the entry is updated and the old clusters are freed. The entry
+/// must not be open while it is moved: `File`s and `Dir`s opened before refer
+/// to the old clusters.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `path` is the root directory
+/// and of kind `Other` if no run of free clusters is large enough.
+pub fn defragment<P: AsRef<Path>>(vfat: &Shared<VFat>, path: P) -> io::Result<u32> {
+    let entry = vfat.open(path)?;
+    let position = entry.position()
+        .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "cannot move the root directory"))?;
+
+    let mut fs = vfat.borrow_mut();
+    let chain = entry_chain(&mut fs, &entry)?;
+    let fragments = count_fragments(&chain);
+    if fragments <= 1 {
+        return Ok(fragments);
+    }
+
+    let count = chain.len() as u32;
+    let start = free_runs(&mut fs)?
+        .into_iter()
+        .find(|&(_, len)| len >= count)
+        .map(|(start, _)| Cluster::from(start))
+        .ok_or(io::Error::new(io::ErrorKind::Other, "no run of free clusters is large enough"))?;
+
+    let mut buf = vec![0; fs.bytes_per_cluster()];
+    for (i, &old) in chain.iter().enumerate() {
+        fs.read_cluster(old, 0, &mut buf)?;
+        fs.write_cluster(Cluster::from(start.id() + i as u32), 0, &buf)?;
+    }
+    fs.link_contiguous(start, count)?;
+
+    fs.dir_entry_mut(position.dir_cluster, position.index)?
+        .regular_mut()
+        .metadata
+        .set_start_cluster(start.id());
+    if entry.is_dir() {
+        // The directory's own `.` entry and the `..` entries of its
+        // subdirectories refer to its first cluster.
+        fs.dir_entry_mut(start, 0)?.regular_mut().metadata.set_start_cluster(start.id());
+        let entries_per_cluster = fs.bytes_per_cluster() / 32;
+        for index in 2..count as usize * entries_per_cluster {
+            let raw = fs.dir_entry(start, index)?;
+            let unknown = raw.unknown();
+            if unknown.prev_is_last_entry() {
+                break;
+            }
+            let child = raw.regular().metadata;
+            if unknown.is_free() || unknown.is_lnf() || !child.is_dir() {
+                continue;
+            }
+            let child_start = Cluster::from(child.start_cluster());
+            if fs.is_data_cluster(child_start) {
+                fs.dir_entry_mut(child_start, 1)?.regular_mut().metadata.set_start_cluster(start.id());
+            }
+        }
+        fs.invalidate_dentries_in(chain[0]);
+    }
+
+    fs.free_chain(chain[0])?;
+    fs.flush()?;
+    Ok(fragments)
+}
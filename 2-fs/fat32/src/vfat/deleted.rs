@@ -0,0 +1,158 @@
+use std::io;
+
+use vfat::{Cluster, Dir, Metadata, Status, VFat};
+use vfat::dir::{decode_long_name, EntryPosition, RawEntries, VFatLfnDirEntry};
+use vfat::name;
+
+/// A deleted entry of a directory, as listed by `Dir::deleted_entries()`.
+#[derive(Debug, Clone)]
+pub struct DeletedEntry {
+    /// The long name of the entry if its long file name entries survived,
+    /// otherwise its short name with the lost first character shown as `?`.
+    pub name: String,
+    /// Whether `name` was recovered completely.
+    pub name_complete: bool,
+    /// The metadata of the entry as it was when the entry was deleted,
+    /// including its start cluster and size.
+    pub metadata: Metadata,
+    /// Whether the entry's data can be restored: its clusters lie within the
+    /// volume and are all still free. The data may nevertheless have been
+    /// overwritten by a file that was since deleted too.
+    pub recoverable: bool,
+    /// The raw 8.3 name of the entry, whose first byte was overwritten.
+    pub(crate) short_name: [u8; 11],
+    pub(crate) position: EntryPosition,
+}
+
+impl DeletedEntry {
+    /// The number of clusters the entry's data occupied, assuming its chain
+    /// was contiguous. Only the first cluster of a directory is assumed to
+    /// be its own.
+    pub(crate) fn clusters(&self, bytes_per_cluster: usize) -> u32 {
+        use traits::Metadata;
+
+        if self.metadata.is_dir() {
+            return 1;
+        }
+        let len = self.metadata.len();
+        ((len + bytes_per_cluster as u64 - 1) / bytes_per_cluster as u64) as u32
+    }
+}
+
+/// Reassembles the long name stored in `lfn`, the deleted LFN entries
+/// directly preceding a deleted entry with the raw short name `short_name`,
+/// in on-disk order. Returns the name and the number of LFN entries that
+/// stored it, or `None` if the entries do not belong to the short name.
+fn recover_long_name(lfn: &[VFatLfnDirEntry], short_name: &[u8; 11]) -> Option<(String, usize)> {
+    let checksum = lfn.last()?.checksum();
+    let count = lfn.iter()
+        .rev()
+        .take_while(|entry| entry.checksum() == checksum)
+        .count();
+    if count > name::MAX_LFN_LEN / 13 + 1 {
+        return None;
+    }
+
+    // Deletion overwrote the first byte of the short name, but the checksum
+    // is a bijection of the first byte for the other ten: exactly one first
+    // byte matches. The entries belong to the short name if it is valid.
+    let mut raw = *short_name;
+    let first = (0..=255u8).find(|&b| {
+        raw[0] = b;
+        name::lfn_checksum(&raw) == checksum
+    })?;
+    if (first < 0x20 && first != 0x05) || first == b' ' || first == 0xE5 {
+        return None;
+    }
+
+    let long_name = decode_long_name(&lfn[lfn.len() - count..]);
+    if long_name.is_empty() {
+        return None;
+    }
+    Some((long_name, count))
+}
+
+/// Returns whether the `count` clusters starting at `start` are all data
+/// clusters that are free.
+pub(crate) fn clusters_free(fs: &mut VFat, start: Cluster, count: u32) -> io::Result<bool> {
+    for id in (start.id() as u64)..(start.id() as u64 + count as u64) {
+        if id > ::std::u32::MAX as u64 || !fs.is_data_cluster(Cluster::from(id as u32)) {
+            return Ok(false);
+        }
+        let cluster = Cluster::from(id as u32);
+        if fs.fat_entry(cluster)?.status() != Status::Free {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+impl Dir {
+    /// Returns the deleted entries of `self` whose directory entries have not
+    /// been reused, in directory order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be read.
+    pub fn deleted_entries(&self) -> io::Result<Vec<DeletedEntry>> {
+        let code_page = self.fs().borrow().code_page();
+
+        let mut lfn: Vec<VFatLfnDirEntry> = Vec::new();
+        let mut deleted = Vec::new();
+        let mut raw = RawEntries::new(self.cluster());
+        'scan: while raw.read_next(&mut self.fs().borrow_mut())? {
+            for (i, entry) in raw.entries().iter().enumerate() {
+                let index = raw.start() + i;
+                let unknown = entry.unknown();
+                if unknown.prev_is_last_entry() {
+                    break 'scan;
+                } else if !unknown.is_deleted_or_unused() {
+                    lfn.clear();
+                    continue;
+                } else if unknown.is_lnf() {
+                    // Only the entries of one name, and one more to tell that
+                    // there are too many, are needed to recover it.
+                    if lfn.len() > name::MAX_LFN_LEN / 13 + 1 {
+                        lfn.remove(0);
+                    }
+                    lfn.push(entry.long_filename());
+                    continue;
+                } else if unknown.is_volume_label() {
+                    lfn.clear();
+                    continue;
+                }
+
+                let regular = entry.regular();
+                let short_name = regular.short_name();
+                let (name, name_complete, first) = match recover_long_name(&lfn, &short_name) {
+                    Some((long_name, count)) => (long_name, true, index - count),
+                    None => {
+                        let mut name = name::decode_short_name(&short_name, code_page);
+                        name.replace_range(..name.chars().next().map_or(0, |c| c.len_utf8()), "?");
+                        (name, false, index)
+                    }
+                };
+                lfn.clear();
+
+                let mut deleted_entry = DeletedEntry {
+                    name,
+                    name_complete,
+                    metadata: regular.metadata,
+                    recoverable: false,
+                    short_name,
+                    position: EntryPosition { dir_cluster: self.cluster(), first, index },
+                };
+                let start = Cluster::from(regular.metadata.start_cluster());
+                let mut fs = self.fs().borrow_mut();
+                let count = deleted_entry.clusters(fs.bytes_per_cluster());
+                deleted_entry.recoverable = match (start.is_valid(), count) {
+                    (_, 0) => true,
+                    (false, _) => false,
+                    (true, count) => clusters_free(&mut fs, start, count)?,
+                };
+                deleted.push(deleted_entry);
+            }
+        }
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,185 @@
+extern crate hashbrown;
+
+use self::hashbrown::HashMap;
+use vfat::dir::EntryPosition;
+
+/// Counters describing the effectiveness of the directory entry cache.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DentryStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that required searching the directory.
+    pub misses: u64,
+    /// Entries dropped from the cache to make room for another.
+    pub evictions: u64,
+    /// Entries dropped because the directory entry they refer to changed.
+    pub invalidations: u64,
+}
+
+/// The number of lookups cached by default.
+pub const DEFAULT_DENTRY_CAPACITY: usize = 256;
+
+/// The result of looking up a name in a directory: the name of the entry
+/// found and where its directory entry is.
+#[derive(Debug, Clone)]
+pub(crate) struct Dentry {
+    /// The name of the entry, which may differ from the name looked up in
+    /// case or by being the long name of a short name alias.
+    pub name: String,
+    pub position: EntryPosition,
+}
+
+#[derive(Debug)]
+struct Slot {
+    key: (u32, String),
+    dentry: Dentry,
+    /// Set on every hit; cleared as the eviction hand passes the slot.
+    referenced: bool,
+}
+
+/// A cache of at most `capacity` lookups of a name in a directory, keyed by
+/// the first cluster of the directory and the case-folded name. Entries are
+/// evicted using the CLOCK approximation of LRU.
+///
+/// Only the location of an entry is cached; its metadata is read from the
+/// directory entry on every hit. Callers must invalidate cached lookups when
+/// the directory entries they refer to are deleted.
+#[derive(Debug)]
+pub(crate) struct DentryCache {
+    map: HashMap<(u32, String), usize>,
+    slots: Vec<Slot>,
+    capacity: usize,
+    hand: usize,
+    stats: DentryStats,
+}
+
+impl DentryCache {
+    /// Returns an empty cache holding at most `capacity` lookups. A capacity
+    /// of zero disables the cache.
+    pub fn new(capacity: usize) -> DentryCache {
+        DentryCache {
+            map: HashMap::new(),
+            slots: Vec::new(),
+            capacity,
+            hand: 0,
+            stats: DentryStats::default(),
+        }
+    }
+
+    /// The maximum number of lookups held in the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the maximum number of lookups held in the cache, evicting
+    /// lookups if it currently holds more than `capacity`.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        while self.slots.len() > capacity {
+            let last = self.slots.len() - 1;
+            self.remove(last);
+            self.stats.evictions += 1;
+        }
+        self.hand = 0;
+        self.capacity = capacity;
+    }
+
+    pub fn stats(&self) -> DentryStats {
+        self.stats
+    }
+
+    /// Returns the cached lookup of the case-folded `name` in the directory
+    /// starting at cluster `dir`, counting a hit or a miss.
+    pub fn get(&mut self, dir: u32, name: &str) -> Option<Dentry> {
+        match self.map.get(&(dir, name.to_string())) {
+            Some(&slot) => {
+                self.stats.hits += 1;
+                self.slots[slot].referenced = true;
+                Some(self.slots[slot].dentry.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches that looking up the case-folded `name` in the directory
+    /// starting at cluster `dir` finds `dentry`.
+    pub fn insert(&mut self, dir: u32, name: String, dentry: Dentry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (dir, name);
+        if let Some(&slot) = self.map.get(&key) {
+            self.slots[slot].dentry = dentry;
+            return;
+        }
+
+        let slot = Slot { key: key.clone(), dentry, referenced: false };
+        if self.slots.len() < self.capacity {
+            self.map.insert(key, self.slots.len());
+            self.slots.push(slot);
+            return;
+        }
+
+        loop {
+            let i = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            if self.slots[i].referenced {
+                self.slots[i].referenced = false;
+            } else {
+                self.map.remove(&self.slots[i].key);
+                self.map.insert(key, i);
+                self.slots[i] = slot;
+                self.stats.evictions += 1;
+                return;
+            }
+        }
+    }
+
+    /// Drops every lookup that found the entry at `position`.
+    pub fn invalidate(&mut self, position: EntryPosition) {
+        self.invalidate_where(|dentry| {
+            dentry.position.dir_cluster == position.dir_cluster && dentry.position.index == position.index
+        });
+    }
+
+    /// Drops every lookup of an entry in the directory starting at cluster
+    /// `dir`, e.g. because the directory was removed.
+    pub fn invalidate_dir(&mut self, dir: u32) {
+        self.invalidate_where(|dentry| dentry.position.dir_cluster.id() == dir);
+    }
+
+    /// Drops every lookup.
+    pub fn clear(&mut self) {
+        self.stats.invalidations += self.slots.len() as u64;
+        self.map.clear();
+        self.slots.clear();
+        self.hand = 0;
+    }
+
+    fn invalidate_where<F: Fn(&Dentry) -> bool>(&mut self, stale: F) {
+        let mut i = 0;
+        while i < self.slots.len() {
+            if stale(&self.slots[i].dentry) {
+                self.remove(i);
+                self.stats.invalidations += 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Removes the lookup in `slot`, moving the last slot into its place.
+    fn remove(&mut self, slot: usize) {
+        let removed = self.slots.swap_remove(slot);
+        self.map.remove(&removed.key);
+        if slot < self.slots.len() {
+            *self.map.get_mut(&self.slots[slot].key).unwrap() = slot;
+        }
+        if self.hand >= self.slots.len() {
+            self.hand = 0;
+        }
+    }
+}
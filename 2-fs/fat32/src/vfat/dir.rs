@@ -1,18 +1,32 @@
-use core::char::decode_utf16;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use std::ffi::OsStr;
 use std::io;
 
 use traits;
 use util::VecExt;
-use vfat::{Cluster, Entry, File, Shared, VFat};
+use vfat::{Cluster, CodePage, Entry, Shared, Timestamp, VFat};
 use vfat::Metadata;
+use vfat::name;
 
 #[derive(Debug)]
 pub struct Dir {
     cluster: Cluster,
     fs: Shared<VFat>,
     pub name: String,
-    pub metadata: Metadata
+    pub metadata: Metadata,
+    position: Option<EntryPosition>,
+}
+
+/// The location of a directory entry inside of its parent directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EntryPosition {
+    /// The first cluster of the parent directory.
+    pub dir_cluster: Cluster,
+    /// The index of the first entry of the entry's set, i.e., its first LFN
+    /// entry, or `index` if the entry has no long file name.
+    pub first: usize,
+    /// The index of the regular (8.3) entry.
+    pub index: usize,
 }
 
 #[repr(C, packed)]
@@ -20,8 +34,8 @@ pub struct Dir {
 pub struct VFatRegularDirEntry {
     file_name: [u8; 8],
     file_ext: [u8; 3],
+    /// The entry's attributes, timestamps, start cluster and size.
     pub metadata: Metadata,
-    size: u32,
 }
 
 #[repr(C, packed)]
@@ -47,51 +61,303 @@ pub struct VFatUnknownDirEntry {
 }
 
 impl VFatUnknownDirEntry {
-    fn is_deleted_or_unused(&self) -> bool {
+    pub(crate) fn is_free(&self) -> bool {
+        self.is_deleted_or_unused() || self.prev_is_last_entry()
+    }
+
+    pub(crate) fn is_deleted_or_unused(&self) -> bool {
         self.entry_type == 0xE5
     }
 
-    fn prev_is_last_entry(&self) -> bool {
+    pub(crate) fn prev_is_last_entry(&self) -> bool {
         self.entry_type == 0x00
     }
 
-    fn is_regular_directory(&self) -> bool {
+    pub(crate) fn is_regular_directory(&self) -> bool {
         (self.attributes & 0x10) == 0x10
     }
 
-    fn is_lnf(&self) -> bool {
+    pub(crate) fn is_lnf(&self) -> bool {
         self.attributes == (0x01 | 0x02 | 0x04 | 0x08)
     }
+
+    /// Whether the entry is the volume label entry of a root directory.
+    pub(crate) fn is_volume_label(&self) -> bool {
+        !self.is_lnf() && (self.attributes & 0x08) == 0x08
+    }
+}
+
+impl VFatRegularDirEntry {
+    pub(crate) fn new(short_name: [u8; 11], metadata: Metadata, size: u32) -> VFatRegularDirEntry {
+        let mut file_name = [0u8; 8];
+        let mut file_ext = [0u8; 3];
+        file_name.copy_from_slice(&short_name[..8]);
+        file_ext.copy_from_slice(&short_name[8..]);
+        let mut metadata = metadata;
+        metadata.set_len(size);
+        VFatRegularDirEntry { file_name, file_ext, metadata }
+    }
+
+    /// The raw, space-padded 8.3 name of the entry.
+    pub(crate) fn short_name(&self) -> [u8; 11] {
+        let mut short_name = [0u8; 11];
+        short_name[..8].copy_from_slice(&{ self.file_name });
+        short_name[8..].copy_from_slice(&{ self.file_ext });
+        short_name
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        traits::Metadata::len(&self.metadata) as u32
+    }
+
+    pub(crate) fn set_size(&mut self, size: u32) {
+        self.metadata.set_len(size);
+    }
+}
+
+impl VFatLfnDirEntry {
+    /// The sequence number of the entry, including the 0x40 flag marking the
+    /// entry holding the last part of the name.
+    pub(crate) fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    /// The checksum of the short name the entry belongs to.
+    pub(crate) fn checksum(&self) -> u8 {
+        self.checksum
+    }
+
+    /// The 13 UTF-16 code units of the name stored in the entry.
+    pub(crate) fn name_units(&self) -> [u16; 13] {
+        let mut units = [0u16; 13];
+        units[..5].copy_from_slice(&{ self.name });
+        units[5..11].copy_from_slice(&{ self.name_2 });
+        units[11..].copy_from_slice(&{ self.name_3 });
+        units
+    }
+
+    /// Returns the LFN entries storing `name` for the short name with
+    /// checksum `checksum`, in on-disk order: the entry holding the last part
+    /// of the name comes first.
+    /// Returns the number of LFN entries that store `name`.
+    fn count_for(name: &str) -> usize {
+        (name.encode_utf16().count() + 12) / 13
+    }
+
+    fn for_name(name: &str, checksum: u8) -> Vec<VFatLfnDirEntry> {
+        let mut units: Vec<u16> = name.encode_utf16().collect();
+        if units.len() % 13 != 0 {
+            units.push(0x0000);
+            while units.len() % 13 != 0 {
+                units.push(0xFFFF);
+            }
+        }
+
+        let count = units.len() / 13;
+        let mut entries: Vec<VFatLfnDirEntry> = units.chunks(13).enumerate().map(|(i, chunk)| {
+            let mut entry = VFatLfnDirEntry {
+                sequence: (i + 1) as u8 | if i + 1 == count { 0x40 } else { 0 },
+                name: [0; 5],
+                attributes: 0x0F,
+                entry_type: 0,
+                checksum,
+                name_2: [0; 6],
+                __r: 0,
+                name_3: [0; 2],
+            };
+            let (mut name, mut name_2, mut name_3) = ([0u16; 5], [0u16; 6], [0u16; 2]);
+            name.copy_from_slice(&chunk[..5]);
+            name_2.copy_from_slice(&chunk[5..11]);
+            name_3.copy_from_slice(&chunk[11..]);
+            entry.name = name;
+            entry.name_2 = name_2;
+            entry.name_3 = name_3;
+            entry
+        }).collect();
+
+        entries.reverse();
+        entries
+    }
 }
 
+/// Decodes the long file name stored in `entries`, LFN entries in on-disk
+/// order: the entry holding the last part of the name first. The name ends
+/// at the first NUL or padding code unit; invalid UTF-16 is replaced with
+/// U+FFFD.
+pub(crate) fn decode_long_name(entries: &[VFatLfnDirEntry]) -> String {
+    let units: Vec<u16> = entries.iter().rev().flat_map(|entry| entry.name_units().to_vec()).collect();
+    let len = units.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or(units.len());
+    decode_utf16(units[..len].iter().cloned())
+        .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
+        .collect()
+}
 
+/// The long file name of a regular directory entry, as found by
+/// `LfnParser::finish()`.
+pub(crate) enum LongName {
+    /// No LFN entries precede the entry.
+    None,
+    /// The long file name and the index of its first LFN entry.
+    Valid(String, usize),
+    /// The LFN entries from the first to the second index, inclusive, are
+    /// incomplete or belong to another short name.
+    Orphaned(usize, usize),
+}
+
+/// Collects the long file name entries preceding each regular entry while a
+/// directory is scanned in order, validating their sequence numbers and
+/// checksums.
+///
+/// A set starts with the entry flagged as holding the last part of the name
+/// and continues with decreasing sequence numbers down to 1, all with the
+/// same checksum. Entries that break the sequence are orphans, reported as
+/// ranges of directory indices.
+#[derive(Default)]
+pub(crate) struct LfnParser {
+    /// The index of the first entry of the pending set.
+    first: usize,
+    /// The entries of the pending set, in on-disk order.
+    entries: Vec<VFatLfnDirEntry>,
+}
+
+impl LfnParser {
+    /// Returns the range of the entries of the pending set, if any.
+    fn pending(&self) -> Option<(usize, usize)> {
+        match self.entries.len() {
+            0 => None,
+            len => Some((self.first, self.first + len - 1)),
+        }
+    }
+
+    /// Drops the pending set, e.g. because a free entry follows it, and
+    /// returns the range of its entries.
+    pub fn abandon(&mut self) -> Option<(usize, usize)> {
+        let pending = self.pending();
+        self.entries.clear();
+        pending
+    }
+
+    /// Adds the LFN entry `entry` at directory index `index`, which must
+    /// directly follow the entries added since the set was last finished or
+    /// abandoned. Returns the range of the entries that became orphans.
+    pub fn push(&mut self, index: usize, entry: VFatLfnDirEntry) -> Option<(usize, usize)> {
+        let number = entry.sequence() & 0x1F;
+        let valid_number = number >= 1 && number as usize <= MAX_LFN_ENTRIES;
+        if entry.sequence() & 0x40 != 0 && valid_number {
+            let orphans = self.abandon();
+            self.first = index;
+            self.entries.push(entry);
+            return orphans;
+        }
+
+        let continues = match self.entries.last() {
+            Some(last) => {
+                entry.sequence() & 0x40 == 0
+                    && number + 1 == last.sequence() & 0x1F
+                    && entry.checksum() == last.checksum()
+            }
+            None => false,
+        };
+        if continues && valid_number {
+            self.entries.push(entry);
+            return None;
+        }
+
+        let first = self.pending().map_or(index, |(first, _)| first);
+        self.entries.clear();
+        Some((first, index))
+    }
+
+    /// Ends the pending set at the regular entry with the raw short name
+    /// `short_name` and returns its long file name.
+    pub fn finish(&mut self, short_name: &[u8; 11]) -> LongName {
+        let complete = self.entries.last().map_or(false, |last| {
+            last.sequence() & 0x1F == 1 && last.checksum() == name::lfn_checksum(short_name)
+        });
+        let long_name = match self.pending() {
+            None => LongName::None,
+            Some((first, _)) if complete => LongName::Valid(decode_long_name(&self.entries), first),
+            Some((first, last)) => LongName::Orphaned(first, last),
+        };
+        self.entries.clear();
+        long_name
+    }
+}
+
+/// The largest number of LFN entries a name can take.
+const MAX_LFN_ENTRIES: usize = name::MAX_LFN_LEN / 13 + 1;
+
+#[derive(Copy, Clone)]
 pub union VFatDirEntry {
     unknown: VFatUnknownDirEntry,
     regular: VFatRegularDirEntry,
     long_filename: VFatLfnDirEntry,
 }
 
+impl VFatDirEntry {
+    pub(crate) fn unknown(&self) -> VFatUnknownDirEntry {
+        unsafe { self.unknown }
+    }
+
+    pub(crate) fn regular(&self) -> VFatRegularDirEntry {
+        unsafe { self.regular }
+    }
+
+    pub(crate) fn long_filename(&self) -> VFatLfnDirEntry {
+        unsafe { self.long_filename }
+    }
+
+    /// Interprets `self` as a regular (8.3) directory entry.
+    pub(crate) fn regular_mut(&mut self) -> &mut VFatRegularDirEntry {
+        unsafe { &mut self.regular }
+    }
+
+    /// Marks the entry as deleted.
+    pub(crate) fn mark_deleted(&mut self) {
+        let mut unknown = unsafe { self.unknown };
+        unknown.entry_type = 0xE5;
+        self.unknown = unknown;
+    }
+}
+
+impl From<VFatRegularDirEntry> for VFatDirEntry {
+    fn from(regular: VFatRegularDirEntry) -> VFatDirEntry {
+        VFatDirEntry { regular }
+    }
+}
+
+impl From<VFatLfnDirEntry> for VFatDirEntry {
+    fn from(long_filename: VFatLfnDirEntry) -> VFatDirEntry {
+        VFatDirEntry { long_filename }
+    }
+}
+
 impl Dir {
-    /// Finds the entry named `name` in `self` and returns it. Comparison is
-    /// case-insensitive.
+    /// Finds the entry named `name` in `self` and returns it. `name` may be
+    /// either the long name or the short (8.3) name of the entry, e.g.
+    /// `PROGRA~1`. Comparison is case-insensitive, using Unicode simple case
+    /// folding.
     ///
     /// # Errors
     ///
     /// If no entry with name `name` exists in `self`, an error of `NotFound` is
-    /// returned.
+    /// returned. Errors reading the directory are returned as is.
     ///
     /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
     /// is returned.
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
         use traits::{Dir, Entry};
 
-        let name_str = name.as_ref().to_str().unwrap();
+        let name_str = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid UTF-8 in name"))?;
 
-        for t in self.entries().unwrap() {
-            if name_str.eq_ignore_ascii_case(t.name()) {
-                return Ok(t);
+        let mut entries = self.entries()?;
+        while let Some((entry, short_name)) = entries.next_with_short_name() {
+            if name::eq_ignore_case(name_str, entry.name()) || name::eq_ignore_case(name_str, &short_name) {
+                return Ok(entry);
             }
         }
+        entries.take_error()?;
         Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
     }
 
@@ -102,8 +368,193 @@ impl Dir {
             fs: fs.clone(),
             name: String::new(),
             metadata: Metadata::default(),
+            position: None,
         }
     }
+
+    pub(crate) fn new(
+        cluster: Cluster,
+        fs: Shared<VFat>,
+        name: String,
+        metadata: Metadata,
+        position: Option<EntryPosition>,
+    ) -> Dir {
+        Dir { cluster, fs, name, metadata, position }
+    }
+
+    /// The first cluster of the directory.
+    pub(crate) fn cluster(&self) -> Cluster {
+        self.cluster
+    }
+
+    /// The location of the directory's entry in its parent, or `None` for the
+    /// root directory.
+    pub(crate) fn position(&self) -> Option<EntryPosition> {
+        self.position
+    }
+
+    /// Whether `self` is the root directory.
+    pub fn is_root(&self) -> bool {
+        self.cluster == self.fs.borrow().root_dir_cluster
+    }
+
+    /// The file system the directory belongs to.
+    pub(crate) fn fs(&self) -> &Shared<VFat> {
+        &self.fs
+    }
+
+    /// Adds an entry named `name` with metadata `metadata` and size `size` to
+    /// `self`, including any LFN entries `name` requires, and returns its
+    /// position. The directory is extended by a cluster if it has no room for
+    /// the entry set.
+    ///
+    /// The caller must ensure that no entry named `name` already exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `name` is not a valid file
+    /// name and of kind `AlreadyExists` if `name` is a short name that is
+    /// already in use as an alias of another entry.
+    pub(crate) fn insert(&self, name: &str, metadata: Metadata, size: u32) -> io::Result<EntryPosition> {
+        self.insert_at(name, metadata, size, None)
+    }
+
+    /// Adds an entry like `insert()`, but places it in the slots `first` to
+    /// `index` of `preferred`, e.g. those of the deleted entry it restores,
+    /// if they are still free and the entry set fits. The regular entry is
+    /// then stored at `preferred.index`.
+    pub(crate) fn insert_at(
+        &self,
+        name: &str,
+        metadata: Metadata,
+        size: u32,
+        preferred: Option<EntryPosition>,
+    ) -> io::Result<EntryPosition> {
+        name::validate(name)?;
+
+        let exact_short_name = name::exact_short_name(name);
+        let count = match exact_short_name {
+            Some(_) => 1,
+            None => VFatLfnDirEntry::count_for(name) + 1,
+        };
+        let preferred = preferred.filter(|position| {
+            position.dir_cluster == self.cluster && position.index - position.first + 1 >= count
+        });
+
+        // Scans the directory a cluster at a time, keeping only the short
+        // names in use, the first run of `count` free slots and how many of
+        // the preferred slots are free.
+        let mut existing: Vec<[u8; 11]> = Vec::new();
+        let mut ended = false;
+        let (mut run, mut free_run, mut preferred_free) = (0, None, 0);
+        let mut raw = RawEntries::new(self.cluster);
+        while raw.read_next(&mut self.fs.borrow_mut())? {
+            for (i, entry) in raw.entries().iter().enumerate() {
+                let index = raw.start() + i;
+                let unknown = entry.unknown();
+                ended |= unknown.prev_is_last_entry();
+                if !ended && !unknown.is_deleted_or_unused() && !unknown.is_lnf() {
+                    existing.push(entry.regular().short_name());
+                }
+                if !unknown.is_free() {
+                    run = 0;
+                    continue;
+                }
+
+                run += 1;
+                if run == count && free_run.is_none() {
+                    free_run = Some(index + 1 - count);
+                }
+                if let Some(ref position) = preferred {
+                    if index >= position.first && index <= position.index {
+                        preferred_free += 1;
+                    }
+                }
+            }
+        }
+
+        let mut entries: Vec<VFatDirEntry> = Vec::new();
+        let short_name = match exact_short_name {
+            Some(short_name) => {
+                if existing.contains(&short_name) {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, "short name in use"));
+                }
+                short_name
+            }
+            None => {
+                let short_name = name::generate_short_name(name, &existing)?;
+                let checksum = name::lfn_checksum(&short_name);
+                entries.extend(VFatLfnDirEntry::for_name(name, checksum).into_iter().map(VFatDirEntry::from));
+                short_name
+            }
+        };
+        entries.push(VFatRegularDirEntry::new(short_name, metadata, size).into());
+
+        let first = match (preferred, free_run) {
+            (Some(position), _) if preferred_free == position.index - position.first + 1 => {
+                position.index + 1 - count
+            }
+            (_, Some(first)) => first,
+            _ => self.extend(raw.last_cluster(), raw.end(), run, count)?,
+        };
+        let mut fs = self.fs.borrow_mut();
+        for (i, entry) in entries.iter().enumerate() {
+            *fs.dir_entry_mut(self.cluster, first + i)? = *entry;
+        }
+
+        Ok(EntryPosition {
+            dir_cluster: self.cluster,
+            first,
+            index: first + entries.len() - 1,
+        })
+    }
+
+    /// Extends the directory, whose `len` entries end in `run` free ones and
+    /// whose last cluster is `last`, with zeroed clusters until it ends in
+    /// `count` free entries, and returns the index of the first of them.
+    /// `last` is `None` for the fixed root directory, which cannot grow.
+    fn extend(&self, last: Option<Cluster>, mut len: usize, mut run: usize, count: usize) -> io::Result<usize> {
+        let mut last = match last {
+            Some(last) => last,
+            None => return Err(io::Error::new(io::ErrorKind::Other, "root directory is full")),
+        };
+
+        let mut fs = self.fs.borrow_mut();
+        let entries_per_cluster = fs.bytes_per_cluster() / ::std::mem::size_of::<VFatDirEntry>();
+        while run < count {
+            last = fs.alloc_cluster(Some(last))?;
+            fs.zero_cluster(last)?;
+            run += entries_per_cluster;
+            len += entries_per_cluster;
+        }
+
+        Ok(len - run)
+    }
+
+    /// Marks the entry set at `position` as deleted.
+    pub(crate) fn remove_entry(fs: &mut VFat, position: EntryPosition) -> io::Result<()> {
+        for index in position.first..=position.index {
+            fs.dir_entry_mut(position.dir_cluster, index)?.mark_deleted();
+        }
+        fs.invalidate_dentry(position);
+        Ok(())
+    }
+
+    /// Writes the `.` and `..` entries of a new directory starting at the
+    /// zeroed cluster `cluster` whose parent directory starts at `parent`.
+    /// `parent` must be `0` if the parent is the root directory.
+    pub(crate) fn write_dot_entries(
+        fs: &mut VFat,
+        cluster: Cluster,
+        parent: u32,
+        timestamp: Timestamp,
+    ) -> io::Result<()> {
+        let dot = VFatRegularDirEntry::new(*b".          ", Metadata::new(true, cluster.id(), timestamp), 0);
+        let dot_dot = VFatRegularDirEntry::new(*b"..         ", Metadata::new(true, parent, timestamp), 0);
+        *fs.dir_entry_mut(cluster, 0)? = dot.into();
+        *fs.dir_entry_mut(cluster, 1)? = dot_dot.into();
+        Ok(())
+    }
 }
 
 impl traits::Dir for Dir {
@@ -111,34 +562,151 @@ impl traits::Dir for Dir {
     type Iter = EntryIterator;
 
     fn entries(&self) -> io::Result<Self::Iter> {
+        EntryIterator::new(self.fs.clone(), self.cluster)
+    }
+}
+
+/// A cursor over the raw 32-byte entries of a directory that holds one
+/// cluster of them at a time, or the whole fixed root directory region of a
+/// FAT12 or FAT16 volume.
+pub(crate) struct RawEntries {
+    /// The entries read last.
+    data: Vec<VFatDirEntry>,
+    /// The directory index of `data[0]`.
+    data_start: usize,
+    /// The cluster read last, if any. Not set for the fixed root directory.
+    last_cluster: Option<Cluster>,
+    /// The next cluster of the directory to read, if any.
+    next_cluster: Option<Cluster>,
+    /// The number of clusters read so far, used to detect looping chains.
+    clusters_read: u32,
+}
+
+impl RawEntries {
+    /// Returns a cursor before the first entry of the directory starting at
+    /// `dir_cluster`.
+    pub(crate) fn new(dir_cluster: Cluster) -> RawEntries {
+        RawEntries {
+            data: Vec::new(),
+            data_start: 0,
+            last_cluster: None,
+            next_cluster: Some(dir_cluster),
+            clusters_read: 0,
+        }
+    }
+
+    /// Replaces the entries held with those of the next cluster of the
+    /// directory. Returns `false` if the directory has no more clusters.
+    pub(crate) fn read_next(&mut self, fs: &mut VFat) -> io::Result<bool> {
+        let cluster = match self.next_cluster.take() {
+            Some(cluster) => cluster,
+            None => return Ok(false),
+        };
+
         let mut buf = Vec::new();
-        self.fs.borrow_mut().read_chain(self.cluster, &mut buf)?;
-        Ok(EntryIterator {
-            fs: self.fs.clone(),
-            curr_idx: 0,
-            data: unsafe { buf.cast() },
-        })
+        if fs.is_fixed_root(cluster) {
+            fs.read_dir(cluster, &mut buf)?;
+        } else {
+            self.clusters_read += 1;
+            if self.clusters_read > fs.data_clusters() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+            buf.resize(fs.bytes_per_cluster(), 0);
+            fs.read_cluster(cluster, 0, &mut buf)?;
+            self.next_cluster = fs.fat_entry(cluster)?.next_cluster();
+            self.last_cluster = Some(cluster);
+        }
+
+        self.data_start += self.data.len();
+        self.data = unsafe { buf.cast() };
+        Ok(true)
+    }
+
+    /// The entries held, the first of which has directory index `start()`.
+    pub(crate) fn entries(&self) -> &[VFatDirEntry] {
+        &self.data
+    }
+
+    /// The directory index of the first entry held.
+    pub(crate) fn start(&self) -> usize {
+        self.data_start
+    }
+
+    /// The number of entries read so far.
+    pub(crate) fn end(&self) -> usize {
+        self.data_start + self.data.len()
+    }
+
+    /// The cluster read last, which is the last cluster of the directory
+    /// once `read_next()` has returned `false`. `None` for the fixed root
+    /// directory.
+    pub(crate) fn last_cluster(&self) -> Option<Cluster> {
+        self.last_cluster
     }
 }
 
+/// An iterator over the entries of a directory that reads the directory one
+/// cluster at a time.
 pub struct EntryIterator {
     fs: Shared<VFat>,
+    dir_cluster: Cluster,
+    /// The directory index of the next entry to examine.
     curr_idx: usize,
-    data: Vec<VFatDirEntry>,
+    /// The entries of the directory cluster being examined.
+    raw: RawEntries,
+    /// The character set of short names.
+    code_page: CodePage,
+    /// The error that ended the iteration early, if any.
+    error: Option<io::Error>,
 }
 
-impl Iterator for EntryIterator {
-    type Item = Entry;
+impl EntryIterator {
+    /// Returns an iterator over the directory starting at `dir_cluster`
+    /// with its first cluster already read.
+    fn new(fs: Shared<VFat>, dir_cluster: Cluster) -> io::Result<EntryIterator> {
+        let code_page = fs.borrow().code_page();
+        let mut raw = RawEntries::new(dir_cluster);
+        raw.read_next(&mut fs.borrow_mut())?;
+        Ok(EntryIterator { fs, dir_cluster, curr_idx: 0, raw, code_page, error: None })
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut raw_long_file_name = [0u16; 260];
-        for _i in self.curr_idx..self.data.len() {
-            let entry = self.data.get(self.curr_idx).unwrap();
+    /// Returns the error that ended the iteration early, such as a failed
+    /// read or an invalid cluster chain, if any. The iterator returns `None`
+    /// after such an error, so callers that need every entry must check it.
+    pub fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EntryIterator {
+    /// Returns the next entry along with its short name, which is also its
+    /// name if it has no long file name.
+    fn next_with_short_name(&mut self) -> Option<(Entry, String)> {
+        // Entries that are out of sequence or belong to another short name
+        // are orphans and ignored, like their set.
+        let mut lfn = LfnParser::default();
+        loop {
+            if self.curr_idx >= self.raw.end() {
+                let result = self.raw.read_next(&mut self.fs.borrow_mut());
+                match result {
+                    Ok(true) => continue,
+                    Ok(false) => return None,
+                    Err(e) => {
+                        self.error = Some(e);
+                        return None;
+                    }
+                }
+            }
+            let entry = self.raw.entries()[self.curr_idx - self.raw.start()];
 
             let unknown = unsafe { entry.unknown };
 
             if unknown.is_deleted_or_unused() {
                 self.curr_idx += 1;
+                lfn.abandon();
                 continue;
             } else if unknown.prev_is_last_entry() {
                 return None;
@@ -146,59 +714,30 @@ impl Iterator for EntryIterator {
 
             self.curr_idx += 1;
             if unknown.is_lnf() {
-                let lnf = unsafe { entry.long_filename };
-
-                let lnf_idx = (lnf.sequence & 0b11111) as usize - 1;
-                let slot = lnf_idx * 13;
-
-                raw_long_file_name[slot..slot + 5].copy_from_slice(&lnf.name);
-                raw_long_file_name[slot + 5..slot + 11].copy_from_slice(&lnf.name_2);
-                raw_long_file_name[slot + 11..slot + 13].copy_from_slice(&lnf.name_3);
+                lfn.push(self.curr_idx - 1, unsafe { entry.long_filename });
             } else {
                 let dir = unsafe { entry.regular };
-
-                let file_name = if raw_long_file_name[0] == 0x00 {
-                    let name = core::str::from_utf8(&dir.file_name).unwrap().trim_end();
-                    let extension = core::str::from_utf8(&dir.file_ext).unwrap().trim_end();
-                    let mut short_file_name = String::from(name);
-                    if !extension.is_empty() {
-                        short_file_name.push('.');
-                        short_file_name.push_str(extension);
-                    }
-                    short_file_name
-                } else {
-                    let mut len = raw_long_file_name.len();
-                    for i in 0..len {
-                        if raw_long_file_name[i] == 0x00 || raw_long_file_name[i] == 0xFF {
-                            len = i;
-                            break;
-                        }
-                    }
-
-                    let long_file_name = decode_utf16(raw_long_file_name[0..len].iter().cloned())
-                        .map(|r| r.unwrap())
-                        .collect::<String>();
-                    long_file_name
+                let short_name = name::decode_short_name(&dir.short_name(), self.code_page);
+                let (file_name, first) = match lfn.finish(&dir.short_name()) {
+                    LongName::Valid(long_name, first) => (long_name, first),
+                    _ => (short_name.clone(), self.curr_idx - 1),
+                };
+                let position = EntryPosition {
+                    dir_cluster: self.dir_cluster,
+                    first,
+                    index: self.curr_idx - 1,
                 };
 
-                if unknown.is_regular_directory() {
-                    return Some(Entry::Dir(Dir {
-                        cluster: Cluster::from(dir.metadata.start_cluster()),
-                        fs: self.fs.clone(),
-                        metadata: dir.metadata,
-                        name: file_name,
-                    }));
-                } else {
-                    return Some(Entry::File(File::new(
-                        file_name,
-                        dir.metadata,
-                        Cluster::from(dir.metadata.start_cluster()),
-                        self.fs.clone(),
-                        dir.size,
-                    )));
-                }
+                return Some((Entry::from_regular(&self.fs, file_name, dir, position), short_name));
             }
         }
-        None
+    }
+}
+
+impl Iterator for EntryIterator {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_short_name().map(|(entry, _)| entry)
     }
 }
\ No newline at end of file
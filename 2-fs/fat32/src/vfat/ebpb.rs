@@ -1,6 +1,7 @@
 use std::{fmt, io};
 use traits::BlockDevice;
-use vfat::Error;
+use vfat::{Error, FatType};
+use vfat::name;
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
@@ -65,11 +66,162 @@ impl BiosParameterBlock {
 
         Ok(block)
     }
+
+    /// Returns `true` if the block describes a plausible FAT volume: a
+    /// power-of-two sector size of 512 to 4096 bytes, a power-of-two number
+    /// of sectors per cluster, at least one reserved sector and FAT, a
+    /// non-zero FAT size and root directory entries exactly when the volume
+    /// is not FAT32.
+    pub fn is_fat(&self) -> bool {
+        let bytes_per_sector = self.bytes_per_sector;
+        (bytes_per_sector >= 512 && bytes_per_sector <= 4096 && bytes_per_sector.is_power_of_two())
+            && self.sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors != 0
+            && self.number_of_fat != 0
+            && self.sectors_per_fat() != 0
+            && (self.max_directory_entries == 0) == (self.fat_type() == FatType::Fat32)
+    }
+
+    /// Returns `true` if the block describes a plausible FAT32 volume.
+    pub fn is_fat32(&self) -> bool {
+        self.is_fat() && self.fat_type() == FatType::Fat32
+    }
+
+    /// Returns the FAT type of the volume.
+    ///
+    /// Only FAT32 boot sectors leave the 16-bit FAT size field zero; volumes
+    /// doing so are FAT32 regardless of their cluster count, as small FAT32
+    /// images are common. Otherwise the type is determined from the number of
+    /// data clusters, as the specification requires.
+    pub fn fat_type(&self) -> FatType {
+        if self.sectors_per_fat == 0 {
+            return FatType::Fat32;
+        }
+
+        let clusters = match self.sectors_per_cluster {
+            0 => 0,
+            per_cluster => self.data_sectors() / per_cluster as u32,
+        };
+        if clusters < 4085 {
+            FatType::Fat12
+        } else {
+            FatType::Fat16
+        }
+    }
+
+    /// Returns the number of sectors occupied by each FAT.
+    pub fn sectors_per_fat(&self) -> u32 {
+        match self.sectors_per_fat {
+            0 => self.sector_per_fat_32,
+            sectors => sectors as u32,
+        }
+    }
+
+    /// Returns the number of entries in the fixed-size root directory of a
+    /// FAT12 or FAT16 volume. Always 0 for FAT32 volumes.
+    pub fn root_dir_entries(&self) -> u16 {
+        self.max_directory_entries
+    }
+
+    /// Returns the number of sectors occupied by the fixed-size root
+    /// directory of a FAT12 or FAT16 volume.
+    pub fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector as u32;
+        match bytes_per_sector {
+            0 => 0,
+            _ => (self.max_directory_entries as u32 * 32 + bytes_per_sector - 1) / bytes_per_sector,
+        }
+    }
+
+    /// Returns the number of sectors in the data region.
+    fn data_sectors(&self) -> u32 {
+        let metadata_sectors = (self.number_of_fat as u32)
+            .saturating_mul(self.sectors_per_fat())
+            .saturating_add(self.reserved_sectors as u32 + self.root_dir_sectors());
+        self.total_sectors().saturating_sub(metadata_sectors)
+    }
+
+    /// Returns the index of the only FAT in use if FAT mirroring is disabled,
+    /// or `None` if every FAT copy is kept up to date. Only FAT32 volumes can
+    /// disable mirroring; an out-of-range active FAT index is ignored.
+    pub fn active_fat(&self) -> Option<u8> {
+        let flags = self.flags;
+        let active = (flags & 0x0F) as u8;
+        match flags & 0x80 {
+            0x80 if self.fat_type() == FatType::Fat32 && active < self.number_of_fat => Some(active),
+            _ => None,
+        }
+    }
+
+    /// Returns the sector of the FSInfo structure relative to the start of
+    /// the volume, or `None` if the volume has none.
+    pub fn fs_info_sector(&self) -> Option<u16> {
+        match { self.fs_info_sector_number } {
+            0 | 0xFFFF => None,
+            sector if sector < self.reserved_sectors && self.fat_type() == FatType::Fat32 => Some(sector),
+            _ => None,
+        }
+    }
+
+    /// Returns the extended boot signature and the fields following it, the
+    /// volume ID and label, if the boot sector has them. They are at
+    /// different offsets on FAT32 and FAT12/16 volumes.
+    fn extended_fields(&self) -> Option<&[u8]> {
+        let bytes: &[u8; 512] = unsafe { &*(self as *const BiosParameterBlock as *const [u8; 512]) };
+        let offset = if self.fat_type() == FatType::Fat32 { 66 } else { 38 };
+        match bytes[offset] {
+            0x28 | 0x29 => Some(&bytes[offset..offset + 16]),
+            _ => None,
+        }
+    }
+
+    /// Returns the volume serial number, if the boot sector records one.
+    pub fn volume_id(&self) -> Option<u32> {
+        self.extended_fields().map(|fields| {
+            fields[1] as u32 | (fields[2] as u32) << 8 | (fields[3] as u32) << 16 | (fields[4] as u32) << 24
+        })
+    }
+
+    /// Returns the volume label recorded in the boot sector, if any.
+    pub fn volume_label(&self) -> Option<String> {
+        match self.extended_fields() {
+            Some(fields) if fields[0] == 0x29 => name::parse_label(&fields[5..16]),
+            _ => None,
+        }
+    }
+
+    /// Returns the total number of logical sectors in the volume.
+    pub fn total_sectors(&self) -> u32 {
+        match self.total_logical_sectors {
+            0 => self.total_logical_sectors_2,
+            sectors => sectors as u32,
+        }
+    }
 }
 
 impl fmt::Debug for BiosParameterBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("BiosParameterBlock")
+            .field("oem_identifier", &String::from_utf8_lossy(&{ self.oem_identifier }))
+            .field("bytes_per_sector", &{ self.bytes_per_sector })
+            .field("sectors_per_cluster", &{ self.sectors_per_cluster })
+            .field("reserved_sectors", &{ self.reserved_sectors })
+            .field("number_of_fat", &{ self.number_of_fat })
+            .field("max_directory_entries", &{ self.max_directory_entries })
+            .field("total_sectors", &self.total_sectors())
+            .field("descriptor_type", &{ self.descriptor_type })
+            .field("sectors_per_fat", &self.sectors_per_fat())
+            .field("hidden_sectors", &{ self.hidden_sectors })
+            .field("flags", &{ self.flags })
+            .field("version", &{ self.version })
+            .field("root_dir_cluster_number", &{ self.root_dir_cluster_number })
+            .field("fs_info_sector_number", &{ self.fs_info_sector_number })
+            .field("backup_boot_sector_number", &{ self.backup_boot_sector_number })
+            .field("drive_number", &{ self.drive_number })
+            .field("signature", &{ self.signature })
+            .field("volume_id", &{ self._volume_id })
+            .field("volume_label", &String::from_utf8_lossy(&{ self.volume_label }))
+            .field("identifier_string", &String::from_utf8_lossy(&{ self.identifier_string }))
             .finish()
     }
 }
@@ -1,5 +1,6 @@
 use traits;
-use vfat::{Dir, File, Metadata};
+use vfat::{Cluster, Dir, File, Metadata, Shared, VFat};
+use vfat::dir::{EntryPosition, VFatRegularDirEntry};
 
 // TODO: You may need to change this definition.
 #[derive(Debug)]
@@ -9,6 +10,38 @@ pub enum Entry {
 }
 
 // TODO: Implement any useful helper methods on `Entry`.
+impl Entry {
+    /// Returns the file or directory named `name` described by the regular
+    /// directory entry `regular` at `position`.
+    pub(crate) fn from_regular(
+        fs: &Shared<VFat>,
+        name: String,
+        regular: VFatRegularDirEntry,
+        position: EntryPosition,
+    ) -> Entry {
+        let metadata = regular.metadata;
+        if metadata.is_dir() {
+            // A `..` entry pointing to the root directory stores cluster 0.
+            let cluster = match metadata.start_cluster() {
+                0 => fs.borrow().root_dir_cluster,
+                cluster => Cluster::from(cluster),
+            };
+            Entry::Dir(Dir::new(cluster, fs.clone(), name, metadata, Some(position)))
+        } else {
+            let cluster = Cluster::from(metadata.start_cluster());
+            Entry::File(File::new(name, metadata, cluster, fs.clone(), regular.size(), Some(position)))
+        }
+    }
+
+    /// The location of the entry in its parent directory, or `None` for the
+    /// root directory.
+    pub(crate) fn position(&self) -> Option<EntryPosition> {
+        match self {
+            Entry::File(file) => file.position(),
+            Entry::Dir(dir) => dir.position(),
+        }
+    }
+}
 
 // FIXME: Implement `traits::Entry` for `Entry`.
 impl traits::Entry for Entry {
@@ -1,13 +1,28 @@
 use std::io;
 
+use gpt;
 use mbr;
 
 #[derive(Debug)]
 pub enum Error {
+    /// The MBR could not be read or is invalid.
     Mbr(mbr::Error),
+    /// The GUID partition table could not be read or is invalid.
+    Gpt(gpt::Error),
+    /// There was an I/O error while reading the volume.
     Io(io::Error),
+    /// The EBPB magic signature was invalid.
     BadSignature,
-    NotFound
+    /// The BIOS parameter block describes an impossible volume; `.0` says
+    /// why.
+    BadBpb(&'static str),
+    /// No FAT volume was found on the device.
+    NotFound,
+    /// Partition `.0` (an MBR slot or a GPT entry index) does not exist or is
+    /// empty.
+    NoPartition(usize),
+    /// The partition has type `.0`, which is not a FAT partition type.
+    UnsupportedPartitionType(u8),
 }
 
 impl From<mbr::Error> for Error {
@@ -16,6 +31,12 @@ impl From<mbr::Error> for Error {
     }
 }
 
+impl From<gpt::Error> for Error {
+    fn from(error: gpt::Error) -> Error {
+        Error::Gpt(error)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
         Error::Io(error)
@@ -19,10 +19,46 @@ pub enum Status {
     Eoc(u32)
 }
 
+/// The width of the entries of a file allocation table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// A divergence of a FAT copy from the FAT in use, as reported by
+/// `VFat::verify_fat_copies()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FatCopyMismatch {
+    /// The index of the diverging copy.
+    pub copy: u8,
+    /// The first cluster whose entry differs.
+    pub first_cluster: u32,
+    /// The number of entries that differ.
+    pub entries: u32,
+}
+
+/// An entry of the file allocation table. Entries of FAT12 and FAT16 volumes
+/// are widened so that their special values match those of FAT32.
 #[repr(C, packed)]
 pub struct FatEntry(pub u32);
 
 impl FatEntry {
+    /// Returns the entry for the raw `fat_type` FAT value `raw`.
+    pub fn from_raw(raw: u32, fat_type: FatType) -> FatEntry {
+        match fat_type {
+            FatType::Fat12 if raw >= 0xFF0 => FatEntry(raw | 0x0FFFF000),
+            FatType::Fat16 if raw >= 0xFFF0 => FatEntry(raw | 0x0FFF0000),
+            _ => FatEntry(raw),
+        }
+    }
+
+    /// Value marking a cluster as unused.
+    pub const FREE: u32 = 0x00000000;
+    /// Value marking a cluster as the last one in its chain.
+    pub const EOC: u32 = 0x0FFFFFFF;
+
     /// Returns the `Status` of the FAT entry `self`.
     pub fn status(&self) -> Status {
         match self.0 & 0x0FFFFFFF {
@@ -36,10 +72,11 @@ impl FatEntry {
         }
     }
 
+    /// Returns the next cluster in the chain, or `None` if this entry does
+    /// not link to another cluster.
     pub fn next_cluster(&self) -> Option<Cluster> {
         match self.status() {
             Data(cluster) => Some(cluster),
-            Eoc(cluster) => Some(Cluster::from(cluster)),
             _ => None,
         }
     }
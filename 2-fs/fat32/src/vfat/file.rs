@@ -2,7 +2,8 @@ use std::cmp::min;
 use std::io::{self, SeekFrom};
 
 use traits;
-use vfat::{Cluster, FatEntry, Metadata, Shared, VFat};
+use vfat::{Cluster, Metadata, Shared, Status, VFat};
+use vfat::dir::EntryPosition;
 
 #[derive(Debug)]
 pub struct File {
@@ -11,12 +12,29 @@ pub struct File {
     start_cluster: Cluster,
     fs: Shared<VFat>,
     size: u32,
-    offset: u32,
-    curr_cluster: Option<Cluster>,
+    offset: u64,
+    /// The clusters of the file's chain known so far, in order: `clusters[i]`
+    /// holds bytes `i * bytes_per_cluster..(i + 1) * bytes_per_cluster`.
+    clusters: Vec<Cluster>,
+    /// The offset the last read ended at, if it was the last access.
+    last_read_end: Option<u64>,
+    /// The index of the cluster following the clusters read ahead last.
+    read_ahead_end: usize,
+    position: Option<EntryPosition>,
+    dirty: bool,
 }
 
 impl File {
-    pub fn new(name: String, metadata: Metadata, start_cluster: Cluster, fs: Shared<VFat>, size: u32) -> File {
+    pub fn new(
+        name: String,
+        metadata: Metadata,
+        start_cluster: Cluster,
+        fs: Shared<VFat>,
+        size: u32,
+        position: Option<EntryPosition>,
+    ) -> File {
+        let mut metadata = metadata;
+        metadata.set_len(size);
         File {
             name,
             metadata,
@@ -24,15 +42,147 @@ impl File {
             fs,
             size,
             offset: 0,
-            curr_cluster: Some(start_cluster),
+            clusters: if start_cluster.is_valid() { vec![start_cluster] } else { Vec::new() },
+            last_read_end: Some(0),
+            read_ahead_end: 0,
+            position,
+            dirty: false,
         }
     }
+
+    /// The first cluster of the file's data. Invalid if the file is empty.
+    pub(crate) fn start_cluster(&self) -> Cluster {
+        self.start_cluster
+    }
+
+    /// The location of the file's entry in its parent directory.
+    pub(crate) fn position(&self) -> Option<EntryPosition> {
+        self.position
+    }
+
+    /// Returns the `index`th cluster of the file's chain, following the chain
+    /// from the last cluster known, or `None` if the chain ends before.
+    fn cluster_at(&mut self, fs: &mut VFat, index: usize) -> io::Result<Option<Cluster>> {
+        if self.clusters.is_empty() {
+            return Ok(None);
+        }
+
+        while self.clusters.len() <= index {
+            let last = self.clusters[self.clusters.len() - 1];
+            let next = match fs.fat_entry(last)?.status() {
+                Status::Data(next) => next,
+                Status::Eoc(_) => return Ok(None),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster chain")),
+            };
+            self.clusters.push(next);
+        }
+        Ok(Some(self.clusters[index]))
+    }
+
+    /// Returns the `index`th cluster of the file's chain, allocating clusters
+    /// at the end of the chain until it is long enough.
+    fn alloc_cluster_at(&mut self, fs: &mut VFat, index: usize) -> io::Result<Cluster> {
+        if let Some(cluster) = self.cluster_at(fs, index)? {
+            return Ok(cluster);
+        }
+
+        if self.clusters.is_empty() {
+            self.start_cluster = fs.alloc_cluster(None)?;
+            self.metadata.set_start_cluster(self.start_cluster.id());
+            self.clusters.push(self.start_cluster);
+        }
+        while self.clusters.len() <= index {
+            let last = self.clusters[self.clusters.len() - 1];
+            let next = fs.alloc_cluster(Some(last))?;
+            self.clusters.push(next);
+        }
+        Ok(self.clusters[index])
+    }
+
+    /// Returns the `index`th cluster of the file's chain and the length of
+    /// the run of contiguous clusters starting there, limited to `max`
+    /// clusters, or `None` if the chain ends before.
+    fn run_at(&mut self, fs: &mut VFat, index: usize, max: usize) -> io::Result<Option<(Cluster, usize)>> {
+        let first = match self.cluster_at(fs, index)? {
+            Some(cluster) => cluster,
+            None => return Ok(None),
+        };
+        let mut len = 1;
+        while len < max {
+            match self.cluster_at(fs, index + len)? {
+                Some(next) if next.id() == first.id() + len as u32 => len += 1,
+                _ => break,
+            }
+        }
+        Ok(Some((first, len)))
+    }
+
+    /// Reads clusters `index` to `last` and the clusters following them into
+    /// the sector cache once a sequential read reaches the clusters read
+    /// ahead before. Errors are ignored: they surface when the data is read.
+    fn read_ahead(&mut self, fs: &mut VFat, index: usize, last: usize) {
+        let count = fs.read_ahead() as usize;
+        if count == 0 || last < self.read_ahead_end {
+            return;
+        }
+
+        let bytes_per_cluster = fs.bytes_per_cluster();
+        let clusters = (self.size as usize + bytes_per_cluster - 1) / bytes_per_cluster;
+        let end = min(last + count, clusters);
+        self.read_ahead_end = end;
+        let mut next = index;
+        while next < end {
+            match self.run_at(fs, next, end - next) {
+                Ok(Some((cluster, run))) => {
+                    let _ = fs.prefetch(cluster, run as u32);
+                    next += run;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Writes `buf` at the current offset, allocating clusters as needed.
+    fn write_bytes(&mut self, fs: &mut VFat, buf: &[u8]) -> io::Result<usize> {
+        self.last_read_end = None;
+        let bytes_per_cluster = fs.bytes_per_cluster() as u64;
+        let mut bytes_written = 0;
+        while bytes_written < buf.len() {
+            let index = (self.offset / bytes_per_cluster) as usize;
+            let cluster = self.alloc_cluster_at(fs, index)?;
+            let bytes = fs.write_cluster(
+                cluster,
+                (self.offset % bytes_per_cluster) as usize,
+                &buf[bytes_written..],
+            )?;
+            bytes_written += bytes;
+            self.offset += bytes as u64;
+            if self.offset > self.size as u64 {
+                self.size = self.offset as u32;
+                self.metadata.set_len(self.size);
+            }
+            self.dirty = true;
+        }
+        Ok(bytes_written)
+    }
 }
 
 // FIXME: Implement `traits::File` (and its supertraits) for `File`.
 impl traits::File for File {
+    /// Writes the file's size, start cluster and modification time to its
+    /// directory entry if the file was written to, then flushes all modified
+    /// sectors to the device.
     fn sync(&mut self) -> io::Result<()> {
-        unimplemented!("read-only system")
+        let mut fs = self.fs.borrow_mut();
+        if self.dirty {
+            let now = fs.now();
+            self.metadata.touch(now);
+            if let Some(position) = self.position {
+                fs.dir_entry_mut(position.dir_cluster, position.index)?.regular_mut().metadata = self.metadata;
+            }
+            self.dirty = false;
+        }
+        fs.flush()
     }
 
     fn size(&self) -> u64 {
@@ -40,47 +190,111 @@ impl traits::File for File {
     }
 }
 
+impl Drop for File {
+    /// Syncs the file if it was written to but not synced. Errors are
+    /// ignored; call `sync()` to observe them.
+    fn drop(&mut self) {
+        if self.dirty {
+            let _ = traits::File::sync(self);
+        }
+    }
+}
+
 impl io::Read for File {
+    /// Reads from the current offset into `buf`. Reading at or beyond the
+    /// end of the file returns `0`.
+    ///
+    /// Each run of contiguous clusters is read at once. Sequential reads
+    /// smaller than a cluster read clusters ahead into the sector cache, as
+    /// configured with `VFat::set_read_ahead()`.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let can_be_read = min(buf.len(), self.size as usize - self.offset as usize);
+        let remaining = (self.size as u64).saturating_sub(self.offset);
+        let can_be_read = min(buf.len() as u64, remaining) as usize;
+        let fs = self.fs.clone();
+        let mut fs = fs.borrow_mut();
+        let bytes_per_cluster = fs.bytes_per_cluster();
+        let sequential = self.last_read_end == Some(self.offset);
+        if !sequential {
+            self.read_ahead_end = 0;
+        }
+
         let mut bytes_read = 0;
-        let mut fs = self.fs.borrow_mut();
-        let bytes_per_cluster = fs.bytes_per_sector as u32 * fs.sectors_per_cluster as u32;
         while bytes_read < can_be_read {
-            let bytes = fs.read_cluster(
-                self.curr_cluster.unwrap(),
-                self.offset as usize % bytes_per_cluster as usize,
-                &mut buf[bytes_read..can_be_read],
-            )?;
-            bytes_read += bytes;
-            self.offset = self.offset + bytes as u32;
-
-            if self.offset % bytes_per_cluster == 0 {
-                let entry: &FatEntry = fs.fat_entry(self.curr_cluster.unwrap())?;
-                let next_cluster = entry.next_cluster();
-                self.curr_cluster = next_cluster;
+            let remaining = can_be_read - bytes_read;
+            let index = (self.offset / bytes_per_cluster as u64) as usize;
+            let cluster_offset = (self.offset % bytes_per_cluster as u64) as usize;
+            if sequential && remaining < bytes_per_cluster {
+                let last = ((self.offset + remaining as u64 - 1) / bytes_per_cluster as u64) as usize;
+                self.read_ahead(&mut fs, index, last);
             }
+
+            // A chain shorter than the file's size indicates a corrupted
+            // volume.
+            let clusters = (cluster_offset + remaining + bytes_per_cluster - 1) / bytes_per_cluster;
+            let (cluster, run) = self.run_at(&mut fs, index, clusters)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "cluster chain shorter than file")
+            })?;
+            let len = min(run * bytes_per_cluster - cluster_offset, remaining);
+            let bytes = fs.read_run(cluster, cluster_offset, &mut buf[bytes_read..bytes_read + len])?;
+            bytes_read += bytes;
+            self.offset += bytes as u64;
         }
 
+        self.last_read_end = Some(self.offset);
         Ok(bytes_read)
     }
 }
 
 impl io::Write for File {
-    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!()
+    /// Writes `buf` at the current offset, overwriting existing data and
+    /// extending the file (and its cluster chain) as needed. If the offset
+    /// is beyond the end of the file, the gap is filled with zeroes first.
+    ///
+    /// The directory entry is not updated until `sync()` or `flush()` is
+    /// called.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the write would grow the
+    /// file beyond the 4GiB FAT32 limit and of kind `Other` if the volume
+    /// runs out of free clusters.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.offset + buf.len() as u64 > ::std::u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large"));
+        }
+
+        let fs = self.fs.clone();
+        let mut fs = fs.borrow_mut();
+        if self.offset > self.size as u64 {
+            let end = self.offset;
+            let zeroes = vec![0; fs.bytes_per_cluster()];
+            self.offset = self.size as u64;
+            while self.offset < end {
+                let len = min(zeroes.len() as u64, end - self.offset) as usize;
+                if let Err(e) = self.write_bytes(&mut fs, &zeroes[..len]) {
+                    self.offset = end;
+                    return Err(e);
+                }
+            }
+        }
+        self.write_bytes(&mut fs, buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        unimplemented!()
+        traits::File::sync(self)
     }
 }
 
 impl io::Seek for File {
     /// Seek to offset `pos` in the file.
     ///
-    /// A seek to the end of the file is allowed. A seek _beyond_ the end of the
-    /// file returns an `InvalidInput` error.
+    /// Seeking to or beyond the end of the file is allowed: reads there
+    /// return no data and writes fill the gap with zeroes. Seeking only
+    /// records the new offset; the cluster holding it is found on the next
+    /// read or write, from the clusters of the chain visited before.
     ///
     /// If the seek operation completes successfully, this method returns the
     /// new position from the start of the stream. That position can be used
@@ -88,28 +302,20 @@ impl io::Seek for File {
     ///
     /// # Errors
     ///
-    /// Seeking before the start of a file or beyond the end of the file results
-    /// in an `InvalidInput` error.
+    /// Seeking before the start of the file or to an offset that overflows
+    /// results in an `InvalidInput` error.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let seek_offset = match pos {
-            SeekFrom::Current(offset) => self.offset + offset as u32,
-            SeekFrom::End(offset) => self.size + offset as u32,
-            SeekFrom::Start(offset) => offset as u32,
+            SeekFrom::Current(offset) => (self.offset as i64).checked_add(offset),
+            SeekFrom::End(offset) => (self.size as i64).checked_add(offset),
+            SeekFrom::Start(offset) if offset <= ::std::i64::MAX as u64 => Some(offset as i64),
+            SeekFrom::Start(_) => None,
         };
 
-        if seek_offset >= self.size {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, ""));
-        } else {
-            self.offset = seek_offset;
-            let mut fs = self.fs.borrow_mut();
-            let bytes_per_cluster = fs.bytes_per_sector as u32 * fs.sectors_per_cluster as u32;
-            let cluster = self.offset / bytes_per_cluster;
-            self.curr_cluster = Some(self.start_cluster);
-            for _i in 0..cluster {
-                self.curr_cluster = fs.fat_entry(self.curr_cluster.unwrap())?.next_cluster();
-            }
-            Ok(self.offset as u64)
-        }
-
+        self.offset = match seek_offset {
+            Some(offset) if offset >= 0 => offset as u64,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        };
+        Ok(self.offset)
     }
 }
@@ -0,0 +1,248 @@
+use std::io;
+use std::ptr;
+
+use traits::BlockDevice;
+use vfat::{Metadata, Timestamp};
+use vfat::dir::VFatRegularDirEntry;
+
+/// Number of reserved sectors preceding the FATs, including the boot sector.
+const RESERVED_SECTORS: u16 = 32;
+/// Number of copies of the FAT.
+const NUMBER_OF_FATS: u8 = 2;
+/// Sector of the FSInfo structure, relative to the start of the volume.
+const FS_INFO_SECTOR: u16 = 1;
+/// Sector of the backup boot sector; the backup FSInfo follows it.
+const BACKUP_BOOT_SECTOR: u16 = 6;
+/// The first cluster of the root directory.
+const ROOT_DIR_CLUSTER: u32 = 2;
+/// Media descriptor of fixed disks.
+const MEDIA_DESCRIPTOR: u8 = 0xF8;
+/// Largest valid number of data clusters of a FAT32 volume.
+const MAX_CLUSTERS: u64 = 0x0FFFFFF5 - 2;
+/// Volume label recorded when none is given.
+const NO_NAME: &[u8; 11] = b"NO NAME    ";
+
+/// Options controlling how `format` lays out a new FAT32 volume.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Size of the device in sectors of `BlockDevice::sector_size()` bytes.
+    pub total_sectors: u64,
+    /// Whether to write an MBR with a single FAT32 (LBA) partition spanning
+    /// the device. Otherwise the volume starts at sector 0 ("superfloppy").
+    pub partition_table: bool,
+    /// Bytes per cluster: a power of two of at least one sector and at most
+    /// 128 sectors. `None` picks a size based on the size of the volume.
+    pub cluster_size: Option<u32>,
+    /// Volume label of up to 11 characters, stored upper-cased in the boot
+    /// sector and as an entry of the root directory. `None` records the
+    /// label `NO NAME` in the boot sector only.
+    pub volume_label: Option<String>,
+    /// Volume serial number. `None` derives one from the current time.
+    pub volume_id: Option<u32>,
+}
+
+impl FormatOptions {
+    /// Returns the default options for a device of `total_sectors` sectors:
+    /// an MBR, automatic cluster size, no label and a time-based volume ID.
+    pub fn new(total_sectors: u64) -> FormatOptions {
+        FormatOptions {
+            total_sectors,
+            partition_table: true,
+            cluster_size: None,
+            volume_label: None,
+            volume_id: None,
+        }
+    }
+}
+
+/// Returns the cluster size Microsoft's tools use for a volume of `bytes`
+/// bytes.
+fn default_cluster_size(bytes: u64) -> u32 {
+    const MIB: u64 = 1 << 20;
+    match bytes {
+        b if b <= 260 * MIB => 512,
+        b if b <= 8 * 1024 * MIB => 4096,
+        b if b <= 16 * 1024 * MIB => 8192,
+        b if b <= 32 * 1024 * MIB => 16384,
+        _ => 32768,
+    }
+}
+
+/// Returns the raw, space-padded upper-case form of the volume label
+/// `label`.
+fn raw_label(label: &str) -> io::Result<[u8; 11]> {
+    let valid = !label.is_empty() && label.len() <= 11 && !label.starts_with(' ')
+        && label.bytes().all(|c| {
+            c.is_ascii_alphanumeric() || b" !#$%&'()-@^_`{}~".contains(&c)
+        });
+    if !valid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid volume label"));
+    }
+
+    let mut raw = [b' '; 11];
+    for (byte, c) in raw.iter_mut().zip(label.bytes()) {
+        *byte = c.to_ascii_uppercase();
+    }
+    Ok(raw)
+}
+
+/// Copies `bytes` into `data` at byte `offset`.
+pub(crate) fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Formats `device` as a FAT32 volume as described by `options`.
+///
+/// Writes, in order: the MBR if `options.partition_table` is set, the boot
+/// sector, FSInfo sector and their backups, both FATs and an empty root
+/// directory. Sectors outside these regions are left untouched, so the data
+/// region of the device is not cleared.
+///
+/// Volumes with fewer than 65525 clusters are formatted as FAT32 anyway;
+/// this crate and most tools accept them, though the specification would
+/// call for FAT16.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if the device's sector size is not
+/// a power of two between 512 and 4096, if the cluster size or volume label
+/// is invalid, or if the device is too small to hold a volume. I/O errors of
+/// `device` are returned as is.
+pub fn format<T: BlockDevice>(mut device: T, options: &FormatOptions) -> io::Result<()> {
+    let invalid = |msg: &'static str| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+    let sector_size = device.sector_size();
+    if sector_size < 512 || sector_size > 4096 || !sector_size.is_power_of_two() {
+        return Err(invalid("unsupported sector size"));
+    }
+
+    let partition_start = match options.partition_table {
+        // Align the partition to 1 MiB unless that would waste much of the
+        // device.
+        true if options.total_sectors * sector_size >= 8 << 20 => (1 << 20) / sector_size,
+        true => 1,
+        false => 0,
+    };
+    let volume_sectors = options.total_sectors.saturating_sub(partition_start);
+    if options.partition_table && options.total_sectors > ::std::u32::MAX as u64 {
+        return Err(invalid("device too large for an MBR"));
+    }
+    if volume_sectors > ::std::u32::MAX as u64 {
+        return Err(invalid("device too large for FAT32"));
+    }
+
+    let cluster_size = match options.cluster_size {
+        Some(size) => size as u64,
+        None => ::std::cmp::max(default_cluster_size(volume_sectors * sector_size) as u64, sector_size),
+    };
+    let sectors_per_cluster = cluster_size / sector_size;
+    if !cluster_size.is_power_of_two() || cluster_size % sector_size != 0 || sectors_per_cluster > 128 {
+        return Err(invalid("invalid cluster size"));
+    }
+
+    // Each cluster costs `sectors_per_cluster` data sectors plus one FAT entry
+    // in every FAT; the first two FAT entries are reserved.
+    let entries_per_sector = sector_size / 4;
+    let available = volume_sectors.saturating_sub(RESERVED_SECTORS as u64);
+    let sectors_per_fat = (available + 2 * sectors_per_cluster + sectors_per_cluster * entries_per_sector
+        + NUMBER_OF_FATS as u64 - 1)
+        / (sectors_per_cluster * entries_per_sector + NUMBER_OF_FATS as u64);
+    let data_start = RESERVED_SECTORS as u64 + NUMBER_OF_FATS as u64 * sectors_per_fat;
+    let clusters = volume_sectors.saturating_sub(data_start) / sectors_per_cluster;
+    if clusters < 1 {
+        return Err(invalid("device too small"));
+    }
+    if clusters > MAX_CLUSTERS {
+        return Err(invalid("too many clusters; use a larger cluster size"));
+    }
+
+    let now = Timestamp::now();
+    let volume_id = options.volume_id.unwrap_or_else(|| now.packed());
+    let label = match options.volume_label {
+        Some(ref label) => Some(raw_label(label)?),
+        None => None,
+    };
+
+    if options.partition_table {
+        let mut sector = vec![0u8; sector_size as usize];
+        put(&mut sector, 440, &volume_id.to_le_bytes());
+        // A single LBA-addressed FAT32 partition; its CHS fields hold the
+        // customary "beyond CHS range" values.
+        put(&mut sector, 446, &[0x00, 0xFE, 0xFF, 0xFF, 0x0C, 0xFE, 0xFF, 0xFF]);
+        put(&mut sector, 446 + 8, &(partition_start as u32).to_le_bytes());
+        put(&mut sector, 446 + 12, &(volume_sectors as u32).to_le_bytes());
+        put(&mut sector, 510, &[0x55, 0xAA]);
+        device.write_sector(0, &sector)?;
+    }
+
+    // Boot sector: BIOS parameter block and FAT32 extended fields.
+    let mut boot = vec![0u8; sector_size as usize];
+    put(&mut boot, 0, &[0xEB, 0x58, 0x90]);
+    put(&mut boot, 3, b"MSWIN4.1");
+    put(&mut boot, 11, &(sector_size as u16).to_le_bytes());
+    boot[13] = sectors_per_cluster as u8;
+    put(&mut boot, 14, &RESERVED_SECTORS.to_le_bytes());
+    boot[16] = NUMBER_OF_FATS;
+    boot[21] = MEDIA_DESCRIPTOR;
+    put(&mut boot, 24, &63u16.to_le_bytes());
+    put(&mut boot, 26, &255u16.to_le_bytes());
+    put(&mut boot, 28, &(partition_start as u32).to_le_bytes());
+    put(&mut boot, 32, &(volume_sectors as u32).to_le_bytes());
+    put(&mut boot, 36, &(sectors_per_fat as u32).to_le_bytes());
+    put(&mut boot, 44, &ROOT_DIR_CLUSTER.to_le_bytes());
+    put(&mut boot, 48, &FS_INFO_SECTOR.to_le_bytes());
+    put(&mut boot, 50, &BACKUP_BOOT_SECTOR.to_le_bytes());
+    boot[64] = 0x80;
+    boot[66] = 0x29;
+    put(&mut boot, 67, &volume_id.to_le_bytes());
+    put(&mut boot, 71, label.as_ref().unwrap_or(NO_NAME));
+    put(&mut boot, 82, b"FAT32   ");
+    put(&mut boot, 510, &[0x55, 0xAA]);
+
+    // FSInfo: all clusters but the root directory's are free.
+    let mut fs_info = vec![0u8; sector_size as usize];
+    put(&mut fs_info, 0, &0x41615252u32.to_le_bytes());
+    put(&mut fs_info, 484, &0x61417272u32.to_le_bytes());
+    put(&mut fs_info, 488, &(clusters as u32 - 1).to_le_bytes());
+    put(&mut fs_info, 492, &(ROOT_DIR_CLUSTER + 1).to_le_bytes());
+    put(&mut fs_info, 508, &0xAA550000u32.to_le_bytes());
+
+    let zero = vec![0u8; sector_size as usize];
+    for i in 0..RESERVED_SECTORS as u64 {
+        let data = match i {
+            0 => &boot,
+            i if i == FS_INFO_SECTOR as u64 => &fs_info,
+            i if i == BACKUP_BOOT_SECTOR as u64 => &boot,
+            i if i == BACKUP_BOOT_SECTOR as u64 + 1 => &fs_info,
+            _ => &zero,
+        };
+        device.write_sector(partition_start + i, data)?;
+    }
+
+    // FATs: the media descriptor, the clean-shutdown entry and the root
+    // directory's single-cluster chain.
+    for fat in 0..NUMBER_OF_FATS as u64 {
+        let fat_start = partition_start + RESERVED_SECTORS as u64 + fat * sectors_per_fat;
+        let mut first = zero.clone();
+        put(&mut first, 0, &(0x0FFFFF00 | MEDIA_DESCRIPTOR as u32).to_le_bytes());
+        put(&mut first, 4, &0x0FFFFFFFu32.to_le_bytes());
+        put(&mut first, 8, &0x0FFFFFFFu32.to_le_bytes());
+        device.write_sector(fat_start, &first)?;
+        for i in 1..sectors_per_fat {
+            device.write_sector(fat_start + i, &zero)?;
+        }
+    }
+
+    // Root directory: empty but for the volume label entry, if any.
+    let mut root = zero.clone();
+    if let Some(label) = label {
+        let entry = VFatRegularDirEntry::new(label, Metadata::volume_label(now), 0);
+        unsafe { ptr::write_unaligned(root.as_mut_ptr() as *mut VFatRegularDirEntry, entry) };
+    }
+    let root_start = partition_start + data_start;
+    for i in 0..sectors_per_cluster {
+        device.write_sector(root_start + i, if i == 0 { &root } else { &zero })?;
+    }
+
+    Ok(())
+}
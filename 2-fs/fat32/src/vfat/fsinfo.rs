@@ -0,0 +1,68 @@
+use std::ptr;
+
+/// The FSInfo sector of a FAT32 volume, which caches the number of free
+/// clusters and a hint of where to look for the next free cluster.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct FsInfo {
+    lead_signature: u32,
+    __reserved: [u8; 480],
+    struct_signature: u32,
+    free_count: u32,
+    next_free: u32,
+    __reserved_2: [u8; 12],
+    trail_signature: u32,
+}
+
+const LEAD_SIGNATURE: u32 = 0x41615252;
+const STRUCT_SIGNATURE: u32 = 0x61417272;
+const TRAIL_SIGNATURE: u32 = 0xAA550000;
+/// Value of `free_count` and `next_free` when they are unknown.
+const UNKNOWN: u32 = 0xFFFFFFFF;
+
+impl FsInfo {
+    /// Parses the FSInfo structure at the start of `sector`. Returns `None`
+    /// if `sector` is too short or any of its three signatures is invalid.
+    pub fn from(sector: &[u8]) -> Option<FsInfo> {
+        if sector.len() < 512 {
+            return None;
+        }
+
+        let info: FsInfo = unsafe { ptr::read_unaligned(sector.as_ptr() as *const FsInfo) };
+        let valid = info.lead_signature == LEAD_SIGNATURE
+            && info.struct_signature == STRUCT_SIGNATURE
+            && info.trail_signature == TRAIL_SIGNATURE;
+        if valid { Some(info) } else { None }
+    }
+
+    /// The last known number of free clusters, if it is known and at most
+    /// `clusters`, the number of clusters of the volume.
+    pub fn free_count(&self, clusters: u32) -> Option<u32> {
+        match { self.free_count } {
+            UNKNOWN => None,
+            count if count <= clusters => Some(count),
+            _ => None,
+        }
+    }
+
+    /// The cluster to start searching for free clusters at, if it is known
+    /// and a valid cluster of a volume with `clusters` clusters.
+    pub fn next_free(&self, clusters: u32) -> Option<u32> {
+        match { self.next_free } {
+            next if next >= 2 && next < clusters + 2 => Some(next),
+            _ => None,
+        }
+    }
+
+    /// Stores `free_count` and `next_free`, either of which may be `None` if
+    /// unknown, in the FSInfo structure at the start of `sector`.
+    pub fn write(sector: &mut [u8], free_count: Option<u32>, next_free: Option<u32>) {
+        let mut info = match FsInfo::from(sector) {
+            Some(info) => info,
+            None => return,
+        };
+        info.free_count = free_count.unwrap_or(UNKNOWN);
+        info.next_free = next_free.unwrap_or(UNKNOWN);
+        unsafe { ptr::write_unaligned(sector.as_mut_ptr() as *mut FsInfo, info) };
+    }
+}
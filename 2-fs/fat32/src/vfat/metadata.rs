@@ -21,7 +21,9 @@ pub struct Attributes(u8);
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Timestamp {
     pub date: Date,
-    pub time: Time
+    pub time: Time,
+    /// Units of 10 ms, in range [0, 199], to add to the two-second `time`.
+    pub centiseconds: u8,
 }
 
 /// Metadata for a directory entry.
@@ -38,12 +40,348 @@ pub struct Metadata {
     last_modification_time: Time,
     last_modification_date: Date,
     low_cluster_number: u16,
+    /// The size of the file in bytes; 0 for directories. Stored last in the
+    /// directory entry, directly after the fields above.
+    size: u32,
+}
+
+const READ_ONLY: u8 = 0x01;
+const HIDDEN: u8 = 0x02;
+const SYSTEM: u8 = 0x04;
+const VOLUME_ID: u8 = 0x08;
+const DIRECTORY: u8 = 0x10;
+const ARCHIVE: u8 = 0x20;
+
+impl Date {
+    /// Packs a calendar date into its on-disk representation. `year` must be
+    /// in range [1980, 2107].
+    pub(crate) fn new(year: usize, month: u8, day: u8) -> Date {
+        Date((((year - 1980) as u16) << 9) | ((month as u16) << 5) | day as u16)
+    }
+}
+
+impl Time {
+    /// Packs a time of day into its on-disk representation. Seconds are
+    /// stored with a resolution of two seconds.
+    pub(crate) fn new(hour: u8, minute: u8, second: u8) -> Time {
+        Time(((hour as u16) << 11) | ((minute as u16) << 5) | (second / 2) as u16)
+    }
+}
+
+/// Returns the number of days in `month` of `year`.
+fn days_in_month(year: usize, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Timestamp {
+    /// The earliest time FAT can represent, 1980-01-01 00:00:00.
+    pub const EPOCH: Timestamp = Timestamp {
+        date: Date((1 << 5) | 1),
+        time: Time(0),
+        centiseconds: 0,
+    };
+
+    /// Returns the timestamp for the given calendar date and time of day, or
+    /// `None` if the fields do not describe a valid time between 1980-01-01
+    /// and 2107-12-31.
+    ///
+    /// FAT stores times with a resolution of 10 milliseconds at best, so
+    /// `millisecond` is rounded down to a multiple of 10. Only creation times
+    /// keep this precision on disk; other timestamps are rounded down to two
+    /// seconds when written.
+    pub fn new(
+        year: usize,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        millisecond: u16,
+    ) -> Option<Timestamp> {
+        if year < 1980 || year > 2107 || month < 1 || month > 12 {
+            return None;
+        }
+        if day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        if hour > 23 || minute > 59 || second > 59 || millisecond > 999 {
+            return None;
+        }
+
+        Some(Timestamp {
+            date: Date::new(year, month, day),
+            time: Time::new(hour, minute, second),
+            centiseconds: (second % 2) * 100 + (millisecond / 10) as u8,
+        })
+    }
+
+    /// Decodes a timestamp from the DOS date and time fields of a directory
+    /// entry and, for creation times, the count of 10 ms units, in range
+    /// [0, 199], to add to the two-second `time`.
+    pub fn from_dos(date: u16, time: u16, centiseconds: u8) -> Timestamp {
+        Timestamp {
+            date: Date(date),
+            time: Time(time),
+            centiseconds: ::std::cmp::min(centiseconds, 199),
+        }
+    }
+
+    /// Encodes the timestamp as the DOS date, time and 10 ms fields of a
+    /// directory entry; the inverse of `from_dos()`.
+    pub fn to_dos(&self) -> (u16, u16, u8) {
+        (self.date.0, self.time.0, self.centiseconds)
+    }
+
+    /// Returns the timestamp `secs` seconds and `nanos` nanoseconds after the
+    /// Unix epoch, or `None` if that time is not in range
+    /// [1980-01-01, 2107-12-31]. Times are in UTC unless the caller adjusts
+    /// `secs` to local time; FAT itself records no time zone.
+    pub fn from_unix(secs: u64, nanos: u32) -> Option<Timestamp> {
+        if secs < FAT_EPOCH_UNIX || nanos >= 1_000_000_000 {
+            return None;
+        }
+
+        // Civil-from-days conversion; see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = (secs / 86400) as i64 + 719468;
+        let era = days / 146097;
+        let doe = days - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as usize;
+
+        let secs_of_day = secs % 86400;
+        Timestamp::new(
+            year,
+            month,
+            day,
+            (secs_of_day / 3600) as u8,
+            (secs_of_day / 60 % 60) as u8,
+            (secs_of_day % 60) as u8,
+            (nanos / 1_000_000) as u16,
+        )
+    }
+
+    /// Returns the number of whole seconds between the Unix epoch and this
+    /// timestamp; the inverse of `from_unix()` up to sub-second precision.
+    pub fn to_unix(&self) -> u64 {
+        use traits::Timestamp;
+
+        // Days-from-civil conversion; see
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let (month, day) = (self.month() as i64, self.day() as i64);
+        let year = self.year() as i64 - if month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let yoe = year - era * 400;
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let secs_of_day = self.hour() as i64 * 3600 + self.minute() as i64 * 60 + self.second() as i64;
+        ::std::cmp::max(days * 86400 + secs_of_day, 0) as u64
+    }
+
+    /// The milliseconds past the second, a multiple of 10.
+    pub fn millisecond(&self) -> u16 {
+        (self.centiseconds % 100) as u16 * 10
+    }
+
+    /// Returns the timestamp rounded down to the two-second resolution of
+    /// modification times, as it reads back once written to disk.
+    pub fn truncated(&self) -> Timestamp {
+        Timestamp { centiseconds: 0, ..*self }
+    }
+
+    /// Returns the current time of the host's clock, or the FAT epoch if the
+    /// host has no clock or it is out of range. File systems use their
+    /// `TimeSource` instead; this is for images created without one.
+    pub(crate) fn now() -> Timestamp {
+        imp::now().unwrap_or(Timestamp::EPOCH)
+    }
+
+    /// Returns the date in the high and the time in the low 16 bits, the
+    /// usual basis of a new volume's serial number.
+    pub(crate) fn packed(&self) -> u32 {
+        ((self.date.0 as u32) << 16) | self.time.0 as u32
+    }
+}
+
+/// Seconds between the Unix epoch and the FAT epoch, 1980-01-01.
+const FAT_EPOCH_UNIX: u64 = 315532800;
+
+/// A source of the current time, used to stamp entries as they are created
+/// and modified.
+///
+/// The host's clock is used by default; set a different source with
+/// `VFat::set_time_source()`, e.g. in a kernel that keeps time with a timer.
+pub trait TimeSource: Send + fmt::Debug {
+    /// Returns the current time, or `None` if it is not known.
+    fn now(&self) -> Option<Timestamp>;
+}
+
+/// The host's system clock, reporting UTC. Without a host clock, as in the
+/// kernel, it never knows the time.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> Option<Timestamp> {
+        imp::now()
+    }
+}
+
+/// A clock stopped at a fixed time, for reproducible images.
+#[derive(Debug, Copy, Clone)]
+pub struct FixedClock(pub Timestamp);
+
+impl TimeSource for FixedClock {
+    fn now(&self) -> Option<Timestamp> {
+        Some(self.0)
+    }
+}
+
+#[cfg(not(target_os = "ros"))]
+mod imp {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use super::Timestamp;
+
+    pub fn now() -> Option<Timestamp> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Timestamp::from_unix(since_epoch.as_secs(), since_epoch.subsec_nanos())
+    }
+}
+
+#[cfg(target_os = "ros")]
+mod imp {
+    use super::Timestamp;
+
+    pub fn now() -> Option<Timestamp> {
+        None
+    }
 }
 
 impl Metadata {
+    /// Returns metadata for a new file or, if `directory` is `true`, a new
+    /// directory, created at `timestamp` and starting at cluster `cluster`.
+    pub(crate) fn new(directory: bool, cluster: u32, timestamp: Timestamp) -> Metadata {
+        let mut metadata = Metadata {
+            attributes: Attributes(if directory { DIRECTORY } else { ARCHIVE }),
+            tenths_creation_time: timestamp.centiseconds,
+            creation_time: timestamp.time,
+            creation_date: timestamp.date,
+            last_accessed_date: timestamp.date,
+            last_modification_time: timestamp.time,
+            last_modification_date: timestamp.date,
+            ..Metadata::default()
+        };
+        metadata.set_start_cluster(cluster);
+        metadata
+    }
+
+    /// Returns metadata for the volume label entry of a root directory,
+    /// written at `timestamp`.
+    pub(crate) fn volume_label(timestamp: Timestamp) -> Metadata {
+        Metadata {
+            attributes: Attributes(VOLUME_ID),
+            last_modification_time: timestamp.time,
+            last_modification_date: timestamp.date,
+            ..Metadata::default()
+        }
+    }
+
     pub fn start_cluster(&self) -> u32 {
         ((self.high_cluster_number as u32) << 16) + self.low_cluster_number as u32
     }
+
+    pub(crate) fn set_start_cluster(&mut self, cluster: u32) {
+        self.high_cluster_number = (cluster >> 16) as u16;
+        self.low_cluster_number = cluster as u16;
+    }
+
+    pub(crate) fn set_len(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    /// Records a modification at `timestamp` and sets the archive bit.
+    pub(crate) fn touch(&mut self, timestamp: Timestamp) {
+        self.set_modified(timestamp);
+        self.set_accessed(timestamp);
+        self.set_archive(true);
+    }
+
+    /// Whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.attributes.0 & DIRECTORY != 0
+    }
+
+    fn set_attribute(&mut self, attribute: u8, value: bool) {
+        if value {
+            self.attributes.0 |= attribute;
+        } else {
+            self.attributes.0 &= !attribute;
+        }
+    }
+
+    /// Sets or clears the read-only attribute.
+    pub fn set_read_only(&mut self, value: bool) {
+        self.set_attribute(READ_ONLY, value);
+    }
+
+    /// Sets or clears the hidden attribute.
+    pub fn set_hidden(&mut self, value: bool) {
+        self.set_attribute(HIDDEN, value);
+    }
+
+    /// Sets or clears the system attribute.
+    pub fn set_system(&mut self, value: bool) {
+        self.set_attribute(SYSTEM, value);
+    }
+
+    /// Sets or clears the archive attribute.
+    pub fn set_archive(&mut self, value: bool) {
+        self.set_attribute(ARCHIVE, value);
+    }
+
+    /// Sets the creation time, with a resolution of 10 ms.
+    pub fn set_created(&mut self, timestamp: Timestamp) {
+        self.creation_date = timestamp.date;
+        self.creation_time = timestamp.time;
+        self.tenths_creation_time = timestamp.centiseconds;
+    }
+
+    /// Sets the last access date. FAT does not record the time of day of
+    /// accesses, so that of `timestamp` is ignored.
+    pub fn set_accessed(&mut self, timestamp: Timestamp) {
+        self.last_accessed_date = timestamp.date;
+    }
+
+    /// Sets the last modification time, rounded down to two seconds.
+    pub fn set_modified(&mut self, timestamp: Timestamp) {
+        self.last_modification_date = timestamp.date;
+        self.last_modification_time = timestamp.time;
+    }
+
+    /// Copies the read-only, hidden, system and archive attributes and the
+    /// timestamps of `other` into `self`, leaving the entry's type, start
+    /// cluster and size untouched.
+    pub(crate) fn update_from(&mut self, other: &Metadata) {
+        const SETTABLE: u8 = READ_ONLY | HIDDEN | SYSTEM | ARCHIVE;
+        self.attributes.0 = (self.attributes.0 & !SETTABLE) | (other.attributes.0 & SETTABLE);
+        self.tenths_creation_time = other.tenths_creation_time;
+        self.creation_time = other.creation_time;
+        self.creation_date = other.creation_date;
+        self.last_accessed_date = other.last_accessed_date;
+        self.last_modification_time = other.last_modification_time;
+        self.last_modification_date = other.last_modification_date;
+    }
 }
 
 // FIXME: Implement `traits::Timestamp` for `Timestamp`.
@@ -69,7 +407,7 @@ impl traits::Timestamp for Timestamp {
     }
 
     fn second(&self) -> u8 {
-        ((self.time.0 as u8) & 0b11111) * 2
+        ((self.time.0 as u8) & 0b11111) * 2 + self.centiseconds / 100
     }
 }
 
@@ -78,32 +416,43 @@ impl traits::Metadata for Metadata {
     type Timestamp = Timestamp;
 
     fn read_only(&self) -> bool {
-        self.attributes.0 & 0x01 == 0x01
+        self.attributes.0 & READ_ONLY == READ_ONLY
     }
 
     fn hidden(&self) -> bool {
-        self.attributes.0 & 0x02 == 0x02
+        self.attributes.0 & HIDDEN == HIDDEN
+    }
+
+    fn system(&self) -> bool {
+        self.attributes.0 & SYSTEM == SYSTEM
+    }
+
+    fn archive(&self) -> bool {
+        self.attributes.0 & ARCHIVE == ARCHIVE
+    }
+
+    fn is_volume_label(&self) -> bool {
+        // Long file name entries set every attribute bit up to VOLUME_ID.
+        self.attributes.0 & (VOLUME_ID | DIRECTORY) == VOLUME_ID
+            && self.attributes.0 & 0x0F != 0x0F
+    }
+
+    fn len(&self) -> u64 {
+        self.size as u64
     }
 
     fn created(&self) -> Self::Timestamp {
-        Timestamp {
-            date: self.creation_date,
-            time: self.creation_time,
-        }
+        Timestamp::from_dos(self.creation_date.0, self.creation_time.0, self.tenths_creation_time)
     }
 
+    /// FAT records only the date of the last access; the time reads as
+    /// midnight.
     fn accessed(&self) -> Self::Timestamp {
-        Timestamp {
-            date: self.last_accessed_date,
-            time: Time(0),
-        }
+        Timestamp::from_dos(self.last_accessed_date.0, 0, 0)
     }
 
     fn modified(&self) -> Self::Timestamp {
-        Timestamp {
-            date: self.last_modification_date,
-            time: self.last_modification_time,
-        }
+        Timestamp::from_dos(self.last_modification_date.0, self.last_modification_time.0, 0)
     }
 }
 
@@ -115,6 +464,9 @@ impl fmt::Display for Metadata {
             .field("cluster", &self.start_cluster())
             .field("read_only", &self.read_only())
             .field("hidden", &self.hidden())
+            .field("system", &self.system())
+            .field("archive", &self.archive())
+            .field("len", &self.len())
             .field("created", &self.created())
             .field("accessed", &self.accessed())
             .field("modified", &self.modified())
@@ -9,6 +9,14 @@ pub(crate) mod entry;
 pub(crate) mod metadata;
 pub(crate) mod cache;
 pub(crate) mod shared;
+pub(crate) mod name;
+pub(crate) mod codepage;
+pub(crate) mod dentry;
+pub(crate) mod deleted;
+pub(crate) mod defrag;
+pub(crate) mod format;
+pub(crate) mod check;
+pub(crate) mod fsinfo;
 
 pub use self::ebpb::BiosParameterBlock;
 pub use self::file::File;
@@ -16,9 +24,17 @@ pub use self::dir::Dir;
 pub use self::error::Error;
 pub use self::vfat::VFat;
 pub use self::entry::Entry;
-pub use self::metadata::{Metadata, Attributes, Date, Time, Timestamp};
+pub use self::metadata::{Metadata, Attributes, Date, Time, Timestamp, TimeSource, SystemClock, FixedClock};
 pub use self::shared::Shared;
+pub use self::cache::CacheStats;
+pub use self::codepage::CodePage;
+pub use self::dentry::DentryStats;
+pub use self::deleted::DeletedEntry;
+pub use self::defrag::{defragment, fragmentation, FragmentationReport, Fragments};
+pub use self::format::{format, FormatOptions};
+pub use self::check::{check, CheckReport, Finding, Problem};
 
 pub(crate) use self::cache::{CachedDevice, Partition};
 pub(crate) use self::fat::{Status, FatEntry};
+pub use self::fat::{FatCopyMismatch, FatType};
 pub(crate) use self::cluster::Cluster;
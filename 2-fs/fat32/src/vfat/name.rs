@@ -0,0 +1,192 @@
+use std::io;
+
+use vfat::CodePage;
+
+/// The maximum length, in UTF-16 code units, of a long file name.
+pub const MAX_LFN_LEN: usize = 255;
+
+/// Characters that may never appear in a long file name.
+const INVALID_LFN_CHARS: &str = "\"*/:<>?\\|";
+
+/// Characters, besides upper-case ASCII letters and digits, allowed in short
+/// (8.3) names.
+const SHORT_NAME_SPECIAL_CHARS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+/// Returns `Ok` if `name` may be used as the name of a new directory entry.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `name` is empty, is `.` or `..`,
+/// is longer than 255 UTF-16 code units, or contains a control character or
+/// one of `"*/:<>?\|`.
+pub fn validate(name: &str) -> io::Result<()> {
+    let invalid = name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.encode_utf16().count() > MAX_LFN_LEN
+        || name.chars().any(|c| (c as u32) < 0x20 || INVALID_LFN_CHARS.contains(c));
+
+    if invalid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+    }
+    Ok(())
+}
+
+/// Computes the checksum of a raw 8.3 name stored in each LFN entry that
+/// belongs to it.
+pub fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c)
+    })
+}
+
+/// Returns the volume label stored in the space-padded `raw` label, or
+/// `None` if it is blank or the placeholder `NO NAME`.
+pub fn parse_label(raw: &[u8]) -> Option<String> {
+    let label = String::from_utf8_lossy(raw).trim_end_matches(|c| c == ' ' || c == '\0').to_string();
+    match &label[..] {
+        "" | "NO NAME" => None,
+        _ => Some(label),
+    }
+}
+
+/// Decodes the raw, space-padded 8.3 name `raw` to its display form, e.g.
+/// `README.TXT`, interpreting bytes above 0x7F in `code_page`.
+pub fn decode_short_name(raw: &[u8; 11], code_page: CodePage) -> String {
+    let mut raw = *raw;
+    // A leading 0xE5 is stored as 0x05 so as not to mark the entry deleted.
+    if raw[0] == 0x05 {
+        raw[0] = 0xE5;
+    }
+
+    let decode = |part: &[u8]| -> String {
+        let len = part.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        part[..len].iter().map(|&b| code_page.decode(b)).collect()
+    };
+    let mut name = decode(&raw[..8]);
+    let extension = decode(&raw[8..]);
+    if !extension.is_empty() {
+        name.push('.');
+        name.push_str(&extension);
+    }
+    name
+}
+
+/// Maps `c` to its simple case folding: the single character that `c` and
+/// all characters differing from it only in case fold to.
+fn fold_case(c: char) -> char {
+    match c as u32 {
+        // Cherokee folds to its upper-case letters, which came first.
+        0x13A0..=0x13F5 => c,
+        0x13F8..=0x13FD => ::std::char::from_u32(c as u32 - 8).unwrap_or(c),
+        0xAB70..=0xABBF => ::std::char::from_u32(c as u32 - 0xAB70 + 0x13A0).unwrap_or(c),
+        // Lower-case letters that fold to another lower-case letter.
+        0x00B5 => 'μ',
+        0x017F => 's',
+        0x0345 | 0x1FBE => 'ι',
+        0x03C2 => 'σ',
+        0x03D0 => 'β',
+        0x03D1 => 'θ',
+        0x03D5 => 'φ',
+        0x03D6 => 'π',
+        0x03F0 => 'κ',
+        0x03F1 => 'ρ',
+        0x03F5 => 'ε',
+        0x1E9B => 'ṡ',
+        _ => {
+            // Otherwise the folding is the lower-case mapping, unless that
+            // takes more than one character, e.g. for U+0130 'İ'.
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) => l,
+                _ => c,
+            }
+        }
+    }
+}
+
+/// Returns `name` with every character case-folded, a key under which all
+/// names `eq_ignore_case()` to `name` are equal.
+pub fn fold(name: &str) -> String {
+    name.chars().map(fold_case).collect()
+}
+
+/// Whether `a` and `b` are equal ignoring case, as by Unicode simple case
+/// folding.
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().map(fold_case).eq(b.chars().map(fold_case))
+}
+
+fn is_short_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || SHORT_NAME_SPECIAL_CHARS.contains(&c)
+}
+
+/// Returns the raw, space-padded 8.3 form of `name` if `name` is already a
+/// valid upper-case short name and therefore needs no long file name entries.
+pub fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
+    };
+
+    let valid = !base.is_empty() && base.len() <= 8 && ext.len() <= 3
+        && (name.contains('.') == !ext.is_empty())
+        && base.bytes().chain(ext.bytes()).all(is_short_name_char);
+    if !valid {
+        return None;
+    }
+
+    let mut raw = [b' '; 11];
+    raw[..base.len()].copy_from_slice(base.as_bytes());
+    raw[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(raw)
+}
+
+/// Generates a unique 8.3 alias (e.g. `LONGFI~1.TXT`) for the long name
+/// `name` that is not in `existing`.
+///
+/// # Errors
+///
+/// Returns an error of kind `AlreadyExists` if every numeric tail from `~1`
+/// to `~999999` is taken.
+pub fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> io::Result<[u8; 11]> {
+    fn convert(part: &str, max: usize) -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+                if c.is_ascii() && is_short_name_char(c as u8) { c as u8 } else { b'_' }
+            })
+            .take(max)
+            .collect()
+    }
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(i) => (convert(&trimmed[..i], 8), convert(&trimmed[i + 1..], 3)),
+        None => (convert(trimmed, 8), Vec::new()),
+    };
+
+    let mut raw = [b' '; 11];
+    raw[8..8 + ext.len()].copy_from_slice(&ext);
+
+    for n in 1..1_000_000u32 {
+        let tail = format!("~{}", n);
+        let base_len = ::std::cmp::min(base.len(), 8 - tail.len());
+        for (i, byte) in raw[..8].iter_mut().enumerate() {
+            *byte = if i < base_len {
+                base[i]
+            } else if i < base_len + tail.len() {
+                tail.as_bytes()[i - base_len]
+            } else {
+                b' '
+            };
+        }
+
+        if !existing.contains(&raw) {
+            return Ok(raw);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "no unique short name available"))
+}
@@ -1,15 +1,22 @@
 use std::cmp::min;
 use std::io;
+use std::ffi::OsStr;
 use std::io::Write;
 use std::mem::size_of;
 use std::path::Component;
 use std::path::Path;
 
+use gpt::{Guid, GuidPartitionTable};
 use mbr::MasterBootRecord;
-use traits::{BlockDevice, FileSystem};
+use traits::{BlockDevice, FileSystem, File as FileTrait};
 use util::SliceExt;
-use vfat::{Cluster, Dir, Entry, Error, FatEntry, File, Shared, Status};
-use vfat::{BiosParameterBlock, CachedDevice, Partition};
+use vfat::{Cluster, CodePage, Dir, Entry, Error, FatCopyMismatch, FatEntry, FatType, File, Metadata, Shared, Status, SystemClock, Timestamp, TimeSource};
+use vfat::{BiosParameterBlock, CacheStats, CachedDevice, DeletedEntry, DentryStats, Partition};
+use vfat::dentry::{Dentry, DentryCache, DEFAULT_DENTRY_CAPACITY};
+use vfat::dir::{EntryPosition, RawEntries, VFatDirEntry};
+use vfat::deleted;
+use vfat::fsinfo::FsInfo;
+use vfat::name;
 
 #[derive(Debug)]
 pub struct VFat {
@@ -17,45 +24,363 @@ pub struct VFat {
     pub bytes_per_sector: u16,
     pub sectors_per_cluster: u8,
     sectors_per_fat: u32,
+    number_of_fats: u8,
+    /// The only FAT in use if mirroring is disabled.
+    active_fat: Option<u8>,
+    fat_type: FatType,
     fat_start_sector: u64,
+    root_dir_start_sector: u64,
+    root_dir_entries: u32,
     data_start_sector: u64,
+    data_clusters: u32,
+    /// The sector of the FSInfo structure, if the volume has a valid one.
+    fs_info_sector: Option<u64>,
+    /// The number of free clusters, if known.
+    free_clusters: Option<u32>,
+    /// The cluster to start searching for a free cluster at.
+    next_free: u32,
+    /// Whether `free_clusters` or `next_free` changed since the FSInfo
+    /// sector was last updated.
+    fs_info_dirty: bool,
+    /// The volume serial number from the boot sector, if any.
+    volume_id: Option<u32>,
+    /// The volume label from the boot sector, if any.
+    boot_volume_label: Option<String>,
+    /// The clock entries are stamped with as they are created and modified.
+    time_source: Box<dyn TimeSource>,
+    /// The character set of short names.
+    code_page: CodePage,
+    /// Cached lookups of names in directories.
+    dentries: DentryCache,
+    /// The number of clusters read into the sector cache ahead of sequential
+    /// file reads.
+    read_ahead: u32,
+    /// The first cluster of the root directory. On FAT12 and FAT16 volumes,
+    /// where the root directory is a fixed region preceding the data region,
+    /// this is cluster 0.
     pub root_dir_cluster: Cluster,
 }
 
 impl VFat {
+    /// Mounts the first FAT12, FAT16 or FAT32 partition listed in the MBR of
+    /// `device`. If the MBR is a protective MBR, the first Microsoft basic
+    /// data or EFI system partition in the GUID partition table holding a FAT
+    /// volume is mounted instead. If the device has no usable partition table
+    /// or no FAT partition but sector 0 holds a FAT boot sector, the whole
+    /// device is mounted as a "superfloppy".
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no FAT partition is found and the device is not
+    /// a superfloppy, `Mbr` if the MBR is invalid and the device is not a
+    /// superfloppy, or `Gpt` if the GPT of a GPT disk is invalid.
     pub fn from<T>(mut device: T) -> Result<Shared<VFat>, Error>
         where T: BlockDevice + 'static
     {
-        let mbr = MasterBootRecord::from(&mut device).unwrap();
-        for _ in 0..4 {
-            let partition = mbr.get_partition(0);
-            match partition.partition_type {
-                0xB | 0xC => {
-                    let partition_start = partition.relative_sector as u64;
-                    let ebpb = BiosParameterBlock::from(&mut device, partition_start).unwrap();
-                    let partition = Partition {
-                        start: partition_start,
-                        sector_size: ebpb.bytes_per_sector as u64,
-                    };
-
-                    let cached_device = CachedDevice::new(device, partition);
-                    let fat_start_sector = partition_start + ebpb.reserved_sectors as u64;
-                    let data_start_sector = fat_start_sector + ebpb.sector_per_fat_32 as u64 * ebpb.number_of_fat as u64;
-                    let vfat = VFat {
-                        device: cached_device,
-                        bytes_per_sector: ebpb.bytes_per_sector,
-                        sectors_per_cluster: ebpb.sectors_per_cluster,
-                        sectors_per_fat: ebpb.sector_per_fat_32,
-                        fat_start_sector,
-                        data_start_sector,
-                        root_dir_cluster: Cluster::from(ebpb.root_dir_cluster_number),
-                    };
-                    return Ok(Shared::new(vfat));
+        let error = match MasterBootRecord::from(&mut device) {
+            Ok(ref mbr) if (0..4).any(|i| mbr.get_partition(i).is_gpt_protective()) => {
+                let gpt = GuidPartitionTable::from(&mut device)?;
+                let start = gpt.partitions()
+                    .filter(|&(_, partition)| {
+                        partition.type_guid == Guid::MICROSOFT_BASIC_DATA
+                            || partition.type_guid == Guid::EFI_SYSTEM
+                    })
+                    .map(|(_, partition)| partition.first_lba)
+                    .find(|&start| match BiosParameterBlock::from(&mut device, start) {
+                        Ok(ebpb) => ebpb.is_fat(),
+                        Err(_) => false,
+                    });
+                if let Some(start) = start {
+                    return VFat::mount(device, start);
                 }
-                _ => {}
+                return Err(Error::NotFound);
             }
+            Ok(mbr) => {
+                let partition = (0..4)
+                    .map(|i| mbr.get_partition(i))
+                    .find(|partition| partition.is_fat());
+                if let Some(partition) = partition {
+                    return VFat::mount(device, partition.relative_sector as u64);
+                }
+                Error::NotFound
+            }
+            Err(e) => Error::Mbr(e),
+        };
+
+        match BiosParameterBlock::from(&mut device, 0) {
+            Ok(ref ebpb) if ebpb.is_fat() => VFat::mount(device, 0),
+            _ => Err(error),
+        }
+    }
+
+    /// Mounts the FAT partition in slot `index` (0 to 3) of the MBR of
+    /// `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoPartition` if `index` is not a valid slot or the slot is
+    /// empty and `UnsupportedPartitionType` if the partition is not FAT.
+    pub fn from_partition<T>(mut device: T, index: usize) -> Result<Shared<VFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        if index >= 4 {
+            return Err(Error::NoPartition(index));
+        }
+
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let partition = mbr.get_partition(index);
+        match partition.partition_type {
+            0x00 => Err(Error::NoPartition(index)),
+            _ if partition.is_fat() => VFat::mount(device, partition.relative_sector as u64),
+            partition_type => Err(Error::UnsupportedPartitionType(partition_type)),
+        }
+    }
+
+    /// Mounts the FAT volume in the partition at index `index` of the GUID
+    /// partition table of `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Gpt` if the GPT is invalid and `NoPartition` if entry `index`
+    /// is unused.
+    pub fn from_gpt_partition<T>(mut device: T, index: usize) -> Result<Shared<VFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        let start = GuidPartitionTable::from(&mut device)?
+            .get_partition(index)
+            .ok_or(Error::NoPartition(index))?
+            .first_lba;
+        VFat::mount(device, start)
+    }
+
+    /// Mounts `device` as a FAT volume without a partition table (a
+    /// "superfloppy"); the boot sector is sector 0.
+    pub fn from_raw<T>(device: T) -> Result<Shared<VFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        VFat::mount(device, 0)
+    }
+
+    /// Mounts the FAT volume whose boot sector is physical sector
+    /// `partition_start` of `device`.
+    fn mount<T>(mut device: T, partition_start: u64) -> Result<Shared<VFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        let ebpb = BiosParameterBlock::from(&mut device, partition_start)?;
+        if !ebpb.is_fat() {
+            return Err(Error::BadBpb("invalid volume geometry"));
+        }
+        if (ebpb.bytes_per_sector as u64) < device.sector_size() {
+            return Err(Error::BadBpb("logical sectors smaller than device sectors"));
+        }
+        let partition = Partition {
+            start: partition_start,
+            sector_size: ebpb.bytes_per_sector as u64,
+        };
+
+        let cached_device = CachedDevice::new(device, partition);
+        let fat_type = ebpb.fat_type();
+        let fat_start_sector = partition_start + ebpb.reserved_sectors as u64;
+        let root_dir_start_sector = fat_start_sector + ebpb.sectors_per_fat() as u64 * ebpb.number_of_fat as u64;
+        let data_start_sector = root_dir_start_sector + ebpb.root_dir_sectors() as u64;
+        let data_sectors = (partition_start + ebpb.total_sectors() as u64)
+            .saturating_sub(data_start_sector);
+        // Clusters beyond what the FAT can describe, or whose numbers would
+        // collide with the FAT's special values, are unusable.
+        let (entry_bits, max_clusters) = match fat_type {
+            FatType::Fat12 => (12, 0xFF0 - 2),
+            FatType::Fat16 => (16, 0xFFF0 - 2),
+            FatType::Fat32 => (32, 0x0FFFFFF0 - 2),
+        };
+        let fat_entries = ebpb.sectors_per_fat() as u64 * ebpb.bytes_per_sector as u64 * 8 / entry_bits;
+        let data_clusters = min(
+            min(data_sectors / ebpb.sectors_per_cluster as u64, fat_entries.saturating_sub(2)),
+            max_clusters,
+        ) as u32;
+        if data_clusters == 0 {
+            return Err(Error::BadBpb("volume has no data clusters"));
+        }
+        let root_dir_cluster = match fat_type {
+            FatType::Fat32 => Cluster::from(ebpb.root_dir_cluster_number),
+            _ => Cluster::from(0),
+        };
+        let mut vfat = VFat {
+            device: cached_device,
+            bytes_per_sector: ebpb.bytes_per_sector,
+            sectors_per_cluster: ebpb.sectors_per_cluster,
+            sectors_per_fat: ebpb.sectors_per_fat(),
+            number_of_fats: ebpb.number_of_fat,
+            active_fat: ebpb.active_fat(),
+            fat_type,
+            fat_start_sector,
+            root_dir_start_sector,
+            root_dir_entries: ebpb.root_dir_entries() as u32,
+            data_start_sector,
+            data_clusters,
+            fs_info_sector: None,
+            free_clusters: None,
+            next_free: 2,
+            fs_info_dirty: false,
+            volume_id: ebpb.volume_id(),
+            boot_volume_label: ebpb.volume_label(),
+            time_source: Box::new(SystemClock),
+            code_page: CodePage::default(),
+            dentries: DentryCache::new(DEFAULT_DENTRY_CAPACITY),
+            read_ahead: DEFAULT_READ_AHEAD,
+            root_dir_cluster,
+        };
+        if fat_type == FatType::Fat32 && !vfat.is_data_cluster(root_dir_cluster) {
+            return Err(Error::BadBpb("root directory cluster out of range"));
         }
-        Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData, "fat32 partition not found")))
+        if let Some(sector) = ebpb.fs_info_sector() {
+            vfat.load_fs_info(partition_start + sector as u64)?;
+        }
+        Ok(Shared::new(vfat))
+    }
+
+    /// Reads the free cluster count and next free cluster hint from the
+    /// FSInfo structure in `sector`. Invalid FSInfo sectors are ignored.
+    fn load_fs_info(&mut self, sector: u64) -> io::Result<()> {
+        let info = match FsInfo::from(self.device.get(sector)?) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        self.fs_info_sector = Some(sector);
+        self.free_clusters = info.free_count(self.data_clusters);
+        self.next_free = info.next_free(self.data_clusters).unwrap_or(2);
+        Ok(())
+    }
+
+    /// Returns the device sector holding the volume's boot sector.
+    pub fn volume_start(&self) -> u64 {
+        self.device.partition_start()
+    }
+
+    /// Sets the clock used to stamp entries as they are created and
+    /// modified, replacing the host's system clock.
+    pub fn set_time_source<T: TimeSource + 'static>(&mut self, source: T) {
+        self.time_source = Box::new(source);
+    }
+
+    /// Returns the current time according to the volume's time source, or
+    /// the FAT epoch if the time is not known.
+    pub fn now(&self) -> Timestamp {
+        self.time_source.now().unwrap_or(Timestamp::EPOCH)
+    }
+
+    /// Returns the OEM code page short names are decoded with.
+    pub fn code_page(&self) -> CodePage {
+        self.code_page
+    }
+
+    /// Sets the OEM code page short names are decoded with; code page 437
+    /// by default.
+    pub fn set_code_page(&mut self, code_page: CodePage) {
+        self.code_page = code_page;
+        // Cached lookups may have matched short names decoded differently.
+        self.dentries.clear();
+    }
+
+    /// Returns the volume serial number recorded in the boot sector, if any.
+    pub fn volume_id(&self) -> Option<u32> {
+        self.volume_id
+    }
+
+    /// Returns the volume label. The label entry of the root directory takes
+    /// precedence over the label in the boot sector, as it is the one
+    /// updated by most tools.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root directory cannot be read.
+    pub fn volume_label(&mut self) -> io::Result<Option<String>> {
+        let mut raw = RawEntries::new(self.root_dir_cluster);
+        'scan: while raw.read_next(self)? {
+            for entry in raw.entries() {
+                let unknown = entry.unknown();
+                if unknown.is_deleted_or_unused() {
+                    continue;
+                } else if unknown.prev_is_last_entry() {
+                    break 'scan;
+                } else if unknown.is_volume_label() {
+                    return Ok(name::parse_label(&entry.regular().short_name()));
+                }
+            }
+        }
+        Ok(self.boot_volume_label.clone())
+    }
+
+    /// Returns the FAT type of the volume.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// Returns `true` if `cluster` is a cluster of the data region.
+    pub(crate) fn is_data_cluster(&self, cluster: Cluster) -> bool {
+        cluster.is_valid() && cluster.id() < self.data_clusters + 2
+    }
+
+    /// Returns `true` if `dir` refers to the fixed-size root directory region
+    /// of a FAT12 or FAT16 volume rather than to a cluster chain.
+    pub(crate) fn is_fixed_root(&self, dir: Cluster) -> bool {
+        self.fat_type != FatType::Fat32 && dir.id() == 0
+    }
+
+    /// Returns the number of data clusters; valid cluster numbers are 2 up to
+    /// and excluding `data_clusters() + 2`.
+    pub(crate) fn data_clusters(&self) -> u32 {
+        self.data_clusters
+    }
+
+    /// Returns the number of copies of the FAT.
+    pub fn number_of_fats(&self) -> u8 {
+        self.number_of_fats
+    }
+
+    /// Returns the index of the FAT that is read: the active FAT if
+    /// mirroring is disabled and the first FAT otherwise.
+    pub fn active_fat(&self) -> u8 {
+        self.active_fat.unwrap_or(0)
+    }
+
+    /// Returns `true` if FAT updates are mirrored into every copy of the FAT.
+    pub fn is_mirroring(&self) -> bool {
+        self.active_fat.is_none()
+    }
+
+    /// Returns the size of the data region of the volume in bytes.
+    pub fn total_space(&self) -> u64 {
+        self.data_clusters as u64 * self.bytes_per_cluster() as u64
+    }
+
+    /// Returns the number of bytes in free clusters. If the number of free
+    /// clusters is not known from the FSInfo sector, it is counted by
+    /// scanning the FAT once.
+    pub fn free_space(&mut self) -> io::Result<u64> {
+        let free = match self.free_clusters {
+            Some(free) => free,
+            None => {
+                let mut free = 0;
+                for id in 2..self.data_clusters + 2 {
+                    if self.fat_entry(Cluster::from(id))?.status() == Status::Free {
+                        free += 1;
+                    }
+                }
+                self.free_clusters = Some(free);
+                free
+            }
+        };
+        Ok(free as u64 * self.bytes_per_cluster() as u64)
+    }
+
+    /// Returns the number of bytes in a cluster.
+    pub fn bytes_per_cluster(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// Returns the first logical sector of the data cluster `cluster`.
+    fn cluster_sector(&self, cluster: Cluster) -> u64 {
+        self.data_start_sector + cluster.index() as u64 * self.sectors_per_cluster as u64
     }
 
     // TODO: The following methods may be useful here:
@@ -68,7 +393,13 @@ impl VFat {
         offset: usize,
         mut buf: &mut [u8],
     ) -> io::Result<usize> {
-        let sector = self.data_start_sector + cluster.index() as u64 * self.sectors_per_cluster as u64;
+        if !self.is_data_cluster(cluster) {
+            return Err(cluster_out_of_range());
+        }
+        if offset > self.bytes_per_cluster() {
+            return Err(invalid_input("offset beyond end of cluster"));
+        }
+        let sector = self.cluster_sector(cluster);
         let mut cur_sector = sector + offset as u64 / self.bytes_per_sector as u64;
         let mut bytes_read = 0;
         let bytes_can_be_read = min(buf.len(), self.sectors_per_cluster as usize * self.bytes_per_sector as usize - offset);
@@ -82,6 +413,68 @@ impl VFat {
 
         Ok(bytes_can_be_read)
     }
+
+    /// Reads `buf.len()` bytes starting at byte `offset` of the run of
+    /// contiguous clusters beginning at `start` into `buf`. The caller must
+    /// ensure that the run holds the bytes read.
+    ///
+    /// Whole sectors making up at least a cluster are read directly from the
+    /// device in a single transfer, bypassing the sector cache unless they
+    /// are already cached; the rest is read through the cache.
+    pub(crate) fn read_run(&mut self, start: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_per_cluster = self.bytes_per_cluster();
+        let clusters = (offset + buf.len() + bytes_per_cluster - 1) / bytes_per_cluster;
+        let last = Cluster::from(start.id().saturating_add(clusters.saturating_sub(1) as u32));
+        if !self.is_data_cluster(start) || !self.is_data_cluster(last) {
+            return Err(cluster_out_of_range());
+        }
+
+        let sector = self.cluster_sector(start);
+        self.device.read_bytes(sector, offset, bytes_per_cluster, buf)
+    }
+
+    /// Reads the `count` contiguous clusters starting at `start` into the
+    /// sector cache ahead of them being read.
+    pub(crate) fn prefetch(&mut self, start: Cluster, count: u32) -> io::Result<()> {
+        let last = Cluster::from(start.id().saturating_add(count.saturating_sub(1)));
+        if count == 0 || !self.is_data_cluster(start) || !self.is_data_cluster(last) {
+            return Ok(());
+        }
+        let sectors = count as u64 * self.sectors_per_cluster as u64;
+        self.device.prefetch(self.cluster_sector(start), sectors)
+    }
+
+    /// Writes `buf` into `cluster` starting at byte `offset` of the cluster.
+    ///
+    /// At most `bytes_per_cluster() - offset` bytes are written. The number of
+    /// bytes written is returned. Data is written to the sector cache; call
+    /// `flush()` to write it to the device.
+    pub fn write_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &[u8],
+    ) -> io::Result<usize> {
+        if !self.is_data_cluster(cluster) {
+            return Err(cluster_out_of_range());
+        }
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let to_write = min(buf.len(), self.bytes_per_cluster().saturating_sub(offset));
+        let mut cur_sector = self.cluster_sector(cluster) + (offset / bytes_per_sector) as u64;
+        let mut cur_offset = offset % bytes_per_sector;
+        let mut bytes_written = 0;
+        while bytes_written < to_write {
+            let len = min(bytes_per_sector - cur_offset, to_write - bytes_written);
+            let data = self.device.get_mut(cur_sector)?;
+            data[cur_offset..cur_offset + len]
+                .copy_from_slice(&buf[bytes_written..bytes_written + len]);
+            bytes_written += len;
+            cur_sector += 1;
+            cur_offset = 0;
+        }
+
+        Ok(to_write)
+    }
     //
     //  * A method to read all of the clusters chained from a starting cluster
     //    into a vector.
@@ -98,6 +491,9 @@ impl VFat {
 
         loop {
             cluster_num += 1;
+            if cluster_num > self.data_clusters as usize {
+                return Err(chain_loops());
+            }
             let cur_entry = self.fat_entry(cur_cluster)?;
             match cur_entry.status() {
                 Status::Data(next_cluster) => {
@@ -119,23 +515,643 @@ impl VFat {
         self.read_cluster(cluster, 0, &mut buf[bytes_per_cluster * (cluster_num - 1)..])
     }
 
+    /// Appends the raw entries of the directory starting at `dir` to `buf`:
+    /// the fixed root directory region if `dir` is the root of a FAT12 or
+    /// FAT16 volume and the directory's cluster chain otherwise.
+    pub(crate) fn read_dir(&mut self, dir: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if !self.is_fixed_root(dir) {
+            return self.read_chain(dir, buf);
+        }
+
+        let bytes = self.root_dir_entries as usize * size_of::<VFatDirEntry>();
+        let sectors = (bytes + self.bytes_per_sector as usize - 1) / self.bytes_per_sector as usize;
+        let start = buf.len();
+        for i in 0..sectors as u64 {
+            let data = self.device.get(self.root_dir_start_sector + i)?;
+            buf.extend_from_slice(data);
+        }
+        buf.truncate(start + bytes);
+        Ok(bytes)
+    }
+
+    /// Returns the byte offset of the entry for `cluster` within a FAT.
+    fn fat_entry_offset(&self, cluster: Cluster) -> u64 {
+        let id = cluster.id() as u64;
+        match self.fat_type {
+            FatType::Fat12 => id + id / 2,
+            FatType::Fat16 => id * 2,
+            FatType::Fat32 => id * 4,
+        }
+    }
+
+    /// Returns the number of bytes that hold the entry for a cluster. FAT12
+    /// entries are read and written as the two bytes they overlap.
+    fn fat_entry_width(&self) -> usize {
+        match self.fat_type {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Reads `buf.len()` bytes at byte `offset` of FAT copy `fat`.
+    fn read_fat_bytes(&mut self, fat: u8, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        let fat_start = self.fat_start_sector + fat as u64 * self.sectors_per_fat as u64;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let position = offset + i as u64;
+            let data = self.device.get(fat_start + position / bytes_per_sector)?;
+            *byte = data[(position % bytes_per_sector) as usize];
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at byte `offset` of FAT copy `fat`.
+    fn write_fat_bytes(&mut self, fat: u8, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        let fat_start = self.fat_start_sector + fat as u64 * self.sectors_per_fat as u64;
+        for (i, &byte) in buf.iter().enumerate() {
+            let position = offset + i as u64;
+            let data = self.device.get_mut(fat_start + position / bytes_per_sector)?;
+            data[(position % bytes_per_sector) as usize] = byte;
+        }
+        Ok(())
+    }
+
+    /// Returns the raw value of FAT copy `fat` for `cluster`.
+    pub(crate) fn raw_fat_entry(&mut self, fat: u8, cluster: Cluster) -> io::Result<u32> {
+        if self.fat_type == FatType::Fat32 {
+            // FAT32 entries are aligned and never straddle a sector.
+            let bytes_per_sector = self.bytes_per_sector as u64;
+            let offset = self.fat_entry_offset(cluster);
+            let sector = self.fat_start_sector + fat as u64 * self.sectors_per_fat as u64
+                + offset / bytes_per_sector;
+            let index = (offset % bytes_per_sector) as usize;
+            let data = self.device.get(sector)?;
+            let entry: &FatEntry = unsafe { &data[index..index + size_of::<FatEntry>()].cast()[0] };
+            return Ok(entry.0 & 0x0FFFFFFF);
+        }
+
+        let mut bytes = [0u8; 4];
+        let width = self.fat_entry_width();
+        let offset = self.fat_entry_offset(cluster);
+        self.read_fat_bytes(fat, offset, &mut bytes[..width])?;
+
+        let raw = u32::from_le_bytes(bytes);
+        Ok(match self.fat_type {
+            FatType::Fat12 if cluster.id() % 2 == 1 => raw >> 4,
+            FatType::Fat12 => raw & 0xFFF,
+            _ => raw,
+        })
+    }
+
     //
     //  * A method to return a reference to a `FatEntry` for a cluster where the
     //    reference points directly into a cached sector.
     //
-    pub fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry> {
-        if !cluster.is_valid() {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, ""));
+    /// Returns the FAT entry for `cluster`.
+    pub fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        if !self.is_data_cluster(cluster) {
+            return Err(cluster_out_of_range());
+        }
+        let raw = self.raw_fat_entry(self.active_fat(), cluster)?;
+        Ok(FatEntry::from_raw(raw, self.fat_type))
+    }
+
+    /// Sets the FAT entry for `cluster` to `value` in every copy of the FAT,
+    /// or only in the active FAT if mirroring is disabled.
+    /// `value` uses FAT32 values and is truncated to the volume's entry width;
+    /// bits of neighbouring FAT12 entries and the reserved high four bits of
+    /// FAT32 entries are preserved.
+    pub(crate) fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        if !self.is_data_cluster(cluster) {
+            return Err(cluster_out_of_range());
         }
-        let fat_width = size_of::<FatEntry>();
+        let width = self.fat_entry_width();
+        let offset = self.fat_entry_offset(cluster);
 
-        let cluster_in_fat_sector = cluster.id() * fat_width as u32 / self.bytes_per_sector as u32;
-        let data = self.device.get(self.fat_start_sector + cluster_in_fat_sector as u64)?;
+        let was_free = self.raw_fat_entry(self.active_fat(), cluster)? == FatEntry::FREE;
+        let is_free = value == FatEntry::FREE;
+        if was_free != is_free {
+            if let Some(free) = self.free_clusters {
+                self.free_clusters = Some(if is_free { free + 1 } else { free.saturating_sub(1) });
+                self.fs_info_dirty |= self.fs_info_sector.is_some();
+            }
+        }
 
-        let index = (cluster.id() * fat_width as u32 - cluster_in_fat_sector * self.bytes_per_sector as u32) as usize;
-        let entry = unsafe { &data[index..index + fat_width].cast()[0] };
+        let copies = match self.active_fat {
+            Some(active) => active..active + 1,
+            None => 0..self.number_of_fats,
+        };
+        for fat in copies {
+            let mut bytes = [0u8; 4];
+            self.read_fat_bytes(fat, offset, &mut bytes[..width])?;
+            let old = u32::from_le_bytes(bytes);
+            let new = match self.fat_type {
+                FatType::Fat12 if cluster.id() % 2 == 1 => (old & 0x000F) | ((value & 0xFFF) << 4),
+                FatType::Fat12 => (old & 0xF000) | (value & 0xFFF),
+                FatType::Fat16 => value & 0xFFFF,
+                FatType::Fat32 => (old & 0xF0000000) | (value & 0x0FFFFFFF),
+            };
+            self.write_fat_bytes(fat, offset, &new.to_le_bytes()[..width])?;
+        }
+        Ok(())
+    }
+
+    /// Compares every copy of the FAT with the active FAT and returns the
+    /// copies that differ from it. Returns no mismatches if mirroring is
+    /// disabled, as the copies are then expected to diverge.
+    pub fn verify_fat_copies(&mut self) -> io::Result<Vec<FatCopyMismatch>> {
+        let mut mismatches = Vec::new();
+        if !self.is_mirroring() {
+            return Ok(mismatches);
+        }
+
+        let active = self.active_fat();
+        for copy in (0..self.number_of_fats).filter(|&copy| copy != active) {
+            let mut mismatch = FatCopyMismatch { copy, first_cluster: 0, entries: 0 };
+            for id in 2..self.data_clusters + 2 {
+                let cluster = Cluster::from(id);
+                if self.raw_fat_entry(copy, cluster)? != self.raw_fat_entry(active, cluster)? {
+                    if mismatch.entries == 0 {
+                        mismatch.first_cluster = id;
+                    }
+                    mismatch.entries += 1;
+                }
+            }
+            if mismatch.entries != 0 {
+                mismatches.push(mismatch);
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Overwrites every other copy of the FAT with the active FAT. Only
+    /// sectors that differ are written.
+    pub fn sync_fat_copies(&mut self) -> io::Result<()> {
+        let active = self.active_fat();
+        let active_start = self.fat_start_sector + active as u64 * self.sectors_per_fat as u64;
+        for copy in (0..self.number_of_fats).filter(|&copy| copy != active) {
+            let copy_start = self.fat_start_sector + copy as u64 * self.sectors_per_fat as u64;
+            for i in 0..self.sectors_per_fat as u64 {
+                let data = self.device.get(active_start + i)?.to_vec();
+                if self.device.get(copy_start + i)? != &data[..] {
+                    self.device.get_mut(copy_start + i)?.copy_from_slice(&data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates a free cluster and marks it as the end of a chain. The search
+    /// starts at the next free cluster hint of the FSInfo sector. If `prev`
+    /// is `Some`, the new cluster is linked to the end of `prev`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if the volume has no free clusters.
+    pub(crate) fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        // Search from the next free cluster hint, wrapping around once.
+        let start = self.next_free;
+        let end = self.data_clusters + 2;
+        for id in (start..end).chain(2..start) {
+            let cluster = Cluster::from(id);
+            if self.fat_entry(cluster)?.status() == Status::Free {
+                self.set_fat_entry(cluster, FatEntry::EOC)?;
+                if let Some(prev) = prev {
+                    self.set_fat_entry(prev, cluster.id())?;
+                }
+                self.next_free = if id + 1 < end { id + 1 } else { 2 };
+                self.fs_info_dirty |= self.fs_info_sector.is_some();
+                return Ok(cluster);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::Other, "no space left on device"))
+    }
+
+    /// Links the `count` consecutive clusters starting at `start` into a
+    /// chain, e.g. to restore the data of a deleted file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if any of the clusters is outside of
+    /// the volume or not free.
+    pub(crate) fn link_contiguous(&mut self, start: Cluster, count: u32) -> io::Result<()> {
+        if !deleted::clusters_free(self, start, count)? {
+            return Err(io::Error::new(io::ErrorKind::Other, "clusters are no longer free"));
+        }
+        for i in 0..count {
+            let next = if i + 1 < count { start.id() + i + 1 } else { FatEntry::EOC };
+            self.set_fat_entry(Cluster::from(start.id() + i), next)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the clusters in the chain starting at `start`, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the chain is broken or
+    /// longer than the volume, i.e. if it loops.
+    pub(crate) fn chain(&mut self, start: Cluster) -> io::Result<Vec<Cluster>> {
+        let mut clusters = vec![start];
+        let mut cur_cluster = start;
+        loop {
+            match self.fat_entry(cur_cluster)?.status() {
+                Status::Data(_) if clusters.len() >= self.data_clusters as usize => {
+                    return Err(chain_loops());
+                }
+                Status::Data(next_cluster) => {
+                    clusters.push(next_cluster);
+                    cur_cluster = next_cluster;
+                }
+                Status::Eoc(_) => return Ok(clusters),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster chain")),
+            }
+        }
+    }
+
+    /// Marks every cluster in the chain starting at `start` as free. Does
+    /// nothing if `start` is not a valid data cluster, as is the case for
+    /// empty files.
+    pub(crate) fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        if !start.is_valid() {
+            return Ok(());
+        }
+        for cluster in self.chain(start)? {
+            self.set_fat_entry(cluster, FatEntry::FREE)?;
+        }
+        Ok(())
+    }
+
+    /// Fills `cluster` with zeroes.
+    pub(crate) fn zero_cluster(&mut self, cluster: Cluster) -> io::Result<()> {
+        let zeroes = vec![0u8; self.bytes_per_cluster()];
+        self.write_cluster(cluster, 0, &zeroes)?;
+        Ok(())
+    }
+
+    /// Returns the logical sector and byte offset in that sector of the
+    /// `index`th 32-byte entry of the directory starting at `dir`.
+    fn dir_entry_sector(&mut self, dir: Cluster, index: usize) -> io::Result<(u64, usize)> {
+        let entry_size = size_of::<VFatDirEntry>();
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        if self.is_fixed_root(dir) {
+            if index >= self.root_dir_entries as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "root directory entry out of range"));
+            }
+            let byte_offset = index * entry_size;
+            let sector = self.root_dir_start_sector + (byte_offset / bytes_per_sector) as u64;
+            return Ok((sector, byte_offset % bytes_per_sector));
+        }
+
+        let entries_per_cluster = self.bytes_per_cluster() / entry_size;
+
+        let mut cluster = dir;
+        for _ in 0..index / entries_per_cluster {
+            cluster = self.fat_entry(cluster)?.next_cluster()
+                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster chain"))?;
+        }
+
+        let byte_offset = (index % entries_per_cluster) * entry_size;
+        let sector = self.cluster_sector(cluster) + (byte_offset / bytes_per_sector) as u64;
+        Ok((sector, byte_offset % bytes_per_sector))
+    }
+
+    /// Returns a copy of the `index`th 32-byte entry of the directory
+    /// starting at `dir`.
+    pub(crate) fn dir_entry(&mut self, dir: Cluster, index: usize) -> io::Result<VFatDirEntry> {
+        let (sector, offset) = self.dir_entry_sector(dir, index)?;
+        let data = self.device.get(sector)?;
+        Ok(unsafe { data[offset..offset + size_of::<VFatDirEntry>()].cast()[0] })
+    }
+
+    /// Returns a mutable reference to the `index`th 32-byte entry of the
+    /// directory starting at `dir`. The reference points directly into a
+    /// cached sector, which is marked dirty.
+    pub(crate) fn dir_entry_mut(&mut self, dir: Cluster, index: usize) -> io::Result<&mut VFatDirEntry> {
+        let (sector, offset) = self.dir_entry_sector(dir, index)?;
+        let data = self.device.get_mut(sector)?;
+        let entry = unsafe { &mut data[offset..offset + size_of::<VFatDirEntry>()].cast_mut()[0] };
         Ok(entry)
     }
+
+    /// Writes all modified cached sectors back to the underlying device.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.fs_info_dirty {
+            if let Some(sector) = self.fs_info_sector {
+                let (free, next) = (self.free_clusters, self.next_free);
+                FsInfo::write(self.device.get_mut(sector)?, free, Some(next));
+            }
+            self.fs_info_dirty = false;
+        }
+        self.device.flush()
+    }
+
+    /// Returns the hit, miss and eviction counters of the sector cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    /// Returns the hit, miss, eviction and invalidation counters of the
+    /// cache of path lookups.
+    pub fn dentry_stats(&self) -> DentryStats {
+        self.dentries.stats()
+    }
+
+    /// Returns the maximum number of lookups held in the cache of path
+    /// lookups.
+    pub fn dentry_capacity(&self) -> usize {
+        self.dentries.capacity()
+    }
+
+    /// Limits the cache of path lookups to `capacity` lookups, evicting
+    /// lookups as needed. A capacity of zero disables the cache.
+    pub fn set_dentry_capacity(&mut self, capacity: usize) {
+        self.dentries.set_capacity(capacity);
+    }
+
+    /// Forgets any cached lookup of the entry at `position`, which is being
+    /// deleted.
+    pub(crate) fn invalidate_dentry(&mut self, position: EntryPosition) {
+        self.dentries.invalidate(position);
+    }
+
+    /// Forgets every cached lookup of an entry of the directory starting at
+    /// `dir`, which is being removed or moved.
+    pub(crate) fn invalidate_dentries_in(&mut self, dir: Cluster) {
+        self.dentries.invalidate_dir(dir.id());
+    }
+
+    /// Forgets every cached lookup of a path, e.g. after directories were
+    /// modified in ways not tracked individually.
+    pub(crate) fn clear_dentries(&mut self) {
+        self.dentries.clear();
+    }
+
+    /// Returns the number of clusters read ahead of sequential file reads.
+    pub fn read_ahead(&self) -> u32 {
+        self.read_ahead
+    }
+
+    /// Reads `clusters` clusters into the sector cache ahead of sequential
+    /// reads of a file that are smaller than a cluster. A value of zero
+    /// disables read-ahead; it is enabled for 8 clusters by default.
+    pub fn set_read_ahead(&mut self, clusters: u32) {
+        self.read_ahead = clusters;
+    }
+
+    /// Returns the maximum number of sectors held in the sector cache.
+    pub fn cache_capacity(&self) -> usize {
+        self.device.capacity()
+    }
+
+    /// Limits the sector cache to `capacity` sectors, writing back and
+    /// evicting sectors as needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `capacity` is zero, or the
+    /// error from writing an evicted sector back.
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        self.device.set_capacity(capacity)
+    }
+}
+
+/// The number of clusters read ahead of sequential file reads by default.
+const DEFAULT_READ_AHEAD: u32 = 8;
+
+fn invalid_input(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn cluster_out_of_range() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "cluster out of range")
+}
+
+fn chain_loops() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops")
+}
+
+/// Splits the absolute path `path` into its parent and its final component.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `path` is not absolute, has no
+/// final component (e.g. `/` or `/a/..`) or its final component is not valid
+/// UTF-8.
+fn split_path(path: &Path) -> io::Result<(&Path, &str)> {
+    if !path.is_absolute() {
+        return Err(invalid_input("path must be absolute"));
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let name = name.to_str().ok_or(invalid_input("invalid UTF-8 in path"))?;
+            Ok((parent, name))
+        }
+        _ => Err(invalid_input("path has no final component")),
+    }
+}
+
+impl Shared<VFat> {
+    /// Sets the read-only, hidden, system and archive attributes and the
+    /// timestamps of the entry at `path` to those of `metadata`. The entry's
+    /// type, start cluster and size are left unchanged.
+    ///
+    /// Open `File`s of the entry keep their own copy of its metadata and
+    /// overwrite these changes if they are written to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `path` is the root
+    /// directory, which has no directory entry.
+    pub fn set_metadata<P: AsRef<Path>>(&self, path: P, metadata: &Metadata) -> io::Result<()> {
+        let position = self.open(path)?
+            .position()
+            .ok_or(invalid_input("the root directory has no metadata"))?;
+
+        let mut fs = self.borrow_mut();
+        fs.dir_entry_mut(position.dir_cluster, position.index)?
+            .regular_mut()
+            .metadata
+            .update_from(metadata);
+        fs.flush()
+    }
+
+    /// Returns the clusters of the file or directory at `path` in chain
+    /// order. Empty files and the root directory of FAT12 and FAT16 volumes
+    /// occupy no clusters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` cannot be opened or its chain is invalid.
+    pub fn cluster_chain<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<u32>> {
+        let start = match self.open(path)? {
+            super::Entry::File(file) => file.start_cluster(),
+            super::Entry::Dir(dir) => dir.cluster(),
+        };
+
+        let mut fs = self.borrow_mut();
+        if !start.is_valid() || fs.is_fixed_root(start) {
+            return Ok(Vec::new());
+        }
+        Ok(fs.chain(start)?.iter().map(|cluster| cluster.id()).collect())
+    }
+
+    /// Restores the deleted entry `deleted`, listed by
+    /// `Dir::deleted_entries()`, as a new entry at `path`, and returns it.
+    ///
+    /// The entry's clusters are assumed to have formed a contiguous chain,
+    /// which is linked again. A restored directory gets back only its first
+    /// cluster; its own entries remain deleted and can be restored in turn.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AlreadyExists` if `path` exists, of kind
+    /// `NotFound` if the directory entry of `deleted` has been reused since
+    /// it was listed, and of kind `Other` if the entry's clusters are no
+    /// longer free.
+    pub fn undelete<P: AsRef<Path>>(&self, deleted: &DeletedEntry, path: P) -> io::Result<Entry> {
+        use traits::Metadata;
+
+        let path = path.as_ref();
+        let (parent, name) = split_path(path)?;
+        name::validate(name)?;
+        let dir = self.parent_dir(parent)?;
+        if dir.find(name).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let position = deleted.position;
+        let raw = self.borrow_mut().dir_entry(position.dir_cluster, position.index)?;
+        let unchanged = raw.unknown().is_deleted_or_unused()
+            && raw.regular().short_name()[1..] == deleted.short_name[1..]
+            && raw.regular().metadata.start_cluster() == deleted.metadata.start_cluster()
+            && raw.regular().size() as u64 == deleted.metadata.len();
+        if !unchanged {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "deleted entry was overwritten"));
+        }
+
+        let mut metadata = deleted.metadata;
+        let size = metadata.len() as u32;
+        let start = Cluster::from(metadata.start_cluster());
+        let count = deleted.clusters(self.borrow().bytes_per_cluster());
+        if count == 0 {
+            metadata.set_start_cluster(0);
+        } else {
+            self.borrow_mut().link_contiguous(start, count)?;
+        }
+
+        let new_position = match dir.insert_at(name, metadata, size, Some(position)) {
+            Ok(position) => position,
+            Err(e) => {
+                if count > 0 {
+                    self.borrow_mut().free_chain(start)?;
+                }
+                return Err(e);
+            }
+        };
+        if metadata.is_dir() {
+            let parent_cluster = if dir.is_root() { 0 } else { dir.cluster().id() };
+            self.borrow_mut()
+                .dir_entry_mut(start, 1)?
+                .regular_mut()
+                .metadata
+                .set_start_cluster(parent_cluster);
+        }
+        self.borrow_mut().flush()?;
+
+        let regular = self.borrow_mut().dir_entry(new_position.dir_cluster, new_position.index)?.regular();
+        Ok(super::Entry::from_regular(self, name.to_string(), regular, new_position))
+    }
+
+    /// Finds the entry named `name` in `dir` like `Dir::find()`, consulting
+    /// and filling the cache of lookups.
+    fn lookup(&self, dir: &Dir, name: &OsStr) -> io::Result<Entry> {
+        use traits::Entry;
+
+        let key = match name.to_str() {
+            Some(name) => name::fold(name),
+            None => return dir.find(name),
+        };
+        let dir_cluster = dir.cluster().id();
+        let cached = self.borrow_mut().dentries.get(dir_cluster, &key);
+        if let Some(dentry) = cached {
+            let raw = self.borrow_mut().dir_entry(dentry.position.dir_cluster, dentry.position.index)?;
+            let unknown = raw.unknown();
+            if !unknown.is_free() && !unknown.is_lnf() && !unknown.is_volume_label() {
+                return Ok(super::Entry::from_regular(self, dentry.name, raw.regular(), dentry.position));
+            }
+            self.borrow_mut().invalidate_dentry(dentry.position);
+        }
+
+        let entry = dir.find(name)?;
+        if let Some(position) = entry.position() {
+            let dentry = Dentry { name: entry.name().to_string(), position };
+            self.borrow_mut().dentries.insert(dir_cluster, key, dentry);
+        }
+        Ok(entry)
+    }
+
+    /// Opens the directory at `path` for use as the parent of a new entry.
+    /// Failures are reported as `InvalidInput`.
+    fn parent_dir(&self, path: &Path) -> io::Result<Dir> {
+        use traits::Entry;
+        self.open(path)
+            .map_err(|_| invalid_input("parent directory does not exist"))?
+            .into_dir()
+            .ok_or(invalid_input("parent is not a directory"))
+    }
+
+    /// Frees the clusters of `entry` and deletes its directory entry. If
+    /// `entry` is a directory, its children are removed first. Every
+    /// directory and cluster chain involved is read and validated before
+    /// anything is changed.
+    fn remove_entry(&self, entry: Entry) -> io::Result<()> {
+        let mut removals = Vec::new();
+        self.collect_removals(entry, &mut removals)?;
+
+        let mut fs = self.borrow_mut();
+        for (cluster, position, is_dir) in removals {
+            if is_dir {
+                fs.invalidate_dentries_in(cluster);
+            }
+            fs.free_chain(cluster)?;
+            Dir::remove_entry(&mut fs, position)?;
+        }
+        Ok(())
+    }
+
+    /// Appends the start cluster, position and type of `entry` to `removals`,
+    /// after those of its descendants if it is a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a directory cannot be read completely or a cluster
+    /// chain is invalid.
+    fn collect_removals(&self, entry: Entry, removals: &mut Vec<(Cluster, EntryPosition, bool)>) -> io::Result<()> {
+        use traits::{Dir, Entry};
+
+        let (cluster, position, is_dir) = match entry {
+            super::Entry::File(file) => (file.start_cluster(), file.position(), false),
+            super::Entry::Dir(dir) => {
+                let mut entries = dir.entries()?;
+                for child in entries.by_ref() {
+                    if child.name() != "." && child.name() != ".." {
+                        self.collect_removals(child, removals)?;
+                    }
+                }
+                entries.take_error()?;
+                (dir.cluster(), dir.position(), true)
+            }
+        };
+
+        let position = position.ok_or(invalid_input("cannot remove the root directory"))?;
+        if cluster.is_valid() {
+            self.borrow_mut().chain(cluster)?;
+        }
+        removals.push((cluster, position, is_dir));
+        Ok(())
+    }
 }
 
 impl<'a> FileSystem for &'a Shared<VFat> {
@@ -145,42 +1161,146 @@ impl<'a> FileSystem for &'a Shared<VFat> {
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
         use traits::Entry;
-        let mut dir = super::Entry::Dir(Dir::new_root(self));
-        for component in path.as_ref().components() {
-            match component {
-                Component::Normal(name) => {
-                    dir = dir.into_dir()
-                        .ok_or(io::Error::new(io::ErrorKind::NotFound, ""))?
-                        .find(name)?;
-                }
-                Component::ParentDir => {
-                    dir = dir.into_dir()
-                        .ok_or(io::Error::new(io::ErrorKind::NotFound, ""))?
-                        .find("..")?;
+
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(invalid_input("path must be absolute"));
+        }
+
+        let components: Vec<_> = path.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                Component::ParentDir => Some("..".as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let mut entry = super::Entry::Dir(Dir::new_root(self));
+        for (i, &name) in components.iter().enumerate() {
+            let dir = entry.into_dir()
+                .ok_or(invalid_input("path component is not a directory"))?;
+            entry = match self.lookup(&dir, name) {
+                Ok(entry) => entry,
+                // The root directory is its own parent.
+                Err(_) if dir.is_root() && name == ".." => super::Entry::Dir(dir),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && i + 1 < components.len() => {
+                    return Err(invalid_input("path component does not exist"));
                 }
-                _ => {}
-            }
+                Err(e) => return Err(e),
+            };
         }
-        Ok(dir)
+        Ok(entry)
     }
 
-    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
-        unimplemented!("read only file system")
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (parent, name) = split_path(path.as_ref())?;
+        let dir = self.parent_dir(parent)?;
+        if dir.find(name).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let now = self.borrow().now();
+        let metadata = Metadata::new(false, 0, now);
+        let position = dir.insert(name, metadata, 0)?;
+        self.borrow_mut().flush()?;
+        Ok(File::new(name.to_string(), metadata, Cluster::from(0), self.clone(), 0, Some(position)))
     }
 
-    fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
         where P: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        let (parent, name) = split_path(path.as_ref())?;
+        let dir = if parents && parent.parent().is_some() {
+            // Creates the missing ancestors first. One that already exists
+            // must be a directory.
+            match self.create_dir(parent, true) {
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => self.parent_dir(parent)?,
+                result => result?,
+            }
+        } else {
+            self.parent_dir(parent)?
+        };
+        if dir.find(name).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+        name::validate(name)?;
+
+        let timestamp = self.borrow().now();
+        let parent_cluster = if dir.is_root() { 0 } else { dir.cluster().id() };
+        let cluster = {
+            let mut fs = self.borrow_mut();
+            let cluster = fs.alloc_cluster(None)?;
+            fs.zero_cluster(cluster)?;
+            Dir::write_dot_entries(&mut fs, cluster, parent_cluster, timestamp)?;
+            cluster
+        };
+
+        let metadata = Metadata::new(true, cluster.id(), timestamp);
+        let position = match dir.insert(name, metadata, 0) {
+            Ok(position) => position,
+            Err(e) => {
+                self.borrow_mut().free_chain(cluster)?;
+                return Err(e);
+            }
+        };
+        self.borrow_mut().flush()?;
+        Ok(Dir::new(cluster, self.clone(), name.to_string(), metadata, Some(position)))
     }
 
-    fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
         where P: AsRef<Path>, Q: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        use traits::Entry;
+
+        let (from, to) = (from.as_ref(), to.as_ref());
+        split_path(from)?;
+        let (to_parent, to_name) = split_path(to)?;
+        name::validate(to_name)?;
+
+        let entry = self.open(from)?;
+        let position = entry.position().ok_or(invalid_input("cannot rename the root directory"))?;
+        match self.open(to) {
+            Ok(ref existing) if existing.position() != Some(position) => {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+            }
+            _ => {}
+        }
+
+        let dir = self.parent_dir(to_parent)?;
+        if entry.is_dir() && to.starts_with(from) {
+            return Err(invalid_input("cannot move a directory into itself"));
+        }
+
+        let (metadata, size) = match entry {
+            super::Entry::File(ref file) => (file.metadata, file.size() as u32),
+            super::Entry::Dir(ref dir) => (dir.metadata, 0),
+        };
+        let new_position = dir.insert(to_name, metadata, size)?;
+
+        let parent_cluster = if dir.is_root() { 0 } else { dir.cluster().id() };
+        let mut fs = self.borrow_mut();
+        Dir::remove_entry(&mut fs, position)?;
+        if let super::Entry::Dir(ref moved) = entry {
+            if new_position.dir_cluster != position.dir_cluster {
+                fs.dir_entry_mut(moved.cluster(), 1)?
+                    .regular_mut()
+                    .metadata
+                    .set_start_cluster(parent_cluster);
+            }
+        }
+        fs.flush()
     }
 
-    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
-        unimplemented!("read only file system")
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        use traits::Entry;
+
+        split_path(path.as_ref())?;
+        let entry = self.open(path)?;
+        if entry.is_dir() && !children {
+            return Err(io::Error::new(io::ErrorKind::Other, "entry is a directory"));
+        }
+
+        self.remove_entry(entry)?;
+        self.borrow_mut().flush()
     }
 }
@@ -0,0 +1,37 @@
+use fat32::vfat::{TimeSource, Timestamp};
+use pi::timer;
+
+/// The Unix time assumed at boot, 2019-01-01 00:00:00 UTC. The Pi has no
+/// real-time clock, so time is kept relative to this.
+const BOOT_UNIX_TIME: u64 = 1546300800;
+
+/// A clock counting up from a fixed boot time using the system timer.
+#[derive(Debug, Copy, Clone)]
+pub struct TimerClock {
+    /// The Unix time at boot, in seconds.
+    boot_time: u64,
+}
+
+impl TimerClock {
+    /// Returns a clock that reads `boot_time` seconds after the Unix epoch
+    /// when the system timer reads zero.
+    pub const fn new(boot_time: u64) -> TimerClock {
+        TimerClock { boot_time }
+    }
+}
+
+impl Default for TimerClock {
+    fn default() -> TimerClock {
+        TimerClock::new(BOOT_UNIX_TIME)
+    }
+}
+
+impl TimeSource for TimerClock {
+    fn now(&self) -> Option<Timestamp> {
+        let micros = timer::current_time();
+        Timestamp::from_unix(
+            self.boot_time + micros / 1_000_000,
+            (micros % 1_000_000) as u32 * 1000,
+        )
+    }
+}
@@ -2,9 +2,11 @@ pub use fat32::traits;
 use fat32::vfat::{Shared, VFat};
 use mutex::Mutex;
 
+use self::clock::TimerClock;
 use self::sd::Sd;
 use console::kprint;
 
+pub mod clock;
 pub mod sd;
 
 #[derive(Debug)]
@@ -27,6 +29,7 @@ impl FileSystem {
     pub fn initialize(&self) {
         kprint!("loading sd\r\n");
         let r = VFat::from(Sd::new().unwrap()).unwrap();
+        r.borrow_mut().set_time_source(TimerClock::default());
         kprint!("sd card loaded\r\n");
         *self.0.lock() = Some(r);
         kprint!("{:?}\r\n", *self.0.lock());